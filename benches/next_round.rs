@@ -0,0 +1,141 @@
+//! Times `DiamondMiner::next_round` on synthetic diamonds up to 128 interfaces wide,
+//! probed by thousands to tens of thousands of flows per TTL,
+//! next to a baseline that recomputes, from the whole reply history, the links and the replies
+//! by node that each round used to go through before the replies were indexed.
+//!
+//! Run with `cargo bench --bench next_round`.
+
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+use caracat::models::{Probe, Reply, L4};
+use voyage::algorithms::diamond_miner::DiamondMiner;
+
+const MIN_TTL: u8 = 1;
+const MAX_TTL: u8 = 8;
+// the flows needed at each TTL grow as the square of the width, so the destination is an IPv6
+// prefix: its addresses alone give more flows than the source ports available per IPv4 address
+const WIDTHS: [u32; 4] = [16, 32, 64, 128];
+
+// a per-flow load balanced network: `width` interfaces at every hop but the first and the last
+fn reply(width: u32, probe: &Probe) -> Reply {
+    let width = if probe.ttl == MIN_TTL || probe.ttl == MAX_TTL {
+        1
+    } else {
+        width
+    };
+    let flow = match probe.dst_addr {
+        IpAddr::V4(_) => unreachable!(),
+        IpAddr::V6(addr) => u128::from(addr) as u64,
+    };
+    let mut hash = (flow << 16 | probe.src_port as u64) ^ ((probe.ttl as u64) << 56);
+    // splitmix64 finalizer, so that the interfaces are spread evenly over the flows
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^= hash >> 31;
+    let interface = (hash % width as u64) as u32;
+
+    Reply {
+        reply_src_addr: IpAddr::V6(Ipv6Addr::from(
+            (0xfd00 << 112) | ((probe.ttl as u128) << 32) | interface as u128,
+        )),
+        reply_icmp_type: 3,
+        reply_protocol: 58,
+        probe_dst_addr: probe.dst_addr,
+        probe_src_port: probe.src_port,
+        probe_dst_port: probe.dst_port,
+        probe_protocol: 17,
+        probe_ttl: probe.ttl,
+        ..Default::default()
+    }
+}
+
+// what a round cost before the replies were indexed: the links from all the replies,
+// then the nodes at each TTL and their number of replies, each one a scan of all the replies
+fn baseline(miner: &DiamondMiner) -> usize {
+    let n_links = miner.links_by_ttl().values().map(|links| links.len()).sum();
+    let replies = miner.replies();
+    let mut n_replies = 0;
+    for ttl in MIN_TTL..=MAX_TTL {
+        let nodes: HashSet<IpAddr> = replies
+            .iter()
+            .filter(|r| r.probe_ttl == ttl)
+            .map(|r| r.reply_src_addr)
+            .collect();
+        for node in nodes {
+            n_replies += replies
+                .iter()
+                .filter(|r| r.reply_src_addr == node && r.probe_ttl == ttl)
+                .count();
+        }
+    }
+    assert_eq!(n_replies, replies.len());
+    n_links
+}
+
+fn bench(width: u32) {
+    let mut miner = DiamondMiner::new(
+        IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0)),
+        MIN_TTL,
+        MAX_TTL,
+        24000,
+        33434,
+        L4::UDP,
+        95.0,
+        100,
+    );
+
+    let mut replies = vec![];
+    let mut n_replies = 0;
+    let mut round_time = Duration::ZERO;
+    let mut baseline_time = Duration::ZERO;
+    for round in 1.. {
+        n_replies += replies.len();
+
+        let start = Instant::now();
+        let probes = miner.next_round(replies, false);
+        let elapsed = start.elapsed();
+        round_time += elapsed;
+
+        let start = Instant::now();
+        let n_links = baseline(&miner);
+        let recompute = start.elapsed();
+        baseline_time += recompute;
+
+        let max_flows = miner
+            .round_plan()
+            .ttls
+            .iter()
+            .map(|plan| plan.flows.end)
+            .max()
+            .unwrap_or(0);
+        println!(
+            "width {:>3} round {:>2}: {:>7} replies, {:>7} probes, {:>6} flows per TTL, {:>7} links, next_round {:>10.3?}, baseline {:>10.3?}",
+            width,
+            round,
+            n_replies,
+            probes.len(),
+            max_flows,
+            n_links,
+            elapsed,
+            recompute,
+        );
+
+        if probes.is_empty() {
+            break;
+        }
+        replies = probes.iter().map(|probe| reply(width, probe)).collect();
+    }
+
+    println!(
+        "width {:>3} total: next_round {:.3?}, baseline {:.3?}\n",
+        width, round_time, baseline_time
+    );
+}
+
+fn main() {
+    for width in WIDTHS {
+        bench(width);
+    }
+}
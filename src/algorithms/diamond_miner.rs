@@ -1,23 +1,45 @@
+mod flow_mapper;
+mod interval_mapper;
 mod probe_generator;
+mod random_mapper;
+mod reply_index;
+mod reverse_byte_mapper;
+mod round_plan;
 mod sequential_mapper;
 mod types;
 
+use anyhow::{bail, Result};
 use caracat::models::{Probe, Reply, L4};
 use itertools::Itertools;
 // use log::debug;
 // use log::{debug, warn};
+pub use flow_mapper::FlowMapper;
+pub use interval_mapper::IntervalFlowMapper;
+pub(crate) use probe_generator::split_prefix;
+pub use random_mapper::RandomFlowMapper;
+use reply_index::ReplyIndex;
+pub use reverse_byte_mapper::ReverseByteFlowMapper;
+pub use round_plan::{NodePlan, RoundPlan, TtlPlan};
 pub use sequential_mapper::*;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::net::IpAddr;
 use std::ops::Range;
 use std::vec;
 
-use crate::algorithms::utils::stopping_point;
+use crate::algorithms::utils::{non_uniform_stopping_point, stopping_point, StoppingRule};
+use crate::checkpoint::MinerState;
 use crate::links::get_links_by_ttl;
-use crate::types::{Link, Port, TTL};
+use crate::load_balancing::{classify_load_balancers, unstable_ttls};
+use crate::topology::Topology;
+use crate::types::{Link, LoadBalancing, Port, TTL};
 
 use super::utils::{estimate_total_interfaces, LIKELIHOOD_THRESHOLD};
+use super::Algorithm;
+
+// source port shift of the flows re-probed towards the same destination,
+// far from the port offsets used by the flow mappers
+const REPROBE_PORT_OFFSET: Port = 1 << 12;
 
 pub struct DiamondMiner {
     // Configuration parameters dst_addr: IpAddr,
@@ -30,12 +52,19 @@ pub struct DiamondMiner {
 
     // Internal state
     failure_probability: f64,
-    mapper_v4: SequentialFlowMapper,
-    mapper_v6: SequentialFlowMapper,
+    stopping_rule: StoppingRule,
+    mapper_v4: Box<dyn FlowMapper>,
+    mapper_v6: Box<dyn FlowMapper>,
     max_round: u32,
     current_round: u32,
     probes_sent: HashMap<TTL, usize>,
     replies_by_round: HashMap<u32, Vec<Reply>>,
+    reply_index: ReplyIndex,
+    // flows re-probed behind each load balancer, to classify it
+    reprobes: usize,
+    checked_load_balancers: HashSet<(TTL, IpAddr)>,
+    topology: Topology,
+    round_plan: RoundPlan,
 }
 
 // impl<'a> DiamondMiner<'a> {
@@ -68,16 +97,58 @@ impl DiamondMiner {
             protocol,
             max_round,
             failure_probability,
-            mapper_v4: SequentialFlowMapper::new(DEFAULT_PREFIX_SIZE_V4),
+            stopping_rule: StoppingRule::default(),
+            mapper_v4: Box::new(SequentialFlowMapper::new(DEFAULT_PREFIX_SIZE_V4)),
             // mapper_v4: SequentialFlowMapper::new(1),
-            mapper_v6: SequentialFlowMapper::new(DEFAULT_PREFIX_SIZE_V6),
+            mapper_v6: Box::new(SequentialFlowMapper::new(DEFAULT_PREFIX_SIZE_V6)),
             // mapper_v6: SequentialFlowMapper::new(1),
             current_round: 0,
             probes_sent: HashMap::new(),
             replies_by_round: HashMap::new(),
+            reply_index: ReplyIndex::new(),
+            reprobes: 0,
+            checked_load_balancers: HashSet::new(),
+            topology: Topology::new(),
+            round_plan: RoundPlan::default(),
         }
     }
 
+    /// Replaces the default sequential flow mappers, used for IPv4 and IPv6 destinations respectively.
+    pub fn with_flow_mappers(
+        mut self,
+        mapper_v4: Box<dyn FlowMapper>,
+        mapper_v6: Box<dyn FlowMapper>,
+    ) -> Self {
+        self.mapper_v4 = mapper_v4;
+        self.mapper_v6 = mapper_v6;
+        self
+    }
+
+    /// Re-probes up to `reprobes` flows behind every load balancer found, once with the same flow
+    /// and with other source ports, to tell per-packet and per-destination balancers apart from
+    /// per-flow ones. Enough source ports are used for a per-flow balancer to send some of them
+    /// elsewhere with the requested confidence, see [`classify_load_balancers`].
+    pub fn with_reprobes(mut self, reprobes: usize) -> Self {
+        self.reprobes = reprobes;
+        self
+    }
+
+    /// Replaces the default uniform stopping rule, used to decide when all the successors
+    /// of a node were found.
+    pub fn with_stopping_rule(mut self, stopping_rule: StoppingRule) -> Self {
+        self.stopping_rule = stopping_rule;
+        self
+    }
+
+    pub fn dst_addr(&self) -> IpAddr {
+        self.dst_addr
+    }
+
+    /// The graph of the interfaces found so far, built from the time exceeded replies.
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
     // pub fn links_by_ttl(&self) -> HashMap<TTL, Vec<Link>> {
     //     get_links_by_ttl(&self.time_exceeded_replies())
     // }
@@ -95,6 +166,22 @@ impl DiamondMiner {
             .collect()
     }
 
+    /// The nodes with several successors, keyed by (TTL, address), and how they balance traffic.
+    pub fn load_balancers(&self) -> HashMap<(TTL, IpAddr), LoadBalancing> {
+        classify_load_balancers(&self.replies(), self.failure_probability)
+    }
+
+    /// TTLs at which the same flow got replies from different interfaces,
+    /// i.e. behind a per-packet load balancer.
+    pub fn unstable_ttls(&self) -> BTreeSet<TTL> {
+        unstable_ttls(&self.replies())
+    }
+
+    /// How the probes returned by the last call to [`DiamondMiner::next_round`] were chosen.
+    pub fn round_plan(&self) -> &RoundPlan {
+        &self.round_plan
+    }
+
     pub fn replies(&self) -> Vec<&Reply> {
         self.replies_by_round
             .values()
@@ -109,20 +196,15 @@ impl DiamondMiner {
             .collect::<Vec<_>>()
     }
 
-    fn nodes_distribution_at_ttl(&self, nodes: &[IpAddr], ttl: u8) -> HashMap<IpAddr, f64> {
-        // TODO: check this code
-        // a routine to fetch the number of replies from a given node at a given TTL
+    pub(crate) fn nodes_distribution_at_ttl(
+        &self,
+        nodes: &[IpAddr],
+        ttl: u8,
+    ) -> HashMap<IpAddr, f64> {
         // NOTE: a node may appear at multiple TTLs
-        fn node_replies(replies: &[&Reply], node: IpAddr, ttl: u8) -> usize {
-            replies
-                .iter()
-                .filter(|r| r.reply_src_addr == node && r.probe_ttl == ttl)
-                .count()
-        }
-
         let link_dist: HashMap<IpAddr, usize> = nodes
             .iter()
-            .map(|&node| (node, node_replies(&self.replies(), node, ttl)))
+            .map(|&node| (node, self.reply_index.n_replies(ttl, node)))
             .collect();
 
         // debug!("TTL {}: Link_dist: {:?}", ttl, link_dist);
@@ -146,203 +228,319 @@ impl DiamondMiner {
         link_dist
     }
 
+    /// Length of the destination prefix covered by the flow mapper
+    fn dst_prefix_len(&self) -> u8 {
+        let (address_bits, prefix_size) = match self.dst_addr {
+            IpAddr::V4(_) => (32, self.mapper_v4.prefix_size()),
+            IpAddr::V6(_) => (128, self.mapper_v6.prefix_size()),
+        };
+        (1 + address_bits - (128 - prefix_size.leading_zeros())) as u8
+    }
+
+    /// Whether `addr` is in the destination prefix covered by the flow mapper.
+    pub(crate) fn in_dst_prefix(&self, addr: IpAddr) -> bool {
+        ip_network::IpNetwork::new_truncate(self.dst_addr, self.dst_prefix_len())
+            .unwrap()
+            .contains(addr)
+    }
+
+    /// Addresses that replied to probes at `ttl`, including the destination.
+    pub(crate) fn nodes_at_ttl(&self, ttl: TTL) -> impl Iterator<Item = IpAddr> + '_ {
+        self.reply_index.nodes_at_ttl(ttl)
+    }
+
+    // indexes a new reply, so that the next rounds do not go through the whole history
+    fn add_reply(&mut self, reply: &Reply) {
+        self.topology.add_reply(reply);
+        self.reply_index.add_reply(reply);
+    }
+
+    pub(crate) fn min_ttl(&self) -> TTL {
+        self.min_ttl
+    }
+
+    pub(crate) fn max_ttl(&self) -> TTL {
+        self.max_ttl
+    }
+
+    pub(crate) fn failure_probability(&self) -> f64 {
+        self.failure_probability
+    }
+
     pub fn unresolved_nodes_at_ttl(
         &self,
         ttl: u8,
         estimate_successors: bool,
     ) -> (HashSet<IpAddr>, usize) {
-        let nodes_at_ttl: HashSet<IpAddr> = self
-            .replies()
-            .iter()
-            .filter(|&r| r.probe_ttl == ttl)
-            .map(|r| r.reply_src_addr)
-            .collect();
+        let plan = self.ttl_plan(ttl, estimate_successors);
+        let unresolved_nodes = plan.unresolved_nodes.iter().map(|node| node.addr).collect();
+        (unresolved_nodes, plan.max_flow)
+    }
 
-        let nodes_at_ttl: Vec<IpAddr> = nodes_at_ttl.into_iter().collect();
+    /// The unresolved nodes at `ttl`, and the number of flows needed at `ttl` to resolve them all.
+    pub fn ttl_plan(&self, ttl: u8, estimate_successors: bool) -> TtlPlan {
+        let nodes_at_ttl: Vec<IpAddr> = self.nodes_at_ttl(ttl).sorted().collect();
 
         let link_dist = self.nodes_distribution_at_ttl(&nodes_at_ttl, ttl);
 
-        let mut unresolved_nodes = HashSet::new();
-        let mut weighted_thresholds = Vec::new();
+        let mut unresolved_nodes = Vec::new();
+        let mut resolved_nodes = Vec::new();
 
         for node in nodes_at_ttl {
             // if node == self.dst_addr {
             //     continue;
             // }
             // if the node is in the same subnet as the destination
-            let prefix_length = (1 + 32 - (128 - self.mapper_v4.prefix_size.leading_zeros())) as u8;
-
-            let dst_network =
-                ip_network::IpNetwork::new_truncate(self.dst_addr, prefix_length).unwrap();
-
-            if dst_network.contains(node) {
+            if self.in_dst_prefix(node) {
                 // println!("network: {:?}", dst_network);
                 // println!(
                 //     "Node {} is in the same subnet as the destination {}, with prefix length {}",
                 //     node, self.dst_addr, prefix_length
                 // );
+                resolved_nodes.push(node);
                 continue;
             }
 
             // successors are nodes at the next TTL that share a link with the current node
             // or links where we do not know the near_ip, ie. the far node is a potential successor
             // ^ this is not true, we only consider links where we know the near_ip AND the far_ip
-            let successors: HashSet<IpAddr> = self
-                .links_by_ttl()
-                .get(&ttl)
-                .unwrap_or(&vec![])
-                .iter()
-                // .filter(|l| (l.near_ip == Some(node) && l.far_ip.is_some()) || l.near_ip.is_none())
-                .filter(|l| l.near_ip == Some(node))
-                .filter_map(|l| l.far_ip)
-                .unique()
-                .collect();
+            let n_successors = self.topology.successors(ttl, node).count();
 
-            let n_successors = successors.len();
-
-            let n_k = stopping_point(n_successors, self.failure_probability);
+            let n_k = match self.stopping_rule {
+                StoppingRule::Uniform => stopping_point(n_successors, self.failure_probability),
+                StoppingRule::NonUniform => non_uniform_stopping_point(
+                    &self.topology.flows_by_successor(ttl, node),
+                    self.failure_probability,
+                ),
+            };
 
             // number of probes that went THROUGH the node
             // ie. the number of outgoing links from the node
             // (remember that links are stored with the 'near_ttl')
-            // we only keep links that have a near_ip AND a far_ip
-            let n_probes = self
-                .links_by_ttl()
-                .get(&ttl)
-                .unwrap_or(&vec![])
-                .iter()
-                .filter(|l| l.near_ip == Some(node) && l.far_ip.is_some())
-                // .filter(|l| l.near_ip == Some(node))
-                .count();
+            let n_probes = self.topology.n_flows_through(ttl, node);
 
             if n_probes >= n_k || node == self.dst_addr {
                 // node is resolved
+                resolved_nodes.push(node);
                 continue;
             }
 
-            // if n_probes < n_k && n_successors > 0 {
-            if n_probes < n_k {
-                // node is unresolved
-                unresolved_nodes.insert(node);
-                if estimate_successors {
-                    let estimate = estimate_total_interfaces(n_k, n_probes, LIKELIHOOD_THRESHOLD);
-                    let optimal_n_k = stopping_point(estimate, self.failure_probability);
-                    if link_dist[&node] >= 0.005 {
-                        weighted_thresholds
-                            .push((n_k.max(optimal_n_k) as f64 / link_dist[&node]) as usize);
-                    }
-                } else {
-                    if link_dist[&node] >= 0.005 {
-                        weighted_thresholds.push((n_k as f64 / link_dist[&node]) as usize);
-                        // weighted_thresholds.push(n_k as usize);
-                    }
-                }
-            }
+            // node is unresolved
+            let n_k = if estimate_successors {
+                let estimate = estimate_total_interfaces(n_k, n_probes, LIKELIHOOD_THRESHOLD);
+                n_k.max(stopping_point(estimate, self.failure_probability))
+            } else {
+                n_k
+            };
+            let weight = link_dist[&node];
+            let threshold = (weight >= 0.005).then(|| (n_k as f64 / weight) as usize);
+            unresolved_nodes.push(NodePlan {
+                addr: node,
+                n_successors,
+                n_probes,
+                n_k,
+                weight,
+                threshold,
+            });
         }
 
-        let max_weighted_threshold = weighted_thresholds.into_iter().max().unwrap_or(0);
-        // let max_weighted_threshold = weighted_thresholds.into_iter().sum();
+        let max_weighted_threshold = unresolved_nodes
+            .iter()
+            .filter_map(|node| node.threshold)
+            .max()
+            .unwrap_or(0);
+
+        TtlPlan {
+            unresolved_nodes,
+            resolved_nodes,
+            ..TtlPlan::new(ttl, max_weighted_threshold)
+        }
+    }
 
-        (unresolved_nodes, max_weighted_threshold)
+    /// The state to write to a checkpoint, to resume the trace later with [`DiamondMiner::restore`],
+    /// without the first `saved[round]` replies of each round, written to an earlier checkpoint.
+    pub fn checkpoint(&self, saved: &HashMap<u32, usize>) -> MinerState {
+        MinerState {
+            dst_addr: self.dst_addr,
+            min_ttl: self.min_ttl,
+            max_ttl: self.max_ttl,
+            src_port: self.src_port,
+            dst_port: self.dst_port,
+            current_round: self.current_round,
+            probes_sent: self.probes_sent.clone(),
+            replies_by_round: self
+                .replies_by_round
+                .iter()
+                .filter_map(|(&round, replies)| {
+                    let new_replies = match saved.get(&round) {
+                        Some(&n_saved) => replies.get(n_saved..).filter(|new| !new.is_empty())?,
+                        None => replies,
+                    };
+                    Some((round, new_replies.iter().map(Into::into).collect()))
+                })
+                .collect(),
+            checked_load_balancers: self.checked_load_balancers.clone(),
+        }
     }
 
+    /// Resumes a trace from a checkpoint. The miner must be new, and configured as the one
+    /// that wrote the checkpoint.
+    pub fn restore(&mut self, state: MinerState) -> Result<()> {
+        if self.current_round != 0 {
+            bail!("cannot restore a checkpoint into a miner that already started");
+        }
+        let config = (
+            self.dst_addr,
+            self.min_ttl,
+            self.max_ttl,
+            self.src_port,
+            self.dst_port,
+        );
+        let checkpoint_config = (
+            state.dst_addr,
+            state.min_ttl,
+            state.max_ttl,
+            state.src_port,
+            state.dst_port,
+        );
+        if config != checkpoint_config {
+            bail!(
+                "the checkpoint was written by another trace: (destination, min TTL, max TTL, source port, destination port) = {:?}, expected {:?}",
+                checkpoint_config,
+                config
+            );
+        }
+
+        self.current_round = state.current_round;
+        self.probes_sent = state.probes_sent;
+        self.checked_load_balancers = state.checked_load_balancers;
+        for (round, records) in state.replies_by_round {
+            let replies: Vec<Reply> = records.into_iter().map(Into::into).collect();
+            for reply in &replies {
+                self.add_reply(reply);
+            }
+            self.replies_by_round.insert(round, replies);
+        }
+        Ok(())
+    }
+
+    /// Adds replies that arrived after their round was closed, each with the round that
+    /// generated its probe. They are stored with the other replies to the probes of that round.
+    pub fn add_late_replies(&mut self, replies: Vec<(u32, Reply)>) {
+        for (round, reply) in replies {
+            self.add_reply(&reply);
+            self.replies_by_round
+                .entry(round + 1)
+                .or_default()
+                .push(reply);
+        }
+    }
+
+    /// Takes the replies to the probes of the previous round, and returns the probes of the next one.
+    /// See [`DiamondMiner::round_plan`] for why these probes were chosen.
     pub fn next_round(&mut self, replies: Vec<Reply>, estimate_successors: bool) -> Vec<Probe> {
+        self.next_round_with(replies, |miner, ttl| {
+            miner.ttl_plan(ttl, estimate_successors)
+        })
+    }
+
+    /// Same as [`DiamondMiner::next_round`], with the number of flows to send at each TTL
+    /// after the first round given by `ttl_plan` instead of the per-node stopping rule.
+    pub(crate) fn next_round_with(
+        &mut self,
+        replies: Vec<Reply>,
+        mut ttl_plan: impl FnMut(&Self, TTL) -> TtlPlan,
+    ) -> Vec<Probe> {
         self.current_round += 1;
-        self.replies_by_round.insert(self.current_round, replies);
+        for reply in &replies {
+            self.add_reply(reply);
+        }
+        self.replies_by_round
+            .entry(self.current_round)
+            .or_default()
+            .extend(replies);
 
+        self.round_plan = RoundPlan {
+            round: self.current_round,
+            ..RoundPlan::default()
+        };
         if self.current_round >= self.max_round {
             return vec![];
         }
 
-        let mut max_flows_by_ttl = HashMap::new();
+        let mut plans_by_ttl = BTreeMap::new();
 
         if self.current_round == 1 {
             let max_flow = stopping_point(1, self.failure_probability);
             for ttl in self.min_ttl..=self.max_ttl {
-                max_flows_by_ttl.insert(ttl, max_flow);
+                plans_by_ttl.insert(ttl, TtlPlan::new(ttl, max_flow));
             }
         } else {
             for ttl in self.min_ttl..=self.max_ttl {
-                let (_, max_flow) = self.unresolved_nodes_at_ttl(ttl, estimate_successors);
-                max_flows_by_ttl.insert(ttl, max_flow);
+                plans_by_ttl.insert(ttl, ttl_plan(self, ttl));
             }
         }
 
-        let flows_by_ttl: HashMap<TTL, Range<usize>> = max_flows_by_ttl
+        let flows_by_ttl: HashMap<TTL, Range<usize>> = plans_by_ttl
             .iter()
-            .map(|(&ttl, &max_flow)| {
+            .map(|(&ttl, plan)| {
                 let combined_max_flow = if ttl < self.min_ttl || ttl > self.max_ttl {
                     1
                 } else {
-                    let previous_max =
-                        *max_flows_by_ttl.get(&(ttl.saturating_sub(1))).unwrap_or(&1);
-                    previous_max.max(max_flow)
+                    let previous_max = plans_by_ttl
+                        .get(&(ttl.saturating_sub(1)))
+                        .map_or(1, |plan| plan.max_flow);
+                    previous_max.max(plan.max_flow)
                 };
                 let sent_probes = *self.probes_sent.get(&ttl).unwrap_or(&0);
                 (ttl, sent_probes..combined_max_flow)
             })
             .collect();
 
+        self.round_plan.ttls = plans_by_ttl
+            .into_values()
+            .map(|plan| {
+                // no new flows when enough were sent in previous rounds
+                let flows = &flows_by_ttl[&plan.ttl];
+                TtlPlan {
+                    flows: flows.start..flows.end.max(flows.start),
+                    ..plan
+                }
+            })
+            .collect();
+
         let mut probes = vec![];
 
-        for (ttl, flow_range) in flows_by_ttl {
+        let mapper = match self.dst_addr {
+            IpAddr::V4(_) => self.mapper_v4.as_ref(),
+            IpAddr::V6(_) => self.mapper_v6.as_ref(),
+        };
+        // the flows are spread over the destination prefix, whatever the address given in it
+        let prefix = ip_network::IpNetwork::new_truncate(self.dst_addr, self.dst_prefix_len())
+            .unwrap()
+            .network_address();
+
+        // by increasing TTL, so that the probes of a round always come in the same order
+        for (ttl, flow_range) in flows_by_ttl.into_iter().sorted_by_key(|&(ttl, _)| ttl) {
             for flow_id in flow_range {
-                match self.dst_addr {
-                    IpAddr::V4(_) => {
-                        let (ip_offset, port_offset) = self.mapper_v4.offset(flow_id as u128);
-
-                        let new_dst_addr: IpAddr = match self.dst_addr {
-                            IpAddr::V4(addr) => {
-                                let ip_offset = ip_offset as u32;
-                                let addr = u32::from(addr);
-                                let new_addr = addr + 2 * ip_offset;
-                                IpAddr::V4(new_addr.into())
-                            }
-                            IpAddr::V6(addr) => {
-                                let addr = u128::from(addr);
-                                let new_addr = addr + 2 * ip_offset;
-                                IpAddr::V6(new_addr.into())
-                            }
-                        };
-
-                        assert!(self.current_round == 1 || ip_offset > 0 || port_offset > 0);
-
-                        let probe = Probe {
-                            dst_addr: new_dst_addr,
-                            src_port: self.src_port + (port_offset as u16),
-                            dst_port: self.dst_port,
-                            protocol: self.protocol,
-                            ttl,
-                        };
-                        probes.push(probe);
-                    }
-                    IpAddr::V6(_) => {
-                        let (ip_offset, port_offset) = self.mapper_v6.offset(flow_id as u128);
-
-                        let new_dst_addr: IpAddr = match self.dst_addr {
-                            IpAddr::V4(addr) => {
-                                let ip_offset = ip_offset as u32;
-                                let addr = u32::from(addr);
-                                let new_addr = addr + ip_offset;
-                                IpAddr::V4(new_addr.into())
-                            }
-                            IpAddr::V6(addr) => {
-                                let addr = u128::from(addr);
-                                let new_addr = addr + ip_offset;
-                                IpAddr::V6(new_addr.into())
-                            }
-                        };
-
-                        let probe = Probe {
-                            dst_addr: new_dst_addr,
-                            src_port: self.src_port + (port_offset as u16),
-                            dst_port: self.dst_port,
-                            protocol: self.protocol,
-                            ttl,
-                        };
-                        probes.push(probe);
-                    }
-                }
+                let (ip_offset, port_offset) = mapper.offset(flow_id as u128);
+
+                // the offsets are below the prefix size, and only set the host bits of the prefix
+                let new_dst_addr: IpAddr = match prefix {
+                    IpAddr::V4(addr) => IpAddr::V4((u32::from(addr) | ip_offset as u32).into()),
+                    IpAddr::V6(addr) => IpAddr::V6((u128::from(addr) | ip_offset).into()),
+                };
+
+                assert!(self.current_round == 1 || ip_offset > 0 || port_offset > 0);
+
+                let probe = Probe {
+                    dst_addr: new_dst_addr,
+                    src_port: self.src_port + (port_offset as u16),
+                    dst_port: self.dst_port,
+                    protocol: self.protocol,
+                    ttl,
+                };
+                probes.push(probe);
             }
         }
 
@@ -362,8 +560,111 @@ impl DiamondMiner {
             assert_eq!(n_probes, n_unique_probes);
         }
 
+        let reprobes = self.reprobes(&probes);
+        self.round_plan.n_reprobes = reprobes.len();
+        probes.extend(reprobes);
+
         probes
     }
+
+    // probes re-sending flows behind the load balancers that were not checked yet,
+    // skipping those already in `probes`
+    fn reprobes(&mut self, probes: &[Probe]) -> Vec<Probe> {
+        if self.reprobes == 0 {
+            return vec![];
+        }
+
+        let load_balancers: Vec<(TTL, IpAddr)> = self
+            .topology
+            .divergence_points()
+            .into_iter()
+            .filter(|key| !self.checked_load_balancers.contains(key))
+            .collect();
+
+        let mut seen: HashSet<(IpAddr, Port, TTL)> = probes
+            .iter()
+            .map(|p| (p.dst_addr, p.src_port, p.ttl))
+            .collect();
+        let mut reprobes = vec![];
+        let mut push = |dst_addr, src_port, ttl| {
+            if seen.insert((dst_addr, src_port, ttl)) {
+                reprobes.push(Probe {
+                    dst_addr,
+                    src_port,
+                    dst_port: self.dst_port,
+                    protocol: self.protocol,
+                    ttl,
+                });
+            }
+        };
+
+        for &(ttl, node) in &load_balancers {
+            // flows that went through the node and got a reply right behind it
+            let flows: Vec<(IpAddr, Port)> = self
+                .reply_index
+                .flows_at_node(ttl, node)
+                .filter(|&flow| self.reply_index.answered_at(flow, ttl + 1))
+                .take(self.reprobes)
+                .collect();
+            // a per-flow balancer sends n other flows with the same successor with probability (1/k)^n
+            let n_successors = self.topology.successors(ttl, node).count().max(2) as f64;
+            let n_other_flows = (self.failure_probability.ln() / (1.0 / n_successors).ln()).ceil();
+            let other_ports = (n_other_flows as usize)
+                .div_ceil(flows.len().max(1))
+                .min((Port::MAX / REPROBE_PORT_OFFSET) as usize)
+                as Port;
+            for (dst_addr, src_port) in flows {
+                // the same flow again: a per-packet balancer may send it elsewhere
+                push(dst_addr, src_port, ttl + 1);
+                // the same destination on other flows: a per-destination balancer will not
+                for i in 1..=other_ports {
+                    let other_port = src_port.wrapping_add(REPROBE_PORT_OFFSET * i);
+                    push(dst_addr, other_port, ttl);
+                    push(dst_addr, other_port, ttl + 1);
+                }
+            }
+        }
+        self.checked_load_balancers.extend(load_balancers);
+        reprobes
+    }
+}
+
+impl Algorithm for DiamondMiner {
+    fn dst_addr(&self) -> IpAddr {
+        DiamondMiner::dst_addr(self)
+    }
+
+    fn next_round(&mut self, replies: Vec<Reply>, estimate_successors: bool) -> Vec<Probe> {
+        DiamondMiner::next_round(self, replies, estimate_successors)
+    }
+
+    fn add_late_replies(&mut self, replies: Vec<(u32, Reply)>) {
+        DiamondMiner::add_late_replies(self, replies)
+    }
+
+    fn replies(&self) -> Vec<&Reply> {
+        DiamondMiner::replies(self)
+    }
+
+    fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    fn load_balancers(&self) -> HashMap<(TTL, IpAddr), LoadBalancing> {
+        DiamondMiner::load_balancers(self)
+    }
+
+    fn round_plan(&self) -> Option<&RoundPlan> {
+        Some(DiamondMiner::round_plan(self))
+    }
+
+    fn checkpoint(&self, saved: &HashMap<u32, usize>) -> Option<MinerState> {
+        Some(DiamondMiner::checkpoint(self, saved))
+    }
+
+    fn restore(&mut self, state: MinerState) -> Result<()> {
+        DiamondMiner::restore(self, state)
+    }
 }
 
 #[cfg(test)]
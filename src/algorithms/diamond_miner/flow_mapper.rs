@@ -0,0 +1,12 @@
+use crate::algorithms::diamond_miner::types;
+
+/// Maps flow identifiers to probe header offsets: an offset on the destination address
+/// within the destination prefix, and an offset on the source port.
+pub trait FlowMapper: Send + Sync {
+    /// The number of addresses in the destination prefix.
+    fn prefix_size(&self) -> types::PrefixSize;
+
+    fn flow_id(&self, addr_offset: types::Offset, port_offset: types::Offset) -> types::FlowId;
+
+    fn offset(&self, flow_id: types::FlowId) -> (types::Offset, types::Offset);
+}
@@ -0,0 +1,47 @@
+use crate::algorithms::diamond_miner::types;
+
+use super::flow_mapper::FlowMapper;
+
+/// Maps the flows to the addresses of the destination prefix that are `step` apart,
+/// e.g. 0, 32, 64, ... for a step of 32, then to source port offsets.
+pub struct IntervalFlowMapper {
+    prefix_size: types::PrefixSize,
+    step: types::BaseType,
+}
+
+impl IntervalFlowMapper {
+    pub fn new(prefix_size: types::PrefixSize, step: types::BaseType) -> Self {
+        assert!(step > 0, "step must be positive.");
+        assert!(
+            prefix_size >= step && prefix_size.is_multiple_of(step),
+            "prefix_size must be a multiple of step."
+        );
+        Self { prefix_size, step }
+    }
+
+    // number of addresses used in the prefix
+    fn period(&self) -> types::BaseType {
+        self.prefix_size / self.step
+    }
+}
+
+impl FlowMapper for IntervalFlowMapper {
+    fn prefix_size(&self) -> types::PrefixSize {
+        self.prefix_size
+    }
+
+    fn flow_id(&self, addr_offset: types::Offset, port_offset: types::Offset) -> types::FlowId {
+        addr_offset / self.step + port_offset
+    }
+
+    fn offset(&self, flow_id: types::FlowId) -> (types::Offset, types::Offset) {
+        let period = self.period();
+        if flow_id < period {
+            return (flow_id * self.step, 0);
+        }
+        ((period - 1) * self.step, flow_id - period + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,22 @@
+use super::*;
+
+#[test]
+fn test_interval_flow_mapper() {
+    for &step in &[1, 2, 32, 256] {
+        let prefix_size = 256;
+        let mapper = IntervalFlowMapper::new(prefix_size, step);
+        for flow_id in 0..(prefix_size + 1024) {
+            let (addr_offset, port_offset) = mapper.offset(flow_id);
+            assert!(addr_offset < prefix_size);
+            assert_eq!(addr_offset % step, 0);
+            assert_eq!(mapper.flow_id(addr_offset, port_offset), flow_id);
+        }
+    }
+
+    let mapper = IntervalFlowMapper::new(256, 64);
+    let offsets: Vec<_> = (0..6).map(|flow_id| mapper.offset(flow_id)).collect();
+    assert_eq!(
+        offsets,
+        vec![(0, 0), (64, 0), (128, 0), (192, 0), (192, 1), (192, 2)]
+    );
+}
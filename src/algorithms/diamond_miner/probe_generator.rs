@@ -6,10 +6,13 @@ use std::net::Ipv6Addr;
 use caracat::models::Probe;
 use caracat::models::L4;
 
+use super::flow_mapper::FlowMapper;
 use super::sequential_mapper::SequentialFlowMapper;
 use super::types::FlowId;
 use super::DEFAULT_PREFIX_LEN_V4;
 use super::DEFAULT_PREFIX_LEN_V6;
+use super::DEFAULT_PREFIX_SIZE_V4;
+use super::DEFAULT_PREFIX_SIZE_V6;
 use super::DEFAULT_PROBE_DST_PORT;
 use super::DEFAULT_PROBE_SRC_PORT;
 
@@ -27,8 +30,8 @@ pub struct ProbeGenerator<'a> {
     prefix_len_v6: u8,
     probe_src_port: u16,
     probe_dst_port: u16,
-    mapper_v4: SequentialFlowMapper,
-    mapper_v6: SequentialFlowMapper,
+    mapper_v4: Box<dyn FlowMapper>,
+    mapper_v6: Box<dyn FlowMapper>,
     seed: u64,
 }
 
@@ -42,8 +45,8 @@ impl<'a> Default for ProbeGenerator<'a> {
             prefix_len_v6: DEFAULT_PREFIX_LEN_V6,
             probe_src_port: DEFAULT_PROBE_SRC_PORT,
             probe_dst_port: DEFAULT_PROBE_DST_PORT,
-            mapper_v4: Default::default(),
-            mapper_v6: Default::default(),
+            mapper_v4: Box::new(SequentialFlowMapper::new(DEFAULT_PREFIX_SIZE_V4)),
+            mapper_v6: Box::new(SequentialFlowMapper::new(DEFAULT_PREFIX_SIZE_V6)),
             seed: Default::default(),
         }
     }
@@ -69,8 +72,8 @@ impl<'a> IntoIterator for ProbeGenerator<'a> {
         let res = grid.iter().map(
             |&((af, subprefix, _subprefix_size, protocol), ttl, flow_id)| -> Probe {
                 let mapper = match af {
-                    4 => &self.mapper_v4,
-                    6 => &self.mapper_v6,
+                    4 => self.mapper_v4.as_ref(),
+                    6 => self.mapper_v6.as_ref(),
                     _ => panic!("Invalid IP version"),
                 };
 
@@ -92,7 +95,7 @@ impl<'a> IntoIterator for ProbeGenerator<'a> {
     }
 }
 
-fn split_prefix(
+pub(crate) fn split_prefix(
     prefix: &str,
     prefix_len_v4: u8,
     prefix_len_v6: u8,
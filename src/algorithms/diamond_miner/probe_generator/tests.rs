@@ -15,7 +15,7 @@ fn test_probe_generator_128() {
         flow_ids: vec![10, 11, 12],
         ttls: vec![41, 42],
         prefix_len_v6: 128,
-        mapper_v6: SequentialFlowMapper::new(1),
+        mapper_v6: Box::new(SequentialFlowMapper::new(1)),
         ..Default::default()
     };
     let probes = generator.into_iter().collect_vec();
@@ -40,7 +40,7 @@ fn test_probe_generator_63() {
         flow_ids: vec![10],
         ttls: vec![41],
         prefix_len_v6: 64,
-        mapper_v6: SequentialFlowMapper::new(1 << 64),
+        mapper_v6: Box::new(SequentialFlowMapper::new(1 << 64)),
         ..Default::default()
     };
 
@@ -81,7 +81,7 @@ fn test_probe_generator_32() {
         flow_ids: vec![10, 11, 12],
         ttls: vec![41, 42],
         prefix_len_v4: 32,
-        mapper_v4: SequentialFlowMapper::new(1),
+        mapper_v4: Box::new(SequentialFlowMapper::new(1)),
         ..Default::default()
     };
 
@@ -116,7 +116,7 @@ fn test_probe_generator_23() {
         flow_ids: vec![10],
         ttls: vec![41],
         prefix_len_v4: 24,
-        mapper_v4: SequentialFlowMapper::new(1 << 8),
+        mapper_v4: Box::new(SequentialFlowMapper::new(1 << 8)),
         ..Default::default()
     };
 
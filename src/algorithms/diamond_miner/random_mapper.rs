@@ -0,0 +1,101 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::algorithms::diamond_miner::types;
+
+use super::flow_mapper::FlowMapper;
+
+/// Maps the flows of the destination prefix to a seeded pseudo-random permutation of its addresses,
+/// so that consecutive flows do not target consecutive addresses.
+/// The permutation is the affine map `a * x + b` modulo the prefix size, with `a` odd,
+/// which is a bijection for power-of-two prefix sizes and can be computed without storing it.
+/// Flow 0 keeps the first address, as with the other mappers: the flow mapped to it by the affine
+/// map takes the address of flow 0 instead.
+pub struct RandomFlowMapper {
+    prefix_size: types::PrefixSize,
+    a: types::BaseType,
+    a_inverse: types::BaseType,
+    b: types::BaseType,
+}
+
+impl RandomFlowMapper {
+    pub fn new(seed: u64, prefix_size: types::PrefixSize) -> Self {
+        assert!(
+            prefix_size.is_power_of_two(),
+            "prefix_size must be a power of two."
+        );
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let a = rng.gen::<types::BaseType>() | 1;
+        let b = rng.gen::<types::BaseType>();
+
+        // multiplicative inverse of `a` modulo 2^128, by Newton's iteration
+        // (each step doubles the number of correct low bits, 3 -> 6 -> ... -> 192)
+        let mut a_inverse = a;
+        for _ in 0..6 {
+            a_inverse = a_inverse.wrapping_mul(2u128.wrapping_sub(a.wrapping_mul(a_inverse)));
+        }
+
+        Self {
+            prefix_size,
+            a,
+            a_inverse,
+            b,
+        }
+    }
+
+    // reduction modulo the prefix size, which is a power of two
+    fn modulo(&self, x: types::BaseType) -> types::BaseType {
+        x & (self.prefix_size - 1)
+    }
+
+    fn affine(&self, flow_id: types::FlowId) -> types::Offset {
+        self.modulo(self.a.wrapping_mul(flow_id).wrapping_add(self.b))
+    }
+
+    fn affine_inverse(&self, addr_offset: types::Offset) -> types::FlowId {
+        self.modulo(
+            addr_offset
+                .wrapping_sub(self.b)
+                .wrapping_mul(self.a_inverse),
+        )
+    }
+}
+
+impl FlowMapper for RandomFlowMapper {
+    fn prefix_size(&self) -> types::PrefixSize {
+        self.prefix_size
+    }
+
+    fn flow_id(&self, addr_offset: types::Offset, port_offset: types::Offset) -> types::FlowId {
+        if port_offset > 0 {
+            return self.prefix_size - 1 + port_offset;
+        }
+        if addr_offset == 0 {
+            return 0;
+        }
+        if addr_offset == self.affine(0) {
+            return self.affine_inverse(0);
+        }
+        self.affine_inverse(addr_offset)
+    }
+
+    fn offset(&self, flow_id: types::FlowId) -> (types::Offset, types::Offset) {
+        if flow_id == 0 {
+            return (0, 0);
+        }
+        if flow_id < self.prefix_size {
+            let addr_offset = self.affine(flow_id);
+            // swapped with flow 0
+            if addr_offset == 0 {
+                return (self.affine(0), 0);
+            }
+            return (addr_offset, 0);
+        }
+        // the address of the last flow is reused for the flows beyond the prefix size
+        let (last_addr_offset, _) = self.offset(self.prefix_size - 1);
+        (last_addr_offset, flow_id - self.prefix_size + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,46 @@
+use std::collections::HashSet;
+
+use super::*;
+
+#[test]
+fn test_random_flow_mapper() {
+    for &prefix_len in &[23, 24, 28, 32] {
+        let prefix_size = 1 << (32 - prefix_len);
+        let mapper = RandomFlowMapper::new(42, prefix_size);
+        for flow_id in 0..(prefix_size + 1024) {
+            let (addr_offset, port_offset) = mapper.offset(flow_id);
+            assert!(addr_offset < prefix_size);
+            assert_eq!(mapper.flow_id(addr_offset, port_offset), flow_id);
+        }
+    }
+}
+
+#[test]
+fn test_random_flow_mapper_permutation() {
+    let mapper = RandomFlowMapper::new(42, 256);
+    let offsets: HashSet<_> = (0..256).map(|flow_id| mapper.offset(flow_id).0).collect();
+    assert_eq!(offsets.len(), 256);
+    // only flow 0 gets the first address
+    assert_eq!(mapper.offset(0), (0, 0));
+    for seed in 0..64 {
+        let mapper = RandomFlowMapper::new(seed, 256);
+        assert!((1..256 + 16).all(|flow_id| mapper.offset(flow_id) != (0, 0)));
+    }
+    assert_ne!(
+        (0..8)
+            .map(|flow_id| mapper.offset(flow_id).0)
+            .collect::<Vec<_>>(),
+        (0..8).collect::<Vec<_>>()
+    );
+
+    // the same seed gives the same permutation
+    let other = RandomFlowMapper::new(42, 256);
+    assert!((0..256).all(|flow_id| mapper.offset(flow_id) == other.offset(flow_id)));
+
+    // the permutation also works on IPv6 prefixes
+    let mapper = RandomFlowMapper::new(42, 1 << 64);
+    for flow_id in 0..1024 {
+        let (addr_offset, port_offset) = mapper.offset(flow_id);
+        assert_eq!(mapper.flow_id(addr_offset, port_offset), flow_id);
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::IpAddr;
+
+use caracat::models::Reply;
+
+use crate::types::{Port, TTL};
+
+/// Replies received by a miner, indexed by TTL, by node and by flow,
+/// so that each round only has to look at its own replies.
+#[derive(Debug, Default)]
+pub(crate) struct ReplyIndex {
+    nodes_by_ttl: HashMap<TTL, HashSet<IpAddr>>,
+    replies_by_node: HashMap<(TTL, IpAddr), usize>,
+    // flows are identified by their destination address and source port,
+    // the other fields being the same for all the probes of a miner
+    flows_by_node: HashMap<(TTL, IpAddr), BTreeSet<(IpAddr, Port)>>,
+    ttls_by_flow: HashMap<(IpAddr, Port), HashSet<TTL>>,
+}
+
+impl ReplyIndex {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn add_reply(&mut self, reply: &Reply) {
+        let ttl = reply.probe_ttl;
+        let node = reply.reply_src_addr;
+        let flow = (reply.probe_dst_addr, reply.probe_src_port);
+
+        self.nodes_by_ttl.entry(ttl).or_default().insert(node);
+        *self.replies_by_node.entry((ttl, node)).or_insert(0) += 1;
+        self.flows_by_node
+            .entry((ttl, node))
+            .or_default()
+            .insert(flow);
+        self.ttls_by_flow.entry(flow).or_default().insert(ttl);
+    }
+
+    /// Addresses that replied to probes at `ttl`.
+    pub(crate) fn nodes_at_ttl(&self, ttl: TTL) -> impl Iterator<Item = IpAddr> + '_ {
+        self.nodes_by_ttl.get(&ttl).into_iter().flatten().copied()
+    }
+
+    /// Number of replies sent by `node` to probes at `ttl`.
+    pub(crate) fn n_replies(&self, ttl: TTL, node: IpAddr) -> usize {
+        self.replies_by_node.get(&(ttl, node)).copied().unwrap_or(0)
+    }
+
+    /// Flows that got a reply from `node` at `ttl`, in increasing order.
+    pub(crate) fn flows_at_node(
+        &self,
+        ttl: TTL,
+        node: IpAddr,
+    ) -> impl Iterator<Item = (IpAddr, Port)> + '_ {
+        self.flows_by_node
+            .get(&(ttl, node))
+            .into_iter()
+            .flatten()
+            .copied()
+    }
+
+    /// Whether the flow got a reply at `ttl`.
+    pub(crate) fn answered_at(&self, flow: (IpAddr, Port), ttl: TTL) -> bool {
+        self.ttls_by_flow
+            .get(&flow)
+            .is_some_and(|ttls| ttls.contains(&ttl))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,37 @@
+use super::*;
+
+use crate::helpers::reply;
+
+#[test]
+fn test_reply_index() {
+    let mut index = ReplyIndex::new();
+    let replies = vec![
+        reply(1, "10.0.0.1", "192.168.0.2"),
+        reply(2, "10.0.0.2", "192.168.0.2"),
+        reply(1, "10.0.0.1", "192.168.0.4"),
+        reply(2, "10.0.0.3", "192.168.0.4"),
+        // a retransmission
+        reply(2, "10.0.0.3", "192.168.0.4"),
+    ];
+    for reply in &replies {
+        index.add_reply(reply);
+    }
+
+    let node = |addr: &str| addr.parse::<IpAddr>().unwrap();
+    let flow = |addr: &str| (node(addr), 0);
+
+    let nodes: HashSet<IpAddr> = index.nodes_at_ttl(2).collect();
+    assert_eq!(nodes, HashSet::from([node("10.0.0.2"), node("10.0.0.3")]));
+    assert_eq!(index.nodes_at_ttl(3).count(), 0);
+
+    assert_eq!(index.n_replies(1, node("10.0.0.1")), 2);
+    assert_eq!(index.n_replies(2, node("10.0.0.3")), 2);
+    assert_eq!(index.n_replies(2, node("10.0.0.1")), 0);
+
+    let flows: Vec<_> = index.flows_at_node(1, node("10.0.0.1")).collect();
+    assert_eq!(flows, vec![flow("192.168.0.2"), flow("192.168.0.4")]);
+
+    assert!(index.answered_at(flow("192.168.0.2"), 2));
+    assert!(!index.answered_at(flow("192.168.0.2"), 3));
+    assert!(!index.answered_at(flow("192.168.0.6"), 1));
+}
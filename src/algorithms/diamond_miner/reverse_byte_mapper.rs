@@ -0,0 +1,53 @@
+use crate::algorithms::diamond_miner::types;
+
+use super::flow_mapper::FlowMapper;
+
+/// Maps the flows to the addresses of the destination prefix in bit-reversed order
+/// (for a /24: 0, 128, 64, 192, 32, ...), so that the first flows already differ
+/// in the most significant bits of the address offset.
+pub struct ReverseByteFlowMapper {
+    prefix_size: types::PrefixSize,
+    bits: u32,
+}
+
+impl ReverseByteFlowMapper {
+    pub fn new(prefix_size: types::PrefixSize) -> Self {
+        assert!(
+            prefix_size.is_power_of_two(),
+            "prefix_size must be a power of two."
+        );
+        Self {
+            prefix_size,
+            bits: prefix_size.trailing_zeros(),
+        }
+    }
+
+    // reverses the `bits` lowest bits of `offset`
+    fn reverse(&self, offset: types::Offset) -> types::Offset {
+        if self.bits == 0 {
+            return 0;
+        }
+        offset.reverse_bits() >> (types::BaseType::BITS - self.bits)
+    }
+}
+
+impl FlowMapper for ReverseByteFlowMapper {
+    fn prefix_size(&self) -> types::PrefixSize {
+        self.prefix_size
+    }
+
+    fn flow_id(&self, addr_offset: types::Offset, port_offset: types::Offset) -> types::FlowId {
+        self.reverse(addr_offset) + port_offset
+    }
+
+    fn offset(&self, flow_id: types::FlowId) -> (types::Offset, types::Offset) {
+        if flow_id < self.prefix_size {
+            return (self.reverse(flow_id), 0);
+        }
+        // the last flow maps to the last address, which is its own reverse
+        (self.prefix_size - 1, flow_id - self.prefix_size + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,18 @@
+use super::*;
+
+#[test]
+fn test_reverse_byte_flow_mapper() {
+    for &prefix_len in &[23, 24, 28, 32] {
+        let prefix_size = 1 << (32 - prefix_len);
+        let mapper = ReverseByteFlowMapper::new(prefix_size);
+        for flow_id in 0..(prefix_size + 1024) {
+            let (addr_offset, port_offset) = mapper.offset(flow_id);
+            assert!(addr_offset < prefix_size);
+            assert_eq!(mapper.flow_id(addr_offset, port_offset), flow_id);
+        }
+    }
+
+    let mapper = ReverseByteFlowMapper::new(256);
+    let offsets: Vec<_> = (0..5).map(|flow_id| mapper.offset(flow_id).0).collect();
+    assert_eq!(offsets, vec![0, 128, 64, 192, 32]);
+}
@@ -0,0 +1,64 @@
+use std::net::IpAddr;
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::types::TTL;
+
+/// Why a node needs more probes: the stopping point it has not reached yet.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NodePlan {
+    pub addr: IpAddr,
+    /// Successors found so far.
+    pub n_successors: usize,
+    /// Flows that went through the node and got a reply behind it.
+    pub n_probes: usize,
+    /// Flows needed through the node to find all its successors with the requested confidence.
+    pub n_k: usize,
+    /// Share of the flows at this TTL that reach the node.
+    pub weight: f64,
+    /// Flows to send at this TTL for `n_k` of them to reach the node, or `None` if its weight
+    /// is too small to be taken into account.
+    pub threshold: Option<usize>,
+}
+
+/// The probes sent at a TTL in a round, and the nodes that called for them.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TtlPlan {
+    pub ttl: TTL,
+    pub unresolved_nodes: Vec<NodePlan>,
+    /// Nodes found at this TTL that need no more probes, empty for plans that do not depend on the nodes found.
+    pub resolved_nodes: Vec<IpAddr>,
+    /// Flows needed at this TTL according to the stopping rule.
+    pub max_flow: usize,
+    /// Flows sent in the round, after the ones already sent and at least as many as at the previous TTL.
+    pub flows: Range<usize>,
+}
+
+impl TtlPlan {
+    /// A plan sending `max_flow` flows at `ttl`, that does not depend on the nodes found.
+    pub fn new(ttl: TTL, max_flow: usize) -> Self {
+        Self {
+            ttl,
+            unresolved_nodes: vec![],
+            resolved_nodes: vec![],
+            max_flow,
+            flows: 0..0,
+        }
+    }
+}
+
+/// How the probes of a round were chosen, by increasing TTL.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RoundPlan {
+    pub round: u32,
+    pub ttls: Vec<TtlPlan>,
+    /// Flows re-sent to classify the load balancers, on top of the flows of each TTL.
+    pub n_reprobes: usize,
+}
+
+impl RoundPlan {
+    pub fn n_probes(&self) -> usize {
+        self.ttls.iter().map(|plan| plan.flows.len()).sum::<usize>() + self.n_reprobes
+    }
+}
@@ -132,6 +132,44 @@ fn test_unresolved_nodes_at_ttl_basic() {
     );
 }
 
+#[test]
+fn test_round_plan() {
+    let mut miner = diamond_miner();
+    // ---- [ IP[1] ] ---- [ IP[2] ] -.-.-.- [ DEST[0] ]
+    let probes = miner.next_round(vec![], false);
+    let plan = miner.round_plan();
+    assert_eq!(plan.round, 1);
+    assert_eq!(plan.ttls.len(), 20);
+    assert!(plan.ttls.iter().all(|ttl| ttl.unresolved_nodes.is_empty()));
+    assert_eq!(plan.ttls[0].flows, 0..6);
+    assert_eq!(plan.n_probes(), probes.len());
+
+    let replies = vec![reply(1, IP[1], DEST[0]), reply(2, IP[2], DEST[0])];
+    let probes = miner.next_round(replies, false);
+    let plan = miner.round_plan();
+    assert_eq!(plan.round, 2);
+    assert_eq!(
+        plan.ttls[0].unresolved_nodes,
+        vec![NodePlan {
+            addr: IpAddr::V4(IP[1].parse().unwrap()),
+            n_successors: 1,
+            n_probes: 1,
+            n_k: 6,
+            weight: 1.0,
+            threshold: Some(6),
+        }]
+    );
+    assert_eq!(plan.ttls[0].max_flow, 6);
+    // the 6 flows were already sent in the first round
+    assert!(plan.ttls[0].flows.is_empty());
+    assert_eq!(plan.n_probes(), probes.len());
+
+    // the plan can be written as JSON
+    let json = serde_json::to_value(plan).unwrap();
+    assert_eq!(json["ttls"][0]["unresolved_nodes"][0]["n_k"], 6);
+    assert_eq!(json["ttls"][0]["flows"]["start"], 6);
+}
+
 #[test]
 fn test_unresolved_nodes_at_ttl_complex() {
     let mut miner = diamond_miner();
@@ -320,3 +358,75 @@ fn test_next_round() {
         HashMap::from([(2, 11 - 6), (3, 18 - 6), (4, 18 - 6)])
     );
 }
+
+#[test]
+fn test_next_round_v6() {
+    let mut miner = DiamondMiner::new(
+        IpAddr::V6("2001:db8::".parse().unwrap()),
+        1,
+        2,
+        24000,
+        33434,
+        L4::ICMP,
+        95.0,
+        10,
+    );
+    assert_eq!(miner.protocol, L4::ICMPv6);
+    assert_eq!(miner.dst_prefix_len(), 64);
+
+    let probes = miner.next_round(vec![], false);
+
+    assert_eq!(probes.len(), 6 * 2);
+    // flows are consecutive addresses of the /64, on a single source port
+    let addrs: HashSet<IpAddr> = probes.iter().map(|p| p.dst_addr).collect();
+    let expected: HashSet<IpAddr> = (0..6)
+        .map(|i| IpAddr::V6(((0x2001_0db8_u128 << 96) | i).into()))
+        .collect();
+    assert_eq!(addrs, expected);
+    assert!(probes.iter().all(|p| p.src_port == 24000));
+    assert!(probes.iter().all(|p| p.protocol == L4::ICMPv6));
+}
+
+#[test]
+fn test_next_round_reverse_byte_mapper() {
+    let dst_addr = IpAddr::V4("192.0.2.0".parse().unwrap());
+    let mut miner = DiamondMiner::new(dst_addr, 1, 1, 24000, 33434, L4::ICMP, 95.0, 10)
+        .with_flow_mappers(
+            Box::new(ReverseByteFlowMapper::new(DEFAULT_PREFIX_SIZE_V4)),
+            Box::new(SequentialFlowMapper::new(DEFAULT_PREFIX_SIZE_V6)),
+        );
+
+    let probes = miner.next_round(vec![], false);
+
+    // the first flows are spread over the whole prefix
+    let offsets: Vec<u32> = probes
+        .iter()
+        .map(|p| match p.dst_addr {
+            IpAddr::V4(addr) => u32::from(addr) - u32::from_be_bytes([192, 0, 2, 0]),
+            IpAddr::V6(_) => unreachable!(),
+        })
+        .sorted()
+        .collect();
+    assert_eq!(offsets, vec![0, 32, 64, 128, 160, 192]);
+}
+
+#[test]
+fn test_dst_prefix_len_v4() {
+    let miner = diamond_miner();
+    assert_eq!(miner.dst_prefix_len(), 24);
+}
+
+#[test]
+fn test_add_late_replies() {
+    let mut miner = diamond_miner();
+    miner.next_round(vec![], false);
+    miner.next_round(vec![reply(1, IP[1], DEST[1])], false);
+
+    // a reply to a probe of the first round arrives during the second round
+    miner.add_late_replies(vec![(1, reply(1, IP[1], DEST[2]))]);
+    miner.next_round(vec![], false);
+
+    assert_eq!(miner.replies_by_round[&2].len(), 2);
+    assert!(miner.replies_by_round[&3].is_empty());
+    assert_eq!(miner.replies().len(), 2);
+}
@@ -0,0 +1,198 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::IpAddr;
+
+use anyhow::Result;
+use caracat::models::{Probe, Reply, L4};
+
+use crate::checkpoint::MinerState;
+use crate::topology::Topology;
+use crate::types::{LoadBalancing, Port, TTL};
+
+use super::diamond_miner::{DiamondMiner, FlowMapper, RoundPlan, TtlPlan};
+use super::utils::{stopping_point, StoppingRule};
+use super::Algorithm;
+
+/// Number of flows the meshing test sends through every vertex of a hop (phi in the paper).
+pub const DEFAULT_MESHING_TEST_FLOWS: usize = 2;
+
+/// MDA-Lite, from "Multilevel MDA-Lite Paris Traceroute" (Vermeulen et al., IMC 2018).
+///
+/// Assumes that diamonds are unmeshed and that load balancing is uniform, and only applies
+/// the stopping rule per hop instead of per node. A meshing test checks the assumption
+/// on every pair of wide hops, and the diamonds found to be meshed fall back to the
+/// per-node rule of [`DiamondMiner`].
+pub struct MdaLite {
+    miner: DiamondMiner,
+    meshing_test_flows: usize,
+    // near TTLs of the hop pairs that belong to a meshed diamond
+    meshed_ttls: BTreeSet<TTL>,
+}
+
+#[allow(clippy::too_many_arguments)]
+impl MdaLite {
+    pub fn new(
+        dst_addr: IpAddr,
+        min_ttl: TTL,
+        max_ttl: TTL,
+        src_port: Port,
+        dst_port: Port,
+        protocol: L4,
+        confidence: f64,
+        max_round: u32,
+    ) -> Self {
+        Self {
+            miner: DiamondMiner::new(
+                dst_addr, min_ttl, max_ttl, src_port, dst_port, protocol, confidence, max_round,
+            ),
+            meshing_test_flows: DEFAULT_MESHING_TEST_FLOWS,
+            meshed_ttls: BTreeSet::new(),
+        }
+    }
+
+    pub fn with_flow_mappers(
+        mut self,
+        mapper_v4: Box<dyn FlowMapper>,
+        mapper_v6: Box<dyn FlowMapper>,
+    ) -> Self {
+        self.miner = self.miner.with_flow_mappers(mapper_v4, mapper_v6);
+        self
+    }
+
+    pub fn with_reprobes(mut self, reprobes: usize) -> Self {
+        self.miner = self.miner.with_reprobes(reprobes);
+        self
+    }
+
+    /// Stopping rule of the meshed diamonds, explored node by node as with Diamond Miner.
+    pub fn with_stopping_rule(mut self, stopping_rule: StoppingRule) -> Self {
+        self.miner = self.miner.with_stopping_rule(stopping_rule);
+        self
+    }
+
+    pub fn with_meshing_test_flows(mut self, meshing_test_flows: usize) -> Self {
+        self.meshing_test_flows = meshing_test_flows;
+        self
+    }
+
+    /// The underlying Diamond Miner instance, which holds the replies.
+    pub fn miner(&self) -> &DiamondMiner {
+        &self.miner
+    }
+
+    /// Near TTLs of the hop pairs that belong to a diamond found to be meshed.
+    pub fn meshed_ttls(&self) -> &BTreeSet<TTL> {
+        &self.meshed_ttls
+    }
+}
+
+// interfaces that answered at `ttl`, outside of the destination prefix
+fn vertices(miner: &DiamondMiner, ttl: TTL) -> HashSet<IpAddr> {
+    miner
+        .nodes_at_ttl(ttl)
+        .filter(|&addr| !miner.in_dst_prefix(addr))
+        .collect()
+}
+
+// whether some vertex at `ttl` has several successors while some vertex at `ttl + 1`
+// has several predecessors, which cannot happen in an unmeshed diamond
+fn is_meshed(miner: &DiamondMiner, ttl: TTL) -> bool {
+    let topology = miner.topology();
+    let nodes: Vec<IpAddr> = topology.nodes_at_ttl(ttl).collect();
+    let far_nodes: Vec<IpAddr> = topology.nodes_at_ttl(ttl + 1).collect();
+    nodes
+        .iter()
+        .any(|&node| topology.successors(ttl, node).count() > 1)
+        && far_nodes
+            .iter()
+            .any(|&node| topology.predecessors(ttl + 1, node).count() > 1)
+}
+
+// near TTLs of the hop pairs of the diamond around the hop pair at `ttl`,
+// i.e. up to the closest hops with a single vertex on either side
+fn diamond_ttls(miner: &DiamondMiner, ttl: TTL, min_ttl: TTL, max_ttl: TTL) -> Vec<TTL> {
+    let mut start = ttl;
+    while start > min_ttl && vertices(miner, start).len() > 1 {
+        start -= 1;
+    }
+    let mut end = ttl + 1;
+    while end < max_ttl && vertices(miner, end).len() > 1 {
+        end += 1;
+    }
+    (start..end).collect()
+}
+
+impl Algorithm for MdaLite {
+    fn dst_addr(&self) -> IpAddr {
+        self.miner.dst_addr()
+    }
+
+    fn next_round(&mut self, replies: Vec<Reply>, estimate_successors: bool) -> Vec<Probe> {
+        let meshed_ttls = &mut self.meshed_ttls;
+        let meshing_test_flows = self.meshing_test_flows;
+        let (min_ttl, max_ttl) = (self.miner.min_ttl(), self.miner.max_ttl());
+        self.miner.next_round_with(replies, |miner, ttl| {
+            if meshed_ttls.contains(&ttl) {
+                return miner.ttl_plan(ttl, estimate_successors);
+            }
+
+            let nodes: Vec<IpAddr> = vertices(miner, ttl).into_iter().collect();
+            if nodes.is_empty() {
+                return TtlPlan::new(ttl, 0);
+            }
+
+            // enough flows to find every vertex of the next hop, assuming uniform load balancing
+            let n_next = vertices(miner, ttl + 1).len();
+            let max_flow = stopping_point(n_next.max(1), miner.failure_probability());
+            if nodes.len() < 2 || n_next < 2 {
+                return TtlPlan::new(ttl, max_flow);
+            }
+
+            if is_meshed(miner, ttl) {
+                meshed_ttls.extend(diamond_ttls(miner, ttl, min_ttl, max_ttl));
+                return miner.ttl_plan(ttl, estimate_successors);
+            }
+
+            // meshing test: a few flows through every vertex, each traced to the next hop
+            let link_dist = miner.nodes_distribution_at_ttl(&nodes, ttl);
+            let meshing_test_max_flow = nodes
+                .iter()
+                .filter(|node| link_dist[node] >= 0.005)
+                .map(|node| (meshing_test_flows as f64 / link_dist[node]).ceil() as usize)
+                .max()
+                .unwrap_or(0);
+            TtlPlan::new(ttl, max_flow.max(meshing_test_max_flow))
+        })
+    }
+
+    fn add_late_replies(&mut self, replies: Vec<(u32, Reply)>) {
+        self.miner.add_late_replies(replies)
+    }
+
+    fn replies(&self) -> Vec<&Reply> {
+        self.miner.replies()
+    }
+
+    fn topology(&self) -> &Topology {
+        self.miner.topology()
+    }
+
+    fn load_balancers(&self) -> HashMap<(TTL, IpAddr), LoadBalancing> {
+        self.miner.load_balancers()
+    }
+
+    fn round_plan(&self) -> Option<&RoundPlan> {
+        Some(self.miner.round_plan())
+    }
+
+    // the meshed diamonds are not saved, they are detected again from the restored replies
+    fn checkpoint(&self, saved: &HashMap<u32, usize>) -> Option<MinerState> {
+        Some(self.miner.checkpoint(saved))
+    }
+
+    fn restore(&mut self, state: MinerState) -> Result<()> {
+        self.miner.restore(state)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+
+use crate::helpers::{addr, network, DEST, SRC};
+use crate::probe::Prober;
+use crate::simulator::{Network, SimulatedProber};
+
+use super::*;
+
+const IP: [&str; 7] = [
+    "192.168.0.2",
+    "192.168.0.3",
+    "192.168.0.4",
+    "192.168.0.5",
+    "192.168.0.6",
+    "192.168.0.7",
+    "192.168.0.8",
+];
+
+// runs `alg` over `network` and returns the number of probes sent
+fn run(network: Network, alg: &mut impl Algorithm) -> usize {
+    let mut prober = SimulatedProber::new(network);
+    let mut n_probes = 0;
+    let mut probes = alg.next_round(vec![], false);
+    while !probes.is_empty() {
+        n_probes += probes.len();
+        let replies = prober.probe(probes).unwrap();
+        probes = alg.next_round(replies, false);
+    }
+    n_probes
+}
+
+fn discovered_links(alg: &impl Algorithm) -> HashSet<(TTL, IpAddr, IpAddr)> {
+    alg.topology()
+        .links()
+        .map(|(ttl, near, far, _)| (ttl, near, far))
+        .collect()
+}
+
+fn mda_lite() -> MdaLite {
+    MdaLite::new(addr(DEST), 1, 10, 24000, 33434, L4::ICMP, 99.0, 10)
+}
+
+fn diamond_miner() -> DiamondMiner {
+    DiamondMiner::new(addr(DEST), 1, 10, 24000, 33434, L4::ICMP, 99.0, 10)
+}
+
+#[test]
+fn test_unmeshed_diamond() {
+    //            +- [ IP[1] ] -- [ IP[4] ] -+
+    // [ IP[0] ] -+- [ IP[2] ] -- [ IP[5] ] -+- [ IP[6] ]
+    //            +- [ IP[3] ] -- [ IP[4] ] -+
+    let links = [
+        (SRC, IP[0]),
+        (IP[0], IP[1]),
+        (IP[0], IP[2]),
+        (IP[0], IP[3]),
+        (IP[1], IP[4]),
+        (IP[2], IP[5]),
+        (IP[3], IP[4]),
+        (IP[4], IP[6]),
+        (IP[5], IP[6]),
+    ];
+
+    let mut miner = diamond_miner();
+    let miner_probes = run(network(&links), &mut miner);
+
+    let mut alg = mda_lite();
+    let alg_probes = run(network(&links), &mut alg);
+
+    assert!(alg.meshed_ttls().is_empty());
+    assert_eq!(discovered_links(&alg), discovered_links(&miner));
+    assert!(alg_probes < miner_probes, "{} {}", alg_probes, miner_probes);
+}
+
+#[test]
+fn test_meshed_diamond() {
+    //            +- [ IP[1] ] -+- [ IP[3] ] -+
+    // [ IP[0] ] -+             X             +- [ IP[6] ]
+    //            +- [ IP[2] ] -+- [ IP[4] ] -+
+    //                          +- [ IP[5] ] -+
+    let network = network(&[
+        (SRC, IP[0]),
+        (IP[0], IP[1]),
+        (IP[0], IP[2]),
+        (IP[1], IP[3]),
+        (IP[1], IP[4]),
+        (IP[2], IP[3]),
+        (IP[2], IP[4]),
+        (IP[2], IP[5]),
+        (IP[3], IP[6]),
+        (IP[4], IP[6]),
+        (IP[5], IP[6]),
+    ]);
+    let expected: HashSet<(TTL, IpAddr, IpAddr)> = network
+        .paths()
+        .iter()
+        .flat_map(|path| {
+            path.windows(2)
+                .enumerate()
+                .map(|(i, pair)| ((i + 1) as TTL, pair[0], pair[1]))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let mut alg = mda_lite();
+    run(network, &mut alg);
+
+    // the whole diamond, from IP[0] to IP[6], falls back to the per-node rule
+    assert_eq!(alg.meshed_ttls(), &BTreeSet::from([1, 2, 3]));
+    assert_eq!(discovered_links(&alg), expected);
+}
@@ -1,2 +1,106 @@
 pub mod diamond_miner;
+pub mod mda_lite;
+pub mod paris_traceroute;
 pub mod utils;
+
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+
+use anyhow::{bail, Result};
+use caracat::models::{Probe, Reply};
+
+use crate::algorithms::diamond_miner::RoundPlan;
+use crate::checkpoint::MinerState;
+use crate::load_balancing::{classify_load_balancers, unstable_ttls, DEFAULT_FAILURE_PROBABILITY};
+use crate::topology::Topology;
+use crate::types::{LoadBalancing, TTL};
+
+/// A traceroute algorithm towards a single destination, driven one round of probes at a time.
+pub trait Algorithm {
+    fn dst_addr(&self) -> IpAddr;
+
+    /// Takes the replies to the probes of the previous round, and returns the probes of the next one.
+    /// An empty round means that the algorithm is done.
+    fn next_round(&mut self, replies: Vec<Reply>, estimate_successors: bool) -> Vec<Probe>;
+
+    /// Adds replies that arrived after their round was closed, each with the round that generated its probe.
+    fn add_late_replies(&mut self, replies: Vec<(u32, Reply)>);
+
+    fn replies(&self) -> Vec<&Reply>;
+
+    fn time_exceeded_replies(&self) -> Vec<&Reply> {
+        self.replies()
+            .into_iter()
+            .filter(|&r| r.is_time_exceeded())
+            .collect()
+    }
+
+    /// The graph of the interfaces found so far.
+    fn topology(&self) -> &Topology;
+
+    fn load_balancers(&self) -> HashMap<(TTL, IpAddr), LoadBalancing> {
+        classify_load_balancers(&self.replies(), DEFAULT_FAILURE_PROBABILITY)
+    }
+
+    fn unstable_ttls(&self) -> BTreeSet<TTL> {
+        unstable_ttls(&self.replies())
+    }
+
+    /// How the probes of the last round were chosen, if the algorithm can explain it.
+    fn round_plan(&self) -> Option<&RoundPlan> {
+        None
+    }
+
+    /// The state to write to a checkpoint, if the algorithm can be resumed, without the first
+    /// `saved[round]` replies of each round, written to an earlier checkpoint.
+    fn checkpoint(&self, _saved: &HashMap<u32, usize>) -> Option<MinerState> {
+        None
+    }
+
+    /// Resumes from a checkpoint written by [`Algorithm::checkpoint`].
+    fn restore(&mut self, _state: MinerState) -> Result<()> {
+        bail!("this algorithm cannot be resumed from a checkpoint")
+    }
+}
+
+impl<A: Algorithm + ?Sized> Algorithm for Box<A> {
+    fn dst_addr(&self) -> IpAddr {
+        (**self).dst_addr()
+    }
+
+    fn next_round(&mut self, replies: Vec<Reply>, estimate_successors: bool) -> Vec<Probe> {
+        (**self).next_round(replies, estimate_successors)
+    }
+
+    fn add_late_replies(&mut self, replies: Vec<(u32, Reply)>) {
+        (**self).add_late_replies(replies)
+    }
+
+    fn replies(&self) -> Vec<&Reply> {
+        (**self).replies()
+    }
+
+    fn topology(&self) -> &Topology {
+        (**self).topology()
+    }
+
+    fn load_balancers(&self) -> HashMap<(TTL, IpAddr), LoadBalancing> {
+        (**self).load_balancers()
+    }
+
+    fn unstable_ttls(&self) -> BTreeSet<TTL> {
+        (**self).unstable_ttls()
+    }
+
+    fn round_plan(&self) -> Option<&RoundPlan> {
+        (**self).round_plan()
+    }
+
+    fn checkpoint(&self, saved: &HashMap<u32, usize>) -> Option<MinerState> {
+        (**self).checkpoint(saved)
+    }
+
+    fn restore(&mut self, state: MinerState) -> Result<()> {
+        (**self).restore(state)
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use caracat::models::{Probe, Reply, L4};
+
+use crate::topology::Topology;
+use crate::types::{Port, TTL};
+
+use super::Algorithm;
+
+pub const DEFAULT_PROBES_PER_HOP: usize = 3;
+pub const DEFAULT_MAX_SILENT_HOPS: TTL = 5;
+
+/// Single path Paris traceroute: every probe uses the same flow, so that per-flow load balancers
+/// keep it on a single path. Sends a fixed number of probes per TTL, a window of TTLs per round,
+/// and stops at the destination or after a number of consecutive silent hops.
+pub struct ParisTraceroute {
+    dst_addr: IpAddr,
+    min_ttl: TTL,
+    max_ttl: TTL,
+    src_port: Port,
+    dst_port: Port,
+    protocol: L4,
+    probes_per_hop: usize,
+    max_silent_hops: TTL,
+
+    // Internal state
+    current_round: u32,
+    // first TTL that was not probed yet
+    next_ttl: u16,
+    replies_by_round: HashMap<u32, Vec<Reply>>,
+    topology: Topology,
+}
+
+impl ParisTraceroute {
+    pub fn new(
+        dst_addr: IpAddr,
+        min_ttl: TTL,
+        max_ttl: TTL,
+        src_port: Port,
+        dst_port: Port,
+        protocol: L4,
+    ) -> Self {
+        let protocol = match (protocol, dst_addr) {
+            (L4::ICMP, IpAddr::V6(_)) => L4::ICMPv6,
+            (proto, _) => proto,
+        };
+
+        Self {
+            dst_addr,
+            min_ttl,
+            max_ttl,
+            src_port,
+            dst_port,
+            protocol,
+            probes_per_hop: DEFAULT_PROBES_PER_HOP,
+            max_silent_hops: DEFAULT_MAX_SILENT_HOPS,
+            current_round: 0,
+            next_ttl: min_ttl as u16,
+            replies_by_round: HashMap::new(),
+            topology: Topology::new(),
+        }
+    }
+
+    pub fn with_probes_per_hop(mut self, probes_per_hop: usize) -> Self {
+        self.probes_per_hop = probes_per_hop;
+        self
+    }
+
+    /// Stops after `max_silent_hops` consecutive TTLs without any reply.
+    /// This is also the number of TTLs probed per round, less the silent TTLs at the end of the previous round.
+    pub fn with_max_silent_hops(mut self, max_silent_hops: TTL) -> Self {
+        self.max_silent_hops = max_silent_hops.max(1);
+        self
+    }
+
+    /// Whether a reply came from the destination itself, rather than from a router on the way.
+    pub fn destination_reached(&self) -> bool {
+        self.replies_by_round
+            .values()
+            .flatten()
+            .any(|r| !r.is_time_exceeded() || r.reply_src_addr == self.dst_addr)
+    }
+
+    /// Number of consecutive TTLs without any reply at the end of the probed range.
+    pub fn silent_hops(&self) -> TTL {
+        let answered: Vec<TTL> = self
+            .replies_by_round
+            .values()
+            .flatten()
+            .map(|r| r.probe_ttl)
+            .collect();
+        (self.min_ttl as u16..self.next_ttl)
+            .rev()
+            .take_while(|&ttl| !answered.contains(&(ttl as TTL)))
+            .count() as TTL
+    }
+
+    fn is_done(&self) -> bool {
+        self.next_ttl > self.max_ttl as u16
+            || self.destination_reached()
+            || self.silent_hops() >= self.max_silent_hops
+    }
+}
+
+impl Algorithm for ParisTraceroute {
+    fn dst_addr(&self) -> IpAddr {
+        self.dst_addr
+    }
+
+    fn next_round(&mut self, replies: Vec<Reply>, _estimate_successors: bool) -> Vec<Probe> {
+        self.current_round += 1;
+        for reply in &replies {
+            self.topology.add_reply(reply);
+        }
+        self.replies_by_round
+            .entry(self.current_round)
+            .or_default()
+            .extend(replies);
+
+        if self.is_done() {
+            return vec![];
+        }
+
+        // enough TTLs per round to notice the end of the path in a single round,
+        // without probing past the silent hops limit
+        let window = (self.max_silent_hops - self.silent_hops()) as u16;
+        let last_ttl = (self.next_ttl + window - 1).min(self.max_ttl as u16);
+        let mut probes = vec![];
+        for ttl in self.next_ttl..=last_ttl {
+            for _ in 0..self.probes_per_hop {
+                probes.push(Probe {
+                    dst_addr: self.dst_addr,
+                    src_port: self.src_port,
+                    dst_port: self.dst_port,
+                    protocol: self.protocol,
+                    ttl: ttl as TTL,
+                });
+            }
+        }
+        self.next_ttl = last_ttl + 1;
+        probes
+    }
+
+    fn add_late_replies(&mut self, replies: Vec<(u32, Reply)>) {
+        for (round, reply) in replies {
+            self.topology.add_reply(&reply);
+            self.replies_by_round
+                .entry(round + 1)
+                .or_default()
+                .push(reply);
+        }
+    }
+
+    fn replies(&self) -> Vec<&Reply> {
+        self.replies_by_round.values().flatten().collect()
+    }
+
+    fn topology(&self) -> &Topology {
+        &self.topology
+    }
+}
+
+#[cfg(test)]
+mod tests;
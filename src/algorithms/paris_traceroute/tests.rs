@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+
+use crate::helpers::{addr, network, DEST, IP, SRC};
+use crate::probe::Prober;
+use crate::simulator::{Network, Router, SimulatedProber};
+
+use super::*;
+
+// runs `alg` over `network` and returns the number of probes sent
+fn run(network: Network, alg: &mut ParisTraceroute) -> usize {
+    let mut prober = SimulatedProber::new(network);
+    let mut n_probes = 0;
+    let mut probes = alg.next_round(vec![], false);
+    while !probes.is_empty() {
+        n_probes += probes.len();
+        let replies = prober.probe(probes).unwrap();
+        probes = alg.next_round(replies, false);
+    }
+    n_probes
+}
+
+#[test]
+fn test_single_flow() {
+    // [ IP[0] ] -+- [ IP[1] ] -+- [ IP[3] ] -- [ DEST ]
+    //            +- [ IP[2] ] -+
+    let network = network(&[
+        (SRC, IP[0]),
+        (IP[0], IP[1]),
+        (IP[0], IP[2]),
+        (IP[1], IP[3]),
+        (IP[2], IP[3]),
+    ]);
+    let mut alg = ParisTraceroute::new(addr(DEST), 1, 32, 24000, 33434, L4::ICMP);
+
+    let n_probes = run(network, &mut alg);
+
+    // a single window of 5 TTLs reaches the destination at TTL 4
+    assert_eq!(n_probes, 5 * DEFAULT_PROBES_PER_HOP);
+    assert!(alg.destination_reached());
+    // a single path through the diamond
+    let interfaces: HashSet<IpAddr> = alg
+        .time_exceeded_replies()
+        .iter()
+        .filter(|r| r.probe_ttl == 2)
+        .map(|r| r.reply_src_addr)
+        .collect();
+    assert_eq!(interfaces.len(), 1);
+    assert_eq!(alg.topology().n_links(), 2);
+}
+
+#[test]
+fn test_silent_hops() {
+    let mut network = network(&[(SRC, IP[0]), (IP[0], IP[1])]);
+    network.add_router(Router::new(addr(IP[1])).silent());
+    network.set_destination_responsive(false);
+    let mut alg = ParisTraceroute::new(addr(DEST), 1, 32, 24000, 33434, L4::ICMP)
+        .with_probes_per_hop(2)
+        .with_max_silent_hops(3);
+
+    let n_probes = run(network, &mut alg);
+
+    // TTL 1 answers, TTLs 2 and 3 are silent in the first round, then TTL 4 in the second one
+    assert_eq!(n_probes, 2 * 4);
+    assert!(!alg.destination_reached());
+    assert_eq!(alg.silent_hops(), 3);
+}
@@ -1,5 +1,7 @@
 mod stopping_point;
 pub use stopping_point::estimate_total_interfaces;
 pub use stopping_point::event_prob;
+pub use stopping_point::non_uniform_stopping_point;
 pub use stopping_point::stopping_point;
+pub use stopping_point::StoppingRule;
 pub use stopping_point::LIKELIHOOD_THRESHOLD;
@@ -33,6 +33,39 @@ pub fn stopping_point(n_interfaces: usize, failure_probability: f64) -> usize {
         .unwrap_or(MAX_N_PROBES)
 }
 
+/// How the number of probes needed to find all the successors of a node is computed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StoppingRule {
+    /// Traffic is split evenly over the successors, see [`stopping_point`].
+    #[default]
+    Uniform,
+    /// Traffic is split as observed so far, see [`non_uniform_stopping_point`].
+    NonUniform,
+}
+
+/// Stopping point of a node whose successors got `flows_by_successor` flows so far,
+/// not assuming that the load balancer splits traffic evenly.
+///
+/// An unseen successor is assumed to get as few flows as the least used successor seen.
+/// The number of probes is the smallest one such that such a successor is seen with
+/// probability at least (1 - failure_probability), and never less than the uniform stopping point.
+pub fn non_uniform_stopping_point(flows_by_successor: &[usize], failure_probability: f64) -> usize {
+    let uniform = stopping_point(flows_by_successor.len(), failure_probability);
+    let n_flows: usize = flows_by_successor.iter().sum();
+    let Some(&min_flows) = flows_by_successor.iter().min() else {
+        return uniform;
+    };
+    // with a single successor seen, nothing is known about the split
+    if flows_by_successor.len() < 2 || min_flows == 0 {
+        return uniform;
+    }
+    let min_share = min_flows as Probability / n_flows as Probability;
+    // share of the unseen successor once added to the ones seen
+    let unseen_share = min_share / (1.0 + min_share);
+    let n_probes = (failure_probability.ln() / (1.0 - unseen_share).ln()).ceil() as usize;
+    uniform.max(n_probes.min(MAX_N_PROBES))
+}
+
 fn binomial(n: usize, k: usize) -> Probability {
     (0..k).fold(1.0, |result, i| {
         result * (n - i) as Probability / (k - i) as Probability
@@ -144,6 +177,30 @@ mod tests {
         )
     }
 
+    #[test]
+    fn non_uniform_stopping_point_skewed() {
+        // an even split falls back to the uniform rule
+        assert_eq!(
+            non_uniform_stopping_point(&[], 0.05),
+            stopping_point(0, 0.05)
+        );
+        assert_eq!(
+            non_uniform_stopping_point(&[7], 0.05),
+            stopping_point(1, 0.05)
+        );
+        assert_eq!(
+            non_uniform_stopping_point(&[6, 5], 0.05),
+            stopping_point(2, 0.05)
+        );
+        // a successor getting 1 flow out of 20 calls for many more probes
+        let n_probes = non_uniform_stopping_point(&[19, 1], 0.05);
+        assert!(n_probes > stopping_point(2, 0.05));
+        // a successor as rare as the rarest one seen is then found with the requested confidence
+        let unseen_share: f64 = 1.0 / 21.0;
+        assert!(1.0 - (1.0 - unseen_share).powi(n_probes as i32) >= 0.95);
+        assert!(1.0 - (1.0 - unseen_share).powi(n_probes as i32 - 1) < 0.95);
+    }
+
     fn simulate_draw(rng: &mut ChaCha8Rng, n_interfaces: usize, n_probes: usize) -> usize {
         // compute the size of the distinct values set
         // after n_probes samples with replacement
@@ -0,0 +1,317 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::IpAddr;
+
+use anyhow::Result;
+use caracat::models::{Probe, Reply, L4};
+use itertools::Itertools;
+use pnet::packet::icmp::IcmpTypes;
+use pnet::packet::icmpv6::Icmpv6Types;
+use serde::Serialize;
+
+use crate::probe::Prober;
+use crate::topology::Topology;
+use crate::types::{initial_ttl, Port, TTL};
+
+/// IP-IDs needed from an interface before its counter is compared with the ones of other interfaces.
+pub const MBT_MIN_SAMPLES: usize = 3;
+
+/// Increase of a shared IP-ID counter allowed between two replies on top of the expected one,
+/// for the packets the router sent to other hosts in the meantime.
+pub const MBT_SLACK: u16 = 1000;
+
+/// Rounds of echo probes sent to the interfaces whose IP-IDs are too few to be compared.
+pub const DEFAULT_ALIAS_ROUNDS: usize = 5;
+
+// the increase of a shared counter is bounded by twice the velocity of the fastest of the two
+const MBT_VELOCITY_FACTOR: f64 = 2.0;
+
+// TTL of the echo probes, large enough to reach any interface of the trace
+const ECHO_TTL: TTL = 64;
+
+/// The initial TTLs of the replies of an interface, which differ across router vendors and
+/// operating systems (Vanaubel et al., "Network Fingerprinting: TTL-Based Router Signatures", IMC 2013).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Fingerprint {
+    /// Initial TTL of the time exceeded replies.
+    pub time_exceeded_ttl: Option<u8>,
+    /// Initial TTL of the echo replies.
+    pub echo_ttl: Option<u8>,
+    /// TTL of the quoted probe, 0 or 1 depending on whether the router decrements it before quoting it.
+    /// Larger quoted TTLs come from the path, e.g. an MPLS tunnel, and are left out.
+    pub quoted_ttl: Option<u8>,
+}
+
+impl Fingerprint {
+    /// Whether two interfaces can belong to the same router: the values known for both are equal.
+    pub fn is_compatible(&self, other: &Fingerprint) -> bool {
+        let compatible = |a: Option<u8>, b: Option<u8>| a.zip(b).is_none_or(|(a, b)| a == b);
+        compatible(self.time_exceeded_ttl, other.time_exceeded_ttl)
+            && compatible(self.echo_ttl, other.echo_ttl)
+            && compatible(self.quoted_ttl, other.quoted_ttl)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    timestamp: u64,
+    ip_id: u16,
+}
+
+// what the IP-IDs of an interface tell about its counter
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Counter {
+    TooFew,
+    /// IPv6, constant, random or reflected IP-IDs.
+    Unusable,
+    /// Increase of the counter per microsecond.
+    Velocity(f64),
+}
+
+/// Tells which interfaces of a trace are aliases of the same router, as the alias resolution stage
+/// of Multilevel MDA-Lite (Vermeulen et al., "Multilevel MDA-Lite Paris Traceroute", IMC 2018).
+/// The candidates are the interfaces found at the same TTL. Two candidates are aliases when their
+/// [`Fingerprint`]s are compatible and their IP-IDs pass the monotonic bounds test of MIDAR
+/// (Keys et al., "Scalable and Accurate IPv4 Alias Resolution", IEEE/ACM ToN 2013): merged by capture
+/// time, they look like the output of a single counter. IPv6 replies have no IP-ID, and their
+/// interfaces are never aliased.
+#[derive(Clone, Debug, Default)]
+pub struct AliasResolver {
+    samples: HashMap<IpAddr, Vec<Sample>>,
+    fingerprints: HashMap<IpAddr, Fingerprint>,
+    // interfaces that copy the IP-ID of the echo probes in their replies
+    reflecting: HashSet<IpAddr>,
+    candidates: BTreeSet<(IpAddr, IpAddr)>,
+}
+
+impl AliasResolver {
+    /// A resolver for the interfaces of `topology`, with the IP-IDs and TTLs of the `replies` of the trace.
+    pub fn new(replies: &[&Reply], topology: &Topology, dst_addr: IpAddr) -> Self {
+        let ttls: BTreeSet<TTL> = topology
+            .nodes()
+            .flat_map(|(_, node)| node.ttls.iter().copied())
+            .collect();
+        let candidates = ttls
+            .into_iter()
+            .flat_map(|ttl| {
+                topology
+                    .nodes_at_ttl(ttl)
+                    .filter(|&addr| addr != dst_addr)
+                    .sorted()
+                    .tuple_combinations()
+            })
+            .collect();
+        let mut resolver = Self {
+            candidates,
+            ..Self::default()
+        };
+        for reply in replies {
+            resolver.add_reply(reply);
+        }
+        resolver
+    }
+
+    /// Adds the IP-ID and the TTLs of a time exceeded or echo reply to the ones of its source.
+    pub fn add_reply(&mut self, reply: &Reply) {
+        let fingerprint = self.fingerprints.entry(reply.reply_src_addr).or_default();
+        if reply.is_time_exceeded() {
+            fingerprint.time_exceeded_ttl = Some(initial_ttl(reply.reply_ttl));
+            if reply.quoted_ttl <= 1 {
+                fingerprint.quoted_ttl = Some(reply.quoted_ttl);
+            }
+        } else if is_echo_reply(reply) {
+            fingerprint.echo_ttl = Some(initial_ttl(reply.reply_ttl));
+            if reply.reply_id == reply.probe_id {
+                self.reflecting.insert(reply.reply_src_addr);
+                return;
+            }
+        } else {
+            return;
+        }
+        self.samples
+            .entry(reply.reply_src_addr)
+            .or_default()
+            .push(Sample {
+                timestamp: reply.capture_timestamp,
+                ip_id: reply.reply_id,
+            });
+    }
+
+    pub fn fingerprint(&self, addr: IpAddr) -> Fingerprint {
+        self.fingerprints.get(&addr).copied().unwrap_or_default()
+    }
+
+    /// Whether `a` and `b` are aliases, or None if their IP-IDs cannot tell (yet).
+    pub fn is_alias(&self, a: IpAddr, b: IpAddr) -> Option<bool> {
+        if !self.fingerprint(a).is_compatible(&self.fingerprint(b)) {
+            return Some(false);
+        }
+        let velocity = match (self.counter(a), self.counter(b)) {
+            (Counter::Velocity(va), Counter::Velocity(vb)) => va.max(vb),
+            (Counter::Unusable, _) | (_, Counter::Unusable) => return Some(false),
+            _ => return None,
+        };
+        let samples = self.samples[&a]
+            .iter()
+            .chain(&self.samples[&b])
+            .sorted_by_key(|sample| sample.timestamp);
+        Some(samples.tuple_windows().all(|(first, second)| {
+            let increase = second.ip_id.wrapping_sub(first.ip_id);
+            let elapsed = (second.timestamp - first.timestamp) as f64;
+            increase > 0
+                && (increase as f64) <= MBT_VELOCITY_FACTOR * velocity * elapsed + MBT_SLACK as f64
+        }))
+    }
+
+    // a counter is usable if it increases by less than half its range between two replies
+    fn counter(&self, addr: IpAddr) -> Counter {
+        if addr.is_ipv6() || self.reflecting.contains(&addr) {
+            return Counter::Unusable;
+        }
+        let Some(samples) = self.samples.get(&addr) else {
+            return Counter::TooFew;
+        };
+        if samples.len() < MBT_MIN_SAMPLES {
+            return Counter::TooFew;
+        }
+        let samples: Vec<&Sample> = samples
+            .iter()
+            .sorted_by_key(|sample| sample.timestamp)
+            .collect();
+        let mut increase = 0;
+        for (first, second) in samples.iter().tuple_windows() {
+            let step = second.ip_id.wrapping_sub(first.ip_id);
+            if step == 0 || step >= 1 << 15 {
+                return Counter::Unusable;
+            }
+            increase += step as u64;
+        }
+        let elapsed = samples[samples.len() - 1].timestamp - samples[0].timestamp;
+        Counter::Velocity(increase as f64 / elapsed.max(1) as f64)
+    }
+
+    /// The candidates whose IP-IDs are too few to tell whether they are aliases.
+    pub fn echo_targets(&self) -> BTreeSet<IpAddr> {
+        self.candidates
+            .iter()
+            .filter(|&&(a, b)| self.is_alias(a, b).is_none())
+            .flat_map(|&(a, b)| [a, b])
+            .collect()
+    }
+
+    /// One echo probe to each of the [`AliasResolver::echo_targets`].
+    pub fn echo_probes(&self, src_port: Port, dst_port: Port) -> Vec<Probe> {
+        self.echo_targets()
+            .into_iter()
+            .map(|dst_addr| Probe {
+                dst_addr,
+                src_port,
+                dst_port,
+                protocol: L4::ICMP,
+                ttl: ECHO_TTL,
+            })
+            .collect()
+    }
+
+    /// The sets of aliases, of two interfaces at least. An interface joins the first set
+    /// whose interfaces are all its aliases, so that every pair of a set passed the tests.
+    pub fn alias_sets(&self) -> Vec<BTreeSet<IpAddr>> {
+        let aliases: HashSet<(IpAddr, IpAddr)> = self
+            .candidates
+            .iter()
+            .filter(|&&(a, b)| self.is_alias(a, b) == Some(true))
+            .copied()
+            .collect();
+        let addrs: BTreeSet<IpAddr> = aliases.iter().flat_map(|&(a, b)| [a, b]).collect();
+        let mut sets: Vec<BTreeSet<IpAddr>> = vec![];
+        for addr in addrs {
+            let set = sets.iter_mut().find(|set| {
+                set.iter()
+                    .all(|&other| aliases.contains(&(other.min(addr), other.max(addr))))
+            });
+            match set {
+                Some(set) => {
+                    set.insert(addr);
+                }
+                None => sets.push(BTreeSet::from([addr])),
+            }
+        }
+        sets.retain(|set| set.len() > 1);
+        sets
+    }
+}
+
+fn is_echo_reply(reply: &Reply) -> bool {
+    match reply.reply_src_addr {
+        IpAddr::V4(_) => reply.reply_icmp_type == IcmpTypes::EchoReply.0,
+        IpAddr::V6(_) => reply.reply_icmp_type == Icmpv6Types::EchoReply.0,
+    }
+}
+
+/// Sends up to `rounds` rounds of echo probes to the interfaces whose IP-IDs are too few to tell
+/// whether they are aliases, one probe per interface and round, so that the IP-IDs of aliases
+/// interleave. Returns the number of probes sent.
+pub fn resolve_aliases<P: Prober>(
+    prober: &mut P,
+    resolver: &mut AliasResolver,
+    rounds: usize,
+    src_port: Port,
+    dst_port: Port,
+) -> Result<usize> {
+    let mut n_probes = 0;
+    for _ in 0..rounds {
+        let probes = resolver.echo_probes(src_port, dst_port);
+        if probes.is_empty() {
+            break;
+        }
+        n_probes += probes.len();
+        for reply in prober.probe(probes)? {
+            resolver.add_reply(&reply);
+        }
+    }
+    Ok(n_probes)
+}
+
+/// The router-level view of a topology: the interfaces of each router and the links between them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RouterGraph {
+    /// The interfaces of each router, sorted. An interface without aliases is a router of its own.
+    pub routers: Vec<BTreeSet<IpAddr>>,
+    /// Links between routers, as indices in `routers`.
+    pub links: BTreeSet<(usize, usize)>,
+}
+
+impl RouterGraph {
+    /// Merges the interfaces of `topology` that are in the same alias set, and their links.
+    pub fn new(topology: &Topology, alias_sets: &[BTreeSet<IpAddr>]) -> Self {
+        let aliased: HashSet<IpAddr> = alias_sets.iter().flatten().copied().collect();
+        let mut routers = alias_sets.to_vec();
+        routers.extend(
+            topology
+                .nodes()
+                .filter(|(addr, _)| !aliased.contains(addr))
+                .map(|(addr, _)| BTreeSet::from([addr])),
+        );
+        routers.sort();
+        let index: HashMap<IpAddr, usize> = routers
+            .iter()
+            .enumerate()
+            .flat_map(|(i, router)| router.iter().map(move |&addr| (addr, i)))
+            .collect();
+        let links = topology
+            .links()
+            .filter_map(|(_, near, far, _)| Some((*index.get(&near)?, *index.get(&far)?)))
+            .filter(|(near, far)| near != far)
+            .collect();
+        Self { routers, links }
+    }
+
+    /// The index of the router of `addr`.
+    pub fn router(&self, addr: IpAddr) -> Option<usize> {
+        self.routers
+            .iter()
+            .position(|router| router.contains(&addr))
+    }
+}
+
+#[cfg(test)]
+mod tests;
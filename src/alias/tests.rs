@@ -0,0 +1,159 @@
+use pnet::packet::icmp::IcmpTypes;
+
+use crate::helpers::{addr, reply, DEST};
+
+use super::*;
+
+const IP: [&str; 4] = ["192.168.0.1", "192.168.0.2", "192.168.0.3", "192.168.0.4"];
+
+// a time exceeded reply from `ip` on the flow of `port`, with an IP-ID and a capture time in milliseconds
+fn sample(ttl: u8, ip: &str, port: u16, ip_id: u16, time: u64) -> Reply {
+    Reply {
+        probe_src_port: port,
+        reply_ttl: 250,
+        reply_id: ip_id,
+        capture_timestamp: time * 1000,
+        ..reply(ttl, ip, DEST)
+    }
+}
+
+// [ IP[0] ] -+- [ IP[1] ] -+- [ IP[3] ]
+//            +- [ IP[2] ] -+
+// with the IP-IDs of IP[1] and IP[2] given for three replies each, one every millisecond
+fn traced(ids_1: [u16; 3], ids_2: [u16; 3]) -> AliasResolver {
+    let mut replies = vec![];
+    for (i, (id_1, id_2)) in ids_1.into_iter().zip(ids_2).enumerate() {
+        let (port_1, port_2, time) = (2 * i as u16, 2 * i as u16 + 1, 2 * i as u64);
+        replies.push(sample(1, IP[0], port_1, 0, time));
+        replies.push(sample(2, IP[1], port_1, id_1, time));
+        replies.push(sample(3, IP[3], port_1, 0, time));
+        replies.push(sample(1, IP[0], port_2, 0, time + 1));
+        replies.push(sample(2, IP[2], port_2, id_2, time + 1));
+        replies.push(sample(3, IP[3], port_2, 0, time + 1));
+    }
+    let replies: Vec<&Reply> = replies.iter().collect();
+    AliasResolver::new(&replies, &Topology::from_replies(&replies), addr(DEST))
+}
+
+#[test]
+fn test_fingerprint() {
+    let juniper = Fingerprint {
+        time_exceeded_ttl: Some(255),
+        echo_ttl: Some(64),
+        quoted_ttl: Some(1),
+    };
+    let cisco = Fingerprint {
+        time_exceeded_ttl: Some(255),
+        echo_ttl: Some(255),
+        quoted_ttl: Some(1),
+    };
+    assert!(!juniper.is_compatible(&cisco));
+    assert!(juniper.is_compatible(&Fingerprint {
+        echo_ttl: None,
+        ..cisco
+    }));
+    assert!(juniper.is_compatible(&Fingerprint::default()));
+}
+
+#[test]
+fn test_shared_counter() {
+    let resolver = traced([100, 300, 500], [200, 400, 600]);
+    assert_eq!(resolver.is_alias(addr(IP[1]), addr(IP[2])), Some(true));
+    assert_eq!(
+        resolver.alias_sets(),
+        vec![BTreeSet::from([addr(IP[1]), addr(IP[2])])]
+    );
+    assert!(resolver.echo_targets().is_empty());
+}
+
+#[test]
+fn test_distinct_counters() {
+    let resolver = traced([100, 300, 500], [30000, 30200, 30400]);
+    assert_eq!(resolver.is_alias(addr(IP[1]), addr(IP[2])), Some(false));
+    assert!(resolver.alias_sets().is_empty());
+
+    // random IP-IDs
+    let random = traced([100, 40000, 500], [200, 400, 600]);
+    assert_eq!(random.is_alias(addr(IP[1]), addr(IP[2])), Some(false));
+}
+
+// answers echo probes with the IP-ID counter of the router of the target,
+// shared by IP[1] and IP[2], one millisecond after the previous reply
+struct EchoProber {
+    time: u64,
+    counters: [u16; 2],
+}
+
+impl Prober for EchoProber {
+    fn probe(&mut self, probes: Vec<Probe>) -> Result<Vec<Reply>> {
+        Ok(probes
+            .iter()
+            .map(|probe| {
+                let router = (probe.dst_addr == addr(IP[3])) as usize;
+                self.counters[router] += 10;
+                self.time += 1;
+                Reply {
+                    reply_src_addr: probe.dst_addr,
+                    reply_icmp_type: IcmpTypes::EchoReply.0,
+                    reply_ttl: 60,
+                    reply_id: self.counters[router],
+                    capture_timestamp: self.time * 1000,
+                    probe_dst_addr: probe.dst_addr,
+                    probe_ttl: probe.ttl,
+                    ..Default::default()
+                }
+            })
+            .collect())
+    }
+}
+
+#[test]
+fn test_echo_probes() {
+    // a single reply from IP[1] and IP[2] during the trace
+    let replies = [sample(2, IP[1], 0, 100, 0), sample(2, IP[2], 1, 110, 1)];
+    let replies: Vec<&Reply> = replies.iter().collect();
+    let mut resolver = AliasResolver::new(&replies, &Topology::from_replies(&replies), addr(DEST));
+    assert_eq!(
+        resolver.echo_targets(),
+        BTreeSet::from([addr(IP[1]), addr(IP[2])])
+    );
+
+    let mut prober = EchoProber {
+        time: 1,
+        counters: [110, 5000],
+    };
+    let probes = resolve_aliases(&mut prober, &mut resolver, 5, 24000, 33434).unwrap();
+    // two more IP-IDs each are enough
+    assert_eq!(probes, 4);
+    assert_eq!(resolver.fingerprint(addr(IP[1])).echo_ttl, Some(64));
+    assert_eq!(
+        resolver.alias_sets(),
+        vec![BTreeSet::from([addr(IP[1]), addr(IP[2])])]
+    );
+}
+
+#[test]
+fn test_router_graph() {
+    let resolver = traced([100, 300, 500], [200, 400, 600]);
+    let replies = [
+        sample(1, IP[0], 0, 0, 0),
+        sample(2, IP[1], 0, 0, 0),
+        sample(3, IP[3], 0, 0, 0),
+        sample(1, IP[0], 1, 0, 0),
+        sample(2, IP[2], 1, 0, 0),
+        sample(3, IP[3], 1, 0, 0),
+    ];
+    let replies: Vec<&Reply> = replies.iter().collect();
+    let graph = RouterGraph::new(&Topology::from_replies(&replies), &resolver.alias_sets());
+    assert_eq!(graph.routers.len(), 3);
+    let (first, second, third) = (
+        graph.router(addr(IP[0])).unwrap(),
+        graph.router(addr(IP[1])).unwrap(),
+        graph.router(addr(IP[3])).unwrap(),
+    );
+    assert_eq!(graph.router(addr(IP[2])), Some(second));
+    assert_eq!(
+        graph.links,
+        BTreeSet::from([(first, second), (second, third)])
+    );
+}
@@ -0,0 +1,238 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use ip_network::IpNetwork;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+
+use crate::topology::Topology;
+use crate::types::TTL;
+
+/// The AS that originates the longest prefix matching an address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Origin {
+    pub asn: u32,
+    #[serde(serialize_with = "serialize_prefix")]
+    pub prefix: IpNetwork,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AS{}", self.asn)
+    }
+}
+
+fn serialize_prefix<S: Serializer>(prefix: &IpNetwork, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(prefix)
+}
+
+/// A table of the prefixes announced in BGP and of the AS that originates them, loaded from disk:
+///
+/// - a pyasn file: `1.0.0.0/24 13335`, one prefix per line, with `;` comments;
+/// - a CAIDA prefix2as file: `1.0.0.0 24 13335`;
+/// - a RIB dump read by `bgpdump -m`: `TABLE_DUMP2|1700000000|B|192.0.2.1|64496|1.0.0.0/24|64496 13335|IGP|...`.
+///
+/// Files can mix formats. The first AS of multi-origin prefixes (`13335_64496`) and AS sets (`{13335,64496}`)
+/// is kept, and the first origin of a prefix seen by several peers of a RIB.
+#[derive(Clone, Debug, Default)]
+pub struct AsnTable {
+    origins: HashMap<IpNetwork, u32>,
+    // prefix lengths in the table, to look for the longest match first
+    lengths_v4: BTreeSet<u8>,
+    lengths_v6: BTreeSet<u8>,
+}
+
+impl AsnTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("cannot read {:?}", path))?;
+        Self::parse(content.lines()).with_context(|| format!("invalid prefix table {:?}", path))
+    }
+
+    pub fn parse<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Self> {
+        let mut table = Self::new();
+        for (i, line) in lines.enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            if let Some((prefix, asn)) =
+                parse_line(line).with_context(|| format!("line {}: {}", i + 1, line))?
+            {
+                table.insert(prefix, asn);
+            }
+        }
+        Ok(table)
+    }
+
+    /// Adds `prefix`, unless it already has an origin.
+    pub fn insert(&mut self, prefix: IpNetwork, asn: u32) {
+        match prefix {
+            IpNetwork::V4(_) => self.lengths_v4.insert(prefix.netmask()),
+            IpNetwork::V6(_) => self.lengths_v6.insert(prefix.netmask()),
+        };
+        self.origins.entry(prefix).or_insert(asn);
+    }
+
+    pub fn len(&self) -> usize {
+        self.origins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.origins.is_empty()
+    }
+
+    /// The origin of the longest prefix that contains `addr`, if any.
+    pub fn lookup(&self, addr: IpAddr) -> Option<Origin> {
+        let addr = match addr {
+            IpAddr::V6(addr) => addr.to_ipv4_mapped().map_or(IpAddr::V6(addr), IpAddr::V4),
+            addr => addr,
+        };
+        let lengths = match addr {
+            IpAddr::V4(_) => &self.lengths_v4,
+            IpAddr::V6(_) => &self.lengths_v6,
+        };
+        lengths.iter().rev().find_map(|&length| {
+            let prefix = IpNetwork::new_truncate(addr, length).ok()?;
+            let &asn = self.origins.get(&prefix)?;
+            Some(Origin { asn, prefix })
+        })
+    }
+
+    /// The origins of the interfaces of `topology` and of `dst_addr`, and the AS boundaries between them.
+    pub fn annotate(&self, topology: &Topology, dst_addr: IpAddr) -> AsnAnnotations {
+        let origins: HashMap<IpAddr, Origin> = topology
+            .nodes()
+            .map(|(addr, _)| addr)
+            .chain([dst_addr])
+            .filter_map(|addr| Some((addr, self.lookup(addr)?)))
+            .collect();
+        let mut boundaries: Vec<AsBoundary> = topology
+            .links()
+            .filter_map(|(ttl, near, far, _)| {
+                let (near_asn, far_asn) = (origins.get(&near)?.asn, origins.get(&far)?.asn);
+                (near_asn != far_asn).then_some(AsBoundary {
+                    ttl,
+                    near,
+                    far,
+                    near_asn,
+                    far_asn,
+                })
+            })
+            .collect();
+        boundaries.sort_by_key(|boundary| (boundary.ttl, boundary.near, boundary.far));
+        AsnAnnotations {
+            origins,
+            boundaries,
+        }
+    }
+}
+
+// the prefix and origin of a line, or None for the lines of a RIB dump that are not table entries
+fn parse_line(line: &str) -> Result<Option<(IpNetwork, u32)>> {
+    if line.contains('|') {
+        let fields: Vec<&str> = line.split('|').collect();
+        if !fields[0].starts_with("TABLE_DUMP") {
+            return Ok(None);
+        }
+        let (Some(prefix), Some(path)) = (fields.get(5), fields.get(6)) else {
+            bail!("missing prefix or AS path");
+        };
+        let Some(origin) = path.split_whitespace().last() else {
+            return Ok(None);
+        };
+        return Ok(Some((prefix.parse()?, parse_asn(origin)?)));
+    }
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    match fields[..] {
+        [prefix, asn] => Ok(Some((prefix.parse()?, parse_asn(asn)?))),
+        [addr, length, asn] => Ok(Some((
+            IpNetwork::new(addr.parse::<IpAddr>()?, length.parse()?)?,
+            parse_asn(asn)?,
+        ))),
+        _ => bail!("expected a prefix and an AS number"),
+    }
+}
+
+fn parse_asn(asn: &str) -> Result<u32> {
+    let first = asn
+        .trim_matches(|c| c == '{' || c == '}')
+        .split(['_', ','])
+        .next()
+        .unwrap_or_default();
+    first
+        .parse()
+        .with_context(|| format!("invalid AS number: {}", asn))
+}
+
+/// A link between interfaces originated by different ASes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct AsBoundary {
+    /// TTL of the near interface.
+    pub ttl: TTL,
+    pub near: IpAddr,
+    pub far: IpAddr,
+    pub near_asn: u32,
+    pub far_asn: u32,
+}
+
+/// The origins of the addresses of a trace, see [`AsnTable::annotate`].
+/// Links with an end without origin, e.g. a private address, are not AS boundaries.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AsnAnnotations {
+    pub origins: HashMap<IpAddr, Origin>,
+    pub boundaries: Vec<AsBoundary>,
+}
+
+impl AsnAnnotations {
+    pub fn origin(&self, addr: IpAddr) -> Option<Origin> {
+        self.origins.get(&addr).copied()
+    }
+
+    /// The boundaries crossed to reach `addr` at `ttl`.
+    pub fn boundaries_to(&self, ttl: TTL, addr: IpAddr) -> impl Iterator<Item = &AsBoundary> {
+        self.boundaries
+            .iter()
+            .filter(move |boundary| boundary.ttl + 1 == ttl && boundary.far == addr)
+    }
+
+    /// Adds `<key>_asn` and `<key>_prefix` next to every `<key>` of the objects in `value`
+    /// whose value is an address with an origin, e.g. the `from` of the replies of the Atlas format.
+    pub fn annotate_json(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                let origins: Vec<(String, Origin)> = object
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        let addr = value.as_str()?.parse().ok()?;
+                        Some((key.clone(), self.origin(addr)?))
+                    })
+                    .collect();
+                for value in object.values_mut() {
+                    self.annotate_json(value);
+                }
+                for (key, origin) in origins {
+                    object.insert(format!("{}_asn", key), origin.asn.into());
+                    object.insert(format!("{}_prefix", key), origin.prefix.to_string().into());
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    self.annotate_json(value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
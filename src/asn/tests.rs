@@ -0,0 +1,105 @@
+use caracat::models::Reply;
+use serde_json::json;
+
+use crate::helpers::{addr, reply};
+
+use super::*;
+
+const DEST: &str = "8.8.8.8";
+
+fn table() -> AsnTable {
+    let lines = [
+        "; IP-ASN32-DAT file",
+        "8.8.8.0/24\t15169",
+        "4.0.0.0 9 3356",
+        "4.68.0.0 16 3356_3549",
+        "TABLE_DUMP2|1700000000|B|192.0.2.1|64496|4.69.0.0/16|64496 3356 {3549,3550}|IGP|192.0.2.1|0|0||NAG||",
+        "BGP4MP|1700000000|A|192.0.2.1|64496|9.9.9.0/24|64496 19281|IGP",
+        "2001:4860::/32 15169",
+    ];
+    AsnTable::parse(lines.into_iter()).unwrap()
+}
+
+#[test]
+fn test_parse() {
+    let table = table();
+    assert_eq!(table.len(), 5);
+    assert_eq!(table.lookup(addr("8.8.8.8")).unwrap().asn, 15169);
+    assert_eq!(table.lookup(addr("4.68.1.1")).unwrap().asn, 3356);
+    assert_eq!(table.lookup(addr("4.69.1.1")).unwrap().asn, 3549);
+    assert_eq!(table.lookup(addr("9.9.9.9")), None);
+    assert_eq!(table.lookup(addr("2001:4860::8888")).unwrap().asn, 15169);
+    assert!(AsnTable::parse(["8.8.8.0/24"].into_iter()).is_err());
+    assert!(AsnTable::parse(["8.8.8.0/24 google"].into_iter()).is_err());
+}
+
+#[test]
+fn test_longest_match() {
+    let table = table();
+    let origin = table.lookup(addr("4.68.1.1")).unwrap();
+    assert_eq!(origin.prefix.to_string(), "4.68.0.0/16");
+    assert_eq!(origin.to_string(), "AS3356");
+    let origin = table.lookup(addr("4.1.1.1")).unwrap();
+    assert_eq!(origin.prefix.to_string(), "4.0.0.0/9");
+    assert_eq!(table.lookup(addr("4.128.0.1")), None);
+    // IPv4-mapped addresses
+    assert_eq!(table.lookup(addr("::ffff:8.8.8.8")).unwrap().asn, 15169);
+}
+
+// 192.168.1.1 -> 4.68.1.1 -> 4.69.1.1 -> 8.8.8.1
+fn topology() -> Topology {
+    let replies: Vec<Reply> = ["192.168.1.1", "4.68.1.1", "4.69.1.1", "8.8.8.1"]
+        .iter()
+        .enumerate()
+        .map(|(i, ip)| reply(i as u8 + 1, ip, DEST))
+        .collect();
+    let replies: Vec<&Reply> = replies.iter().collect();
+    Topology::from_replies(&replies)
+}
+
+#[test]
+fn test_annotate() {
+    let asn = table().annotate(&topology(), addr(DEST));
+    assert_eq!(asn.origin(addr("192.168.1.1")), None);
+    assert_eq!(asn.origin(addr(DEST)).unwrap().asn, 15169);
+    // the link from the private address is not a boundary
+    assert_eq!(
+        asn.boundaries,
+        vec![
+            AsBoundary {
+                ttl: 2,
+                near: addr("4.68.1.1"),
+                far: addr("4.69.1.1"),
+                near_asn: 3356,
+                far_asn: 3549,
+            },
+            AsBoundary {
+                ttl: 3,
+                near: addr("4.69.1.1"),
+                far: addr("8.8.8.1"),
+                near_asn: 3549,
+                far_asn: 15169,
+            }
+        ]
+    );
+    assert_eq!(asn.boundaries_to(4, addr("8.8.8.1")).count(), 1);
+    assert_eq!(asn.boundaries_to(2, addr("4.68.1.1")).count(), 0);
+}
+
+#[test]
+fn test_annotate_json() {
+    let asn = table().annotate(&topology(), addr(DEST));
+    let mut value = json!({
+        "dst_addr": DEST,
+        "src_addr": "192.168.1.2",
+        "result": [{"hop": 2, "result": [{"from": "4.68.1.1", "rtt": 1.5}]}],
+    });
+    asn.annotate_json(&mut value);
+    assert_eq!(value["dst_addr_asn"], 15169);
+    assert_eq!(value["dst_addr_prefix"], "8.8.8.0/24");
+    assert!(value.get("src_addr_asn").is_none());
+    assert_eq!(
+        value["result"][0]["result"][0],
+        json!({"from": "4.68.1.1", "from_asn": 3356, "from_prefix": "4.68.0.0/16", "rtt": 1.5})
+    );
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::mem;
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Context, Result};
+use caracat::high_level::Config;
+use caracat::models::{Probe, Reply};
+use futures::stream::{self, Stream};
+use log::{debug, warn};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::algorithms::Algorithm;
+use crate::probe::{Prober, ProbingSession};
+use crate::receiver::RoundReplies;
+use crate::replay::ReplayProber;
+use crate::simulator::SimulatedProber;
+
+/// An asynchronous probing backend, the counterpart of [`Prober`] for traces driven by an async runtime.
+pub trait AsyncProber {
+    fn probe(&mut self, probes: Vec<Probe>) -> impl Future<Output = Result<Vec<Reply>>> + Send;
+
+    /// Takes the replies that arrived after their round was closed, see [`Prober::late_replies`].
+    fn late_replies(&mut self) -> Vec<(u32, Reply)> {
+        vec![]
+    }
+}
+
+// the replies of a round, and the late replies to the previous ones
+type ProbedRound = (RoundReplies, Vec<(u32, Reply)>);
+
+enum Request {
+    Probe(Vec<Probe>, oneshot::Sender<Result<ProbedRound>>),
+    Close(oneshot::Sender<Vec<(u32, Reply)>>),
+}
+
+/// A [`ProbingSession`] running on its own thread, so that waiting for the replies of a round
+/// does not block the runtime. Each round is a future that can be dropped: the probes already
+/// sent are then answered in the background, and their replies are discarded.
+pub struct AsyncProbingSession {
+    requests: Option<mpsc::Sender<Request>>,
+    handle: Option<JoinHandle<()>>,
+    // rounds sent to the session, including the cancelled ones
+    sent_rounds: u32,
+    // round of the trace of each session round that was not cancelled
+    rounds: HashMap<u32, u32>,
+    late_replies: Vec<(u32, Reply)>,
+}
+
+impl AsyncProbingSession {
+    pub async fn new(config: Config) -> Result<Self> {
+        let (requests, mut rx) = mpsc::channel(1);
+        let (opened, opened_rx) = oneshot::channel();
+
+        let handle = thread::spawn(move || {
+            let mut session = match ProbingSession::new(config) {
+                Ok(session) => {
+                    let _ = opened.send(Ok(()));
+                    session
+                }
+                Err(err) => {
+                    let _ = opened.send(Err(err));
+                    return;
+                }
+            };
+            while let Some(request) = rx.blocking_recv() {
+                match request {
+                    Request::Probe(probes, reply) => {
+                        let round = session
+                            .probe_round(probes)
+                            .map(|round| (round, session.late_replies()));
+                        if reply.send(round).is_err() {
+                            debug!("round cancelled, dropping its replies");
+                        }
+                    }
+                    Request::Close(reply) => {
+                        let _ = reply.send(session.close());
+                        return;
+                    }
+                }
+            }
+            // the session was dropped without being closed
+            session.close();
+        });
+
+        opened_rx
+            .await
+            .context("probing thread stopped before opening the session")??;
+        Ok(Self {
+            requests: Some(requests),
+            handle: Some(handle),
+            sent_rounds: 0,
+            rounds: HashMap::new(),
+            late_replies: vec![],
+        })
+    }
+
+    /// Sends a round of probes and waits until each of them is answered or timed out.
+    pub async fn probe_round(&mut self, probes: Vec<Probe>) -> Result<RoundReplies> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(Request::Probe(probes, reply)).await?;
+        self.sent_rounds += 1;
+        let (round, late_replies) = reply_rx
+            .await
+            .map_err(|_| anyhow!("probing thread stopped during the round"))??;
+        self.rounds
+            .insert(self.sent_rounds, self.rounds.len() as u32 + 1);
+        self.attribute(late_replies);
+        Ok(round)
+    }
+
+    // renumbers the rounds of late replies without the cancelled rounds
+    fn attribute(&mut self, late_replies: Vec<(u32, Reply)>) {
+        for (round, reply) in late_replies {
+            match self.rounds.get(&round) {
+                Some(&round) => self.late_replies.push((round, reply)),
+                None => debug!("dropping late reply to a cancelled round"),
+            }
+        }
+    }
+
+    /// Stops the receiver. Replies received since the last round are returned as late replies.
+    pub async fn close(mut self) -> Vec<(u32, Reply)> {
+        let (reply, reply_rx) = oneshot::channel();
+        if self.send(Request::Close(reply)).await.is_ok() {
+            let late_replies = reply_rx.await.unwrap_or_default();
+            self.attribute(late_replies);
+        }
+        mem::take(&mut self.late_replies)
+    }
+
+    async fn send(&mut self, request: Request) -> Result<()> {
+        let requests = self.requests.as_ref().context("session closed")?;
+        requests
+            .send(request)
+            .await
+            .map_err(|_| anyhow!("probing thread stopped"))
+    }
+}
+
+impl AsyncProber for AsyncProbingSession {
+    async fn probe(&mut self, probes: Vec<Probe>) -> Result<Vec<Reply>> {
+        let round = self.probe_round(probes).await?;
+        Ok(round.replies)
+    }
+
+    fn late_replies(&mut self) -> Vec<(u32, Reply)> {
+        mem::take(&mut self.late_replies)
+    }
+}
+
+impl AsyncProber for SimulatedProber {
+    async fn probe(&mut self, probes: Vec<Probe>) -> Result<Vec<Reply>> {
+        Prober::probe(self, probes)
+    }
+}
+
+impl AsyncProber for ReplayProber {
+    async fn probe(&mut self, probes: Vec<Probe>) -> Result<Vec<Reply>> {
+        Prober::probe(self, probes)
+    }
+}
+
+impl Drop for AsyncProbingSession {
+    fn drop(&mut self) {
+        // closing the channel stops the thread once its current round is over
+        self.requests.take();
+        if let Some(handle) = self.handle.take() {
+            if !handle.is_finished() {
+                debug!("probing thread still running, not waiting for it");
+            } else if handle.join().is_err() {
+                warn!("probing thread panicked");
+            }
+        }
+    }
+}
+
+/// The outcome of a round of an [`AsyncTrace`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoundResult {
+    pub round: u32,
+    pub probes: usize,
+    pub replies: usize,
+    pub late_replies: usize,
+    /// Links and nodes found since the start of the trace.
+    pub links: usize,
+    pub nodes: usize,
+    /// Probes of the next round, 0 if the trace is done.
+    pub next_probes: usize,
+}
+
+/// The probing rounds of an algorithm over an [`AsyncProber`], one future per round.
+///
+/// A round is only taken into account once its replies are received: if its future is dropped,
+/// e.g. by a timeout, the next call sends the same probes again.
+pub struct AsyncTrace<A, P> {
+    algorithm: A,
+    prober: P,
+    estimate_successors: bool,
+    round: u32,
+    // probes of the next round, None before the first one
+    probes: Option<Vec<Probe>>,
+}
+
+impl<A: Algorithm, P: AsyncProber> AsyncTrace<A, P> {
+    pub fn new(algorithm: A, prober: P) -> Self {
+        Self {
+            algorithm,
+            prober,
+            estimate_successors: false,
+            round: 0,
+            probes: None,
+        }
+    }
+
+    pub fn with_estimate_successors(mut self, estimate_successors: bool) -> Self {
+        self.estimate_successors = estimate_successors;
+        self
+    }
+
+    /// Runs the next round, or returns `None` if the algorithm is done.
+    pub async fn next_round(&mut self) -> Option<Result<RoundResult>> {
+        let probes = self
+            .probes
+            .get_or_insert_with(|| self.algorithm.next_round(vec![], self.estimate_successors));
+        if probes.is_empty() {
+            return None;
+        }
+        let n_probes = probes.len();
+        let replies = match self.prober.probe(probes.clone()).await {
+            Ok(replies) => replies,
+            Err(err) => return Some(Err(err)),
+        };
+        let late_replies = self.prober.late_replies();
+
+        self.round += 1;
+        let n_replies = replies.len();
+        let n_late_replies = late_replies.len();
+        self.algorithm.add_late_replies(late_replies);
+        let probes = self.algorithm.next_round(replies, self.estimate_successors);
+        let next_probes = probes.len();
+        self.probes = Some(probes);
+
+        let topology = self.algorithm.topology();
+        Some(Ok(RoundResult {
+            round: self.round,
+            probes: n_probes,
+            replies: n_replies,
+            late_replies: n_late_replies,
+            links: topology.n_links(),
+            nodes: topology.n_nodes(),
+            next_probes,
+        }))
+    }
+
+    /// The rounds until the algorithm is done, or until the first error.
+    pub fn stream(&mut self) -> impl Stream<Item = Result<RoundResult>> + '_ {
+        stream::unfold(Some(self), |trace| async move {
+            let trace = trace?;
+            match trace.next_round().await? {
+                Ok(round) => Some((Ok(round), Some(trace))),
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    }
+
+    /// Number of rounds whose replies were received.
+    pub fn rounds(&self) -> u32 {
+        self.round
+    }
+
+    pub fn algorithm(&self) -> &A {
+        &self.algorithm
+    }
+
+    pub fn into_algorithm(self) -> A {
+        self.algorithm
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,117 @@
+use std::future::pending;
+use std::time::Duration;
+
+use futures::{future, StreamExt, TryStreamExt};
+use itertools::Itertools;
+use tokio::time::timeout;
+
+use crate::helpers::{addr, miner, prober, DEST};
+use crate::simulator::SimulatedProber;
+use crate::topology::Topology;
+use crate::tracer::Tracer;
+
+use super::*;
+
+// a prober whose first round never ends
+struct StalledProber {
+    prober: SimulatedProber,
+    stalled: bool,
+}
+
+impl AsyncProber for StalledProber {
+    async fn probe(&mut self, probes: Vec<Probe>) -> Result<Vec<Reply>> {
+        if !self.stalled {
+            self.stalled = true;
+            return pending().await;
+        }
+        Prober::probe(&mut self.prober, probes)
+    }
+}
+
+#[tokio::test]
+async fn test_stream() {
+    let mut trace = AsyncTrace::new(miner(95.0), prober());
+    let rounds: Vec<RoundResult> = trace.stream().try_collect().await.unwrap();
+
+    assert!(rounds.len() > 1);
+    assert_eq!(rounds.len() as u32, trace.rounds());
+    assert_eq!(rounds.first().unwrap().round, 1);
+    let last = rounds.last().unwrap();
+    assert_eq!(last.next_probes, 0);
+    assert_eq!(last.links, 4);
+    for (round, next) in rounds.iter().zip(&rounds[1..]) {
+        assert_eq!(round.next_probes, next.probes);
+    }
+    assert!(trace.next_round().await.is_none());
+
+    // the same topology as the blocking trace
+    let mut alg = miner(95.0);
+    let mut sync_prober = prober();
+    let mut probes = alg.next_round(vec![], false);
+    while !probes.is_empty() {
+        let replies = Prober::probe(&mut sync_prober, probes).unwrap();
+        probes = alg.next_round(replies, false);
+    }
+    let links = |topology: &Topology| {
+        topology
+            .links()
+            .map(|(ttl, near, far, _)| (ttl, near, far))
+            .sorted()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(links(trace.algorithm().topology()), links(alg.topology()));
+    assert_eq!(trace.algorithm().replies().len(), alg.replies().len());
+}
+
+#[tokio::test]
+async fn test_cancel() {
+    // a round that times out is not taken into account
+    let prober = StalledProber {
+        prober: prober(),
+        stalled: false,
+    };
+    let mut trace = AsyncTrace::new(miner(95.0), prober);
+    let round = timeout(Duration::from_millis(10), trace.next_round()).await;
+    assert!(round.is_err());
+    assert_eq!(trace.rounds(), 0);
+
+    // and is sent again by the next call
+    let rounds: Vec<RoundResult> = trace.stream().take(1).try_collect().await.unwrap();
+    assert_eq!(rounds.len(), 1);
+    assert_eq!(rounds[0].round, 1);
+    assert!(rounds[0].replies > 0);
+
+    // the rest of the trace after the stream is dropped
+    let rest = trace.stream().count().await;
+    assert!(rest > 0);
+    assert_eq!(trace.into_algorithm().topology().n_links(), 4);
+}
+
+#[tokio::test]
+async fn test_multiplex() {
+    let mut traces: Vec<_> = (0..3)
+        .map(|_| AsyncTrace::new(miner(95.0), prober()))
+        .collect();
+    let rounds = future::join_all(
+        traces
+            .iter_mut()
+            .map(|trace| trace.stream().try_collect::<Vec<_>>()),
+    )
+    .await;
+    for rounds in rounds {
+        assert_eq!(rounds.unwrap().last().unwrap().links, 4);
+    }
+
+    // in a task of its own
+    let mut trace = Tracer::new(addr(DEST))
+        .with_ttl_range(1, 10)
+        .into_async(prober())
+        .unwrap();
+    let task = tokio::spawn(async move {
+        let rounds = trace.stream().count().await;
+        (rounds, trace.into_algorithm())
+    });
+    let (rounds, alg) = task.await.unwrap();
+    assert!(rounds > 1);
+    assert_eq!(alg.topology().n_links(), 4);
+}
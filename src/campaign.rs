@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use caracat::models::{Probe, Reply, L4};
+use ip_network::IpNetwork;
+use log::debug;
+
+use crate::algorithms::diamond_miner::{
+    split_prefix, DiamondMiner, FlowMapper, DEFAULT_PREFIX_LEN_V4, DEFAULT_PREFIX_LEN_V6,
+};
+use crate::algorithms::Algorithm;
+use crate::probe::LATE_REPLY_WINDOW;
+use crate::types::{Port, TTL};
+
+type ProbeKey = (IpAddr, Port, Port, TTL);
+// instances that sent each probe of a round
+type Senders = HashMap<ProbeKey, Vec<usize>>;
+
+/// Traces many destinations at once, with one algorithm instance (Diamond Miner by default) per target.
+/// The probes of every instance are interleaved into a single batch per round,
+/// and the replies are dispatched back to the instances that sent the matching probe.
+pub struct Campaign<A: Algorithm = DiamondMiner> {
+    miners: Vec<A>,
+    active: Vec<bool>,
+    // probes requested by each instance
+    probes_sent: Vec<usize>,
+    round: u32,
+    // instances waiting for the reply to each probe, by round, and end of the round
+    pending: HashMap<u32, (Senders, Option<Instant>)>,
+}
+
+#[allow(clippy::too_many_arguments)]
+impl Campaign<DiamondMiner> {
+    pub fn new(
+        targets: Vec<IpAddr>,
+        min_ttl: TTL,
+        max_ttl: TTL,
+        src_port: Port,
+        dst_port: Port,
+        protocol: L4,
+        confidence: f64,
+        max_round: u32,
+    ) -> Self {
+        let miners: Vec<DiamondMiner> = targets
+            .into_iter()
+            .map(|dst_addr| {
+                DiamondMiner::new(
+                    dst_addr, min_ttl, max_ttl, src_port, dst_port, protocol, confidence, max_round,
+                )
+            })
+            .collect();
+        Self::from_algorithms(miners)
+    }
+
+    /// Replaces the flow mappers of every instance with the (IPv4, IPv6) pair built by `mappers`.
+    pub fn with_flow_mappers(
+        mut self,
+        mappers: impl Fn() -> (Box<dyn FlowMapper>, Box<dyn FlowMapper>),
+    ) -> Self {
+        self.miners = self
+            .miners
+            .into_iter()
+            .map(|miner| {
+                let (mapper_v4, mapper_v6) = mappers();
+                miner.with_flow_mappers(mapper_v4, mapper_v6)
+            })
+            .collect();
+        self
+    }
+
+    /// Re-probes flows behind the load balancers of every instance, see [`DiamondMiner::with_reprobes`].
+    pub fn with_reprobes(mut self, reprobes: usize) -> Self {
+        self.miners = self
+            .miners
+            .into_iter()
+            .map(|miner| miner.with_reprobes(reprobes))
+            .collect();
+        self
+    }
+}
+
+impl<A: Algorithm> Campaign<A> {
+    /// A campaign over already configured instances, one per target.
+    pub fn from_algorithms(miners: Vec<A>) -> Self {
+        Self {
+            active: vec![true; miners.len()],
+            probes_sent: vec![0; miners.len()],
+            miners,
+            round: 0,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn miners(&self) -> &[A] {
+        &self.miners
+    }
+
+    /// Number of targets that still have probes to send.
+    pub fn n_active(&self) -> usize {
+        self.active.iter().filter(|&&active| active).count()
+    }
+
+    pub fn into_miners(self) -> Vec<A> {
+        self.miners
+    }
+
+    /// Probes requested by each instance, including the ones requested by
+    /// several instances and sent once.
+    pub fn probes_sent(&self) -> &[usize] {
+        &self.probes_sent
+    }
+
+    /// The instances that still have probes to send.
+    pub fn active_miners(&self) -> impl Iterator<Item = &A> {
+        self.miners
+            .iter()
+            .zip(&self.active)
+            .filter(|(_, &active)| active)
+            .map(|(miner, _)| miner)
+    }
+
+    // the instances that sent the probe answered by `reply` in `round`
+    fn senders(&self, round: u32, reply: &Reply) -> &[usize] {
+        let key = (
+            reply.probe_dst_addr,
+            reply.probe_src_port,
+            reply.probe_dst_port,
+            reply.probe_ttl,
+        );
+        self.pending
+            .get(&round)
+            .and_then(|(pending, _)| pending.get(&key))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Dispatches replies that arrived after their round was closed to the instances that sent their probe.
+    pub fn add_late_replies(&mut self, replies: Vec<(u32, Reply)>) {
+        let mut replies_by_miner: Vec<Vec<(u32, Reply)>> = vec![vec![]; self.miners.len()];
+        for (round, reply) in replies {
+            for &index in self.senders(round, &reply) {
+                replies_by_miner[index].push((round, reply.clone()));
+            }
+        }
+        for (miner, replies) in self.miners.iter_mut().zip(replies_by_miner) {
+            miner.add_late_replies(replies);
+        }
+    }
+
+    pub fn next_round(&mut self, replies: Vec<Reply>, estimate_successors: bool) -> Vec<Probe> {
+        let mut replies_by_miner: Vec<Vec<Reply>> = vec![vec![]; self.miners.len()];
+        for reply in replies {
+            match self.senders(self.round, &reply) {
+                [] => debug!("dropping unsolicited reply from {}", reply.reply_src_addr),
+                [index] => replies_by_miner[*index].push(reply),
+                indices => {
+                    for &index in indices {
+                        replies_by_miner[index].push(reply.clone());
+                    }
+                }
+            }
+        }
+        // the prober drops the probes of a round once the late reply window is over,
+        // so no reply can be dispatched to their instances anymore
+        let now = Instant::now();
+        self.pending
+            .retain(|_, (_, end)| now.duration_since(*end.get_or_insert(now)) < LATE_REPLY_WINDOW);
+        self.round += 1;
+
+        let mut probes_by_miner = vec![];
+        for (index, (miner, replies)) in self.miners.iter_mut().zip(replies_by_miner).enumerate() {
+            if !self.active[index] {
+                continue;
+            }
+            let probes = miner.next_round(replies, estimate_successors);
+            self.probes_sent[index] += probes.len();
+            if probes.is_empty() {
+                self.active[index] = false;
+            } else {
+                probes_by_miner.push((index, probes.into_iter()));
+            }
+        }
+
+        // round-robin over the instances so that the probing rate is evenly shared between targets,
+        // a probe requested by several instances (overlapping prefixes) is only sent once
+        let mut probes = vec![];
+        let (pending, _) = self.pending.entry(self.round).or_default();
+        while !probes_by_miner.is_empty() {
+            probes_by_miner.retain_mut(|(index, miner_probes)| match miner_probes.next() {
+                Some(probe) => {
+                    let key = (probe.dst_addr, probe.src_port, probe.dst_port, probe.ttl);
+                    let senders = pending.entry(key).or_default();
+                    if senders.is_empty() {
+                        probes.push(probe);
+                    }
+                    senders.push(*index);
+                    true
+                }
+                None => false,
+            });
+        }
+        probes
+    }
+}
+
+/// Reads the campaign targets, one address or prefix per line. Empty lines and `#` comments are skipped.
+/// Prefixes are split into /24 (IPv4) or /64 (IPv6) subprefixes, each traced from its first address.
+pub fn parse_targets<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Vec<IpAddr>> {
+    let mut targets = vec![];
+    for line in lines {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Ok(addr) = line.parse::<IpAddr>() {
+            targets.push(addr);
+            continue;
+        }
+        let network = line
+            .parse::<IpNetwork>()
+            .with_context(|| format!("invalid target: {}", line))?;
+        // a prefix smaller than the ones probed by a miner is traced through its network address
+        let prefix_len = match network {
+            IpNetwork::V4(_) => DEFAULT_PREFIX_LEN_V4,
+            IpNetwork::V6(_) => DEFAULT_PREFIX_LEN_V6,
+        };
+        if network.netmask() > prefix_len {
+            targets.push(network.network_address());
+            continue;
+        }
+        for (af, subprefix, _) in split_prefix(line, DEFAULT_PREFIX_LEN_V4, DEFAULT_PREFIX_LEN_V6) {
+            let addr = match af {
+                4 => IpAddr::V4(subprefix.to_ipv4_mapped().unwrap()),
+                _ => IpAddr::V6(subprefix),
+            };
+            targets.push(addr);
+        }
+    }
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+
+use crate::helpers::{addr, network, prober, IP, SRC};
+use crate::probe::Prober;
+use crate::simulator::SimulatedProber;
+
+use super::*;
+
+const DEST: [&str; 2] = ["192.170.0.0", "192.170.8.0"];
+
+fn campaign(targets: &[&str]) -> Campaign {
+    Campaign::new(
+        targets.iter().map(|&target| addr(target)).collect(),
+        1,
+        10,
+        24000,
+        33434,
+        L4::ICMP,
+        95.0,
+        10,
+    )
+}
+
+#[test]
+fn test_parse_targets() {
+    let lines = "8.8.8.8\n\n# a comment\n192.170.0.0/23 # two /24s\n2001:db8::/63\n";
+    let targets = parse_targets(lines.lines()).unwrap();
+    assert_eq!(
+        targets,
+        vec![
+            addr("8.8.8.8"),
+            addr("192.170.0.0"),
+            addr("192.170.1.0"),
+            addr("2001:db8::"),
+            addr("2001:db8:0:1::"),
+        ]
+    );
+
+    assert!(parse_targets(["not an address"].into_iter()).is_err());
+
+    // prefixes smaller than a /24 or a /64
+    let lines = ["192.0.2.1/32", "192.0.2.128/25", "2001:db8::1/128"];
+    assert_eq!(
+        parse_targets(lines.into_iter()).unwrap(),
+        vec![addr("192.0.2.1"), addr("192.0.2.128"), addr("2001:db8::1")]
+    );
+}
+
+#[test]
+fn test_interleaved_probes() {
+    let mut campaign = campaign(&DEST);
+
+    let probes = campaign.next_round(vec![], false);
+
+    // 6 probes per TTL and per target
+    assert_eq!(probes.len(), 2 * 6 * 10);
+    // the probes of the two targets alternate
+    for pair in probes.chunks(2) {
+        let prefixes: HashSet<_> = pair
+            .iter()
+            .map(|probe| match probe.dst_addr {
+                IpAddr::V4(addr) => addr.octets()[2],
+                IpAddr::V6(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(prefixes, HashSet::from([0, 8]));
+    }
+}
+
+#[test]
+fn test_overlapping_targets() {
+    // the two targets are the same, every probe is shared
+    let mut campaign = campaign(&[DEST[0], DEST[0]]);
+
+    let probes = campaign.next_round(vec![], false);
+    assert_eq!(probes.len(), 6 * 10);
+}
+
+#[test]
+fn test_campaign_over_simulated_network() {
+    let mut prober = prober();
+    let mut campaign = campaign(&DEST);
+
+    let mut probes = campaign.next_round(vec![], false);
+    while !probes.is_empty() {
+        let replies = prober.probe(probes).unwrap();
+        probes = campaign.next_round(replies, false);
+    }
+    assert_eq!(campaign.n_active(), 0);
+
+    for (miner, target) in campaign.miners().iter().zip(DEST) {
+        assert_eq!(miner.dst_addr(), addr(target));
+
+        // every instance only received the replies to its own probes
+        let network = IpNetwork::new_truncate(addr(target), 21).unwrap();
+        assert!(miner
+            .replies()
+            .iter()
+            .all(|r| network.contains(r.probe_dst_addr)));
+
+        assert_eq!(miner.topology().n_links(), 4);
+    }
+}
+
+#[test]
+fn test_late_replies() {
+    let mut prober = SimulatedProber::new(network(&[(SRC, IP[0])]));
+    let mut campaign = campaign(&DEST);
+
+    let probes = campaign.next_round(vec![], false);
+    let mut replies = prober.probe(probes).unwrap();
+    // half of the replies of the first round arrive too late
+    let late_replies: Vec<(u32, Reply)> = replies
+        .split_off(replies.len() / 2)
+        .into_iter()
+        .map(|reply| (1, reply))
+        .collect();
+    let n_replies = replies.len() + late_replies.len();
+
+    campaign.next_round(replies, false);
+    campaign.add_late_replies(late_replies);
+
+    let n_received: usize = campaign
+        .miners()
+        .iter()
+        .map(|miner| miner.replies().len())
+        .sum();
+    assert_eq!(n_received, n_replies);
+}
+
+#[test]
+fn test_pending_probes_expire() {
+    let mut prober = SimulatedProber::new(network(&[(SRC, IP[0])]));
+    let mut campaign = campaign(&DEST);
+
+    let probes = campaign.next_round(vec![], false);
+    let late_replies: Vec<(u32, Reply)> = prober
+        .probe(probes)
+        .unwrap()
+        .into_iter()
+        .map(|reply| (1, reply))
+        .collect();
+    let probes = campaign.next_round(vec![], false);
+    let replies = prober.probe(probes).unwrap();
+    assert!(campaign.pending.contains_key(&1));
+
+    // the first round ended longer than the late reply window ago
+    campaign.pending.get_mut(&1).unwrap().1 = Instant::now().checked_sub(LATE_REPLY_WINDOW);
+    campaign.next_round(replies, false);
+    assert!(!campaign.pending.contains_key(&1));
+    assert!(campaign.pending.contains_key(&2));
+
+    // its replies are no longer dispatched
+    let n_received: usize = campaign
+        .miners()
+        .iter()
+        .map(|miner| miner.replies().len())
+        .sum();
+    campaign.add_late_replies(late_replies);
+    let n_late: usize = campaign
+        .miners()
+        .iter()
+        .map(|miner| miner.replies().len())
+        .sum();
+    assert_eq!(n_late, n_received);
+}
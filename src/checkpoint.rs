@@ -0,0 +1,289 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use caracat::models::{MPLSLabel, Probe, Reply, L4};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{L4Wrapper, Port, TTL};
+
+/// An MPLS label as stored on disk.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MplsLabelRecord {
+    pub label: u32,
+    pub experimental: u8,
+    pub bottom_of_stack: bool,
+    pub ttl: u8,
+}
+
+/// A reply as stored on disk, with the same fields as [`Reply`] which cannot be serialized.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplyRecord {
+    pub capture_timestamp: u64,
+    pub reply_src_addr: IpAddr,
+    pub reply_dst_addr: IpAddr,
+    pub reply_id: u16,
+    pub reply_size: u16,
+    pub reply_ttl: u8,
+    pub reply_protocol: u8,
+    pub reply_icmp_type: u8,
+    pub reply_icmp_code: u8,
+    pub reply_mpls_labels: Vec<MplsLabelRecord>,
+    pub probe_src_addr: IpAddr,
+    pub probe_dst_addr: IpAddr,
+    pub probe_id: u16,
+    pub probe_size: u16,
+    pub probe_protocol: u8,
+    pub probe_ttl: u8,
+    pub probe_src_port: u16,
+    pub probe_dst_port: u16,
+    pub quoted_ttl: u8,
+    pub rtt: u16,
+}
+
+impl From<&Reply> for ReplyRecord {
+    fn from(reply: &Reply) -> Self {
+        Self {
+            capture_timestamp: reply.capture_timestamp,
+            reply_src_addr: reply.reply_src_addr,
+            reply_dst_addr: reply.reply_dst_addr,
+            reply_id: reply.reply_id,
+            reply_size: reply.reply_size,
+            reply_ttl: reply.reply_ttl,
+            reply_protocol: reply.reply_protocol,
+            reply_icmp_type: reply.reply_icmp_type,
+            reply_icmp_code: reply.reply_icmp_code,
+            reply_mpls_labels: reply
+                .reply_mpls_labels
+                .iter()
+                .map(|label| MplsLabelRecord {
+                    label: label.label,
+                    experimental: label.experimental,
+                    bottom_of_stack: label.bottom_of_stack,
+                    ttl: label.ttl,
+                })
+                .collect(),
+            probe_src_addr: reply.probe_src_addr,
+            probe_dst_addr: reply.probe_dst_addr,
+            probe_id: reply.probe_id,
+            probe_size: reply.probe_size,
+            probe_protocol: reply.probe_protocol,
+            probe_ttl: reply.probe_ttl,
+            probe_src_port: reply.probe_src_port,
+            probe_dst_port: reply.probe_dst_port,
+            quoted_ttl: reply.quoted_ttl,
+            rtt: reply.rtt,
+        }
+    }
+}
+
+impl From<ReplyRecord> for Reply {
+    fn from(record: ReplyRecord) -> Self {
+        Self {
+            capture_timestamp: record.capture_timestamp,
+            reply_src_addr: record.reply_src_addr,
+            reply_dst_addr: record.reply_dst_addr,
+            reply_id: record.reply_id,
+            reply_size: record.reply_size,
+            reply_ttl: record.reply_ttl,
+            reply_protocol: record.reply_protocol,
+            reply_icmp_type: record.reply_icmp_type,
+            reply_icmp_code: record.reply_icmp_code,
+            reply_mpls_labels: record
+                .reply_mpls_labels
+                .into_iter()
+                .map(|label| MPLSLabel {
+                    label: label.label,
+                    experimental: label.experimental,
+                    bottom_of_stack: label.bottom_of_stack,
+                    ttl: label.ttl,
+                })
+                .collect(),
+            probe_src_addr: record.probe_src_addr,
+            probe_dst_addr: record.probe_dst_addr,
+            probe_id: record.probe_id,
+            probe_size: record.probe_size,
+            probe_protocol: record.probe_protocol,
+            probe_ttl: record.probe_ttl,
+            probe_src_port: record.probe_src_port,
+            probe_dst_port: record.probe_dst_port,
+            quoted_ttl: record.quoted_ttl,
+            rtt: record.rtt,
+        }
+    }
+}
+
+/// A probe as stored on disk, with its protocol number.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeRecord {
+    pub dst_addr: IpAddr,
+    pub src_port: Port,
+    pub dst_port: Port,
+    pub ttl: TTL,
+    pub protocol: u8,
+}
+
+impl From<&Probe> for ProbeRecord {
+    fn from(probe: &Probe) -> Self {
+        let protocol = match probe.protocol {
+            L4::ICMP => 1,
+            L4::ICMPv6 => 58,
+            L4::UDP => 17,
+        };
+        Self {
+            dst_addr: probe.dst_addr,
+            src_port: probe.src_port,
+            dst_port: probe.dst_port,
+            ttl: probe.ttl,
+            protocol,
+        }
+    }
+}
+
+impl From<ProbeRecord> for Probe {
+    fn from(record: ProbeRecord) -> Self {
+        Self {
+            dst_addr: record.dst_addr,
+            src_port: record.src_port,
+            dst_port: record.dst_port,
+            ttl: record.ttl,
+            protocol: (&L4Wrapper::from(record.protocol)).into(),
+        }
+    }
+}
+
+/// What a Diamond Miner instance learned so far. Everything else is either
+/// configuration, given again when resuming, or derived from the replies.
+/// In a checkpoint file, each checkpoint after the first one only holds the replies
+/// received since the previous one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MinerState {
+    pub dst_addr: IpAddr,
+    pub min_ttl: TTL,
+    pub max_ttl: TTL,
+    pub src_port: Port,
+    pub dst_port: Port,
+    pub current_round: u32,
+    pub probes_sent: HashMap<TTL, usize>,
+    pub replies_by_round: HashMap<u32, Vec<ReplyRecord>>,
+    pub checked_load_balancers: HashSet<(TTL, IpAddr)>,
+}
+
+/// The state of a trace between two rounds: the algorithm state
+/// and the probes of the next round, that may not have been sent.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub state: MinerState,
+    pub probes: Vec<ProbeRecord>,
+}
+
+impl Checkpoint {
+    pub fn new(state: MinerState, probes: &[Probe]) -> Self {
+        Self {
+            state,
+            probes: probes.iter().map(ProbeRecord::from).collect(),
+        }
+    }
+
+    pub fn probes(&self) -> Vec<Probe> {
+        self.probes.iter().cloned().map(Probe::from).collect()
+    }
+
+    /// Writes the checkpoint to `path` as a JSON line, through a temporary file so that
+    /// an interrupted write leaves the previous checkpoint intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, self.to_line()?)
+            .with_context(|| format!("cannot write checkpoint {:?}", tmp_path))?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Reads the checkpoints of a file written by [`Checkpoint::save`] and [`CheckpointLog`],
+    /// with the replies of all of them. A last line cut by an interrupted write is skipped.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("cannot read checkpoint {:?}", path))?;
+        let mut merged: Option<Checkpoint> = None;
+        for (index, line) in data.split_inclusive('\n').enumerate() {
+            let mut checkpoint: Checkpoint = match serde_json::from_str(line) {
+                Ok(checkpoint) => checkpoint,
+                Err(_) if !line.ends_with('\n') => break,
+                Err(err) => {
+                    return Err(err).with_context(|| {
+                        format!("invalid checkpoint {:?}, line {}", path, index + 1)
+                    })
+                }
+            };
+            if let Some(previous) = merged {
+                let mut replies_by_round = previous.state.replies_by_round;
+                for (round, replies) in checkpoint.state.replies_by_round {
+                    replies_by_round.entry(round).or_default().extend(replies);
+                }
+                checkpoint.state.replies_by_round = replies_by_round;
+            }
+            merged = Some(checkpoint);
+        }
+        merged.with_context(|| format!("empty checkpoint {:?}", path))
+    }
+
+    fn to_line(&self) -> Result<Vec<u8>> {
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+        Ok(line)
+    }
+}
+
+/// A checkpoint file written after every round. The first checkpoint replaces the file
+/// with the whole state, the next ones are appended with only the replies received since,
+/// so that a round costs about the same to checkpoint however long the trace.
+pub struct CheckpointLog {
+    path: PathBuf,
+    // None until the first checkpoint is written
+    file: Option<File>,
+    // replies of each round already written
+    saved: HashMap<u32, usize>,
+}
+
+impl CheckpointLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: None,
+            saved: HashMap::new(),
+        }
+    }
+
+    /// Replies of each round already written, to leave out of the next checkpoint.
+    pub fn saved(&self) -> &HashMap<u32, usize> {
+        &self.saved
+    }
+
+    /// Writes `checkpoint`, whose state holds the replies not written yet.
+    pub fn append(&mut self, checkpoint: &Checkpoint) -> Result<()> {
+        match &mut self.file {
+            Some(file) => file
+                .write_all(&checkpoint.to_line()?)
+                .and_then(|_| file.flush())
+                .with_context(|| format!("cannot write checkpoint {:?}", self.path))?,
+            None => {
+                checkpoint.save(&self.path)?;
+                let file = OpenOptions::new()
+                    .append(true)
+                    .open(&self.path)
+                    .with_context(|| format!("cannot open checkpoint {:?}", self.path))?;
+                self.file = Some(file);
+            }
+        }
+        for (&round, replies) in &checkpoint.state.replies_by_round {
+            *self.saved.entry(round).or_default() += replies.len();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
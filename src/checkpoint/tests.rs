@@ -0,0 +1,127 @@
+use std::net::Ipv4Addr;
+
+use crate::algorithms::diamond_miner::DiamondMiner;
+use crate::helpers::{addr, links, miner, prober, reply, DEST, IP};
+use crate::probe::Prober;
+
+use super::*;
+
+#[test]
+fn test_reply_record() {
+    let reply = Reply {
+        reply_mpls_labels: vec![MPLSLabel {
+            label: 24005,
+            experimental: 0,
+            bottom_of_stack: true,
+            ttl: 1,
+        }],
+        reply_dst_addr: IpAddr::from(Ipv4Addr::new(10, 0, 0, 1)),
+        rtt: 42,
+        ..reply(3, IP[1], DEST)
+    };
+    let record = ReplyRecord::from(&reply);
+    let json = serde_json::to_string(&record).unwrap();
+    let restored = Reply::from(serde_json::from_str::<ReplyRecord>(&json).unwrap());
+    assert_eq!(ReplyRecord::from(&restored), record);
+    assert_eq!(restored.reply_mpls_labels[0].label, 24005);
+}
+
+#[test]
+fn test_resume() {
+    // an uninterrupted trace
+    let mut prober = prober();
+    let mut expected = miner(95.0);
+    let mut probes = expected.next_round(vec![], false);
+    let mut n_rounds = 0;
+    while !probes.is_empty() {
+        let replies = prober.probe(probes).unwrap();
+        probes = expected.next_round(replies, false);
+        n_rounds += 1;
+    }
+    assert!(n_rounds > 1);
+
+    // the same trace, killed after its first round
+    let mut prober = self::prober();
+    let mut interrupted = miner(95.0);
+    let probes = interrupted.next_round(vec![], false);
+    let replies = prober.probe(probes).unwrap();
+    let probes = interrupted.next_round(replies, false);
+    let path = std::env::temp_dir().join(format!("voyage-checkpoint-{}.json", std::process::id()));
+    Checkpoint::new(interrupted.checkpoint(&HashMap::new()), &probes)
+        .save(&path)
+        .unwrap();
+    drop(interrupted);
+
+    let checkpoint = Checkpoint::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut resumed = miner(95.0);
+    let mut probes = checkpoint.probes();
+    resumed.restore(checkpoint.state).unwrap();
+    while !probes.is_empty() {
+        let replies = prober.probe(probes).unwrap();
+        probes = resumed.next_round(replies, false);
+    }
+
+    assert_eq!(links(&resumed), links(&expected));
+    assert_eq!(resumed.replies().len(), expected.replies().len());
+
+    // a checkpoint of another trace is rejected
+    let other = DiamondMiner::new(addr(IP[3]), 1, 10, 24000, 33434, L4::ICMP, 95.0, 10);
+    assert!(miner(95.0)
+        .restore(other.checkpoint(&HashMap::new()))
+        .is_err());
+}
+
+#[test]
+fn test_checkpoint_log() {
+    let path = std::env::temp_dir().join(format!("voyage-log-{}.jsonl", std::process::id()));
+    let mut log = CheckpointLog::new(path.clone());
+    let mut prober = prober();
+    let mut miner = miner(95.0);
+    let mut probes = miner.next_round(vec![], false);
+    log.append(&Checkpoint::new(miner.checkpoint(log.saved()), &probes))
+        .unwrap();
+    while !probes.is_empty() {
+        let replies = prober.probe(probes).unwrap();
+        probes = miner.next_round(replies, false);
+        log.append(&Checkpoint::new(miner.checkpoint(log.saved()), &probes))
+            .unwrap();
+    }
+
+    // each checkpoint only holds the replies received since the previous one
+    let data = std::fs::read_to_string(&path).unwrap();
+    let n_written: usize = data
+        .lines()
+        .map(|line| {
+            let checkpoint: Checkpoint = serde_json::from_str(line).unwrap();
+            checkpoint
+                .state
+                .replies_by_round
+                .values()
+                .map(Vec::len)
+                .sum::<usize>()
+        })
+        .sum();
+    assert!(data.lines().count() > 2);
+    assert_eq!(n_written, miner.replies().len());
+
+    let checkpoint = Checkpoint::load(&path).unwrap();
+    assert_eq!(
+        checkpoint.state.current_round,
+        miner.checkpoint(&HashMap::new()).current_round
+    );
+    let mut restored = self::miner(95.0);
+    restored.restore(checkpoint.state).unwrap();
+    assert_eq!(links(&restored), links(&miner));
+    assert_eq!(restored.replies().len(), miner.replies().len());
+
+    // a checkpoint cut by an interrupted write is skipped
+    let last_line = data.trim_end().rfind('\n').unwrap() + 1;
+    std::fs::write(&path, &data[..last_line + 10]).unwrap();
+    let checkpoint = Checkpoint::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        checkpoint.state.current_round,
+        miner.checkpoint(&HashMap::new()).current_round - 1
+    );
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::io::Write;
 use std::net::IpAddr;
 
@@ -8,12 +8,20 @@ use itertools::Itertools;
 use pantrace::formats::internal::{Traceroute, TracerouteHop};
 use pantrace::traits::TracerouteWriter;
 
+use crate::asn::AsnAnnotations;
+use crate::mpls::{Tunnel, TunnelKind};
+use crate::types::LoadBalancing;
+
 pub struct ClassicTracerouteWriter<W: Write> {
     output: W,
     min_ttl: u8,
     max_ttl: u8,
     dst_addr: IpAddr,
     total_flows: usize,
+    load_balancers: HashMap<(u8, IpAddr), LoadBalancing>,
+    unstable_ttls: BTreeSet<u8>,
+    tunnels: Vec<Tunnel>,
+    asn: AsnAnnotations,
 }
 
 impl<W: Write> ClassicTracerouteWriter<W> {
@@ -30,8 +38,67 @@ impl<W: Write> ClassicTracerouteWriter<W> {
             max_ttl,
             dst_addr,
             total_flows,
+            load_balancers: HashMap::new(),
+            unstable_ttls: BTreeSet::new(),
+            tunnels: vec![],
+            asn: AsnAnnotations::default(),
         }
     }
+
+    /// Annotates the load balancers with how they spread traffic,
+    /// and the hops at which a single flow reached several interfaces.
+    pub fn with_load_balancers(
+        mut self,
+        load_balancers: HashMap<(u8, IpAddr), LoadBalancing>,
+        unstable_ttls: BTreeSet<u8>,
+    ) -> ClassicTracerouteWriter<W> {
+        self.load_balancers = load_balancers;
+        self.unstable_ttls = unstable_ttls;
+        self
+    }
+
+    /// Annotates the LSRs and the egress interfaces of MPLS tunnels.
+    pub fn with_tunnels(mut self, tunnels: Vec<Tunnel>) -> ClassicTracerouteWriter<W> {
+        self.tunnels = tunnels;
+        self
+    }
+
+    /// Annotates the interfaces with their origin AS, like `traceroute -A`, and the AS boundaries.
+    pub fn with_asn_annotations(mut self, asn: AsnAnnotations) -> ClassicTracerouteWriter<W> {
+        self.asn = asn;
+        self
+    }
+
+    fn tunnel_annotation(&self, ttl: u8, addr: IpAddr) -> Option<String> {
+        let tunnel = self
+            .tunnels
+            .iter()
+            .find(|tunnel| tunnel.contains_node(ttl, addr))?;
+        if let Some(lsr) = tunnel
+            .lsrs
+            .iter()
+            .find(|hop| hop.ttl == ttl && hop.addr == addr)
+        {
+            return Some(match tunnel.kind {
+                TunnelKind::Explicit => format!(
+                    "{} MPLS tunnel, labels {}",
+                    tunnel.kind,
+                    lsr.labels.iter().join(" ")
+                ),
+                _ => format!("{} MPLS tunnel", tunnel.kind),
+            });
+        }
+        if !matches!(tunnel.kind, TunnelKind::Opaque | TunnelKind::Invisible) {
+            return None;
+        }
+        let mut annotation = format!("egress of an {} MPLS tunnel", tunnel.kind);
+        if !tunnel.revealed.is_empty() {
+            annotation += &format!(", revealed {}", tunnel.revealed.iter().join(" "));
+        } else if let Some(hidden_hops) = tunnel.hidden_hops {
+            annotation += &format!(", {} hidden hops", hidden_hops);
+        }
+        Some(annotation)
+    }
 }
 
 impl<W> TracerouteWriter for ClassicTracerouteWriter<W>
@@ -39,7 +106,15 @@ where
     W: Write,
 {
     fn write_traceroute(&mut self, traceroute: &Traceroute) -> Result<()> {
-        let packet_size = traceroute.flows[0].hops[0].probes[0].size;
+        // a target may not have answered at all, e.g. during a campaign
+        let packet_size = traceroute
+            .flows
+            .iter()
+            .flat_map(|flow| flow.hops.iter())
+            .flat_map(|hop| hop.probes.iter())
+            .map(|probe| probe.size)
+            .next()
+            .unwrap_or(0);
         write!(
             self.output,
             "traceroute to {}({}), {} hops max, {} bytes packets, flow {}/{}\n",
@@ -81,6 +156,9 @@ where
                 break;
             }
             write!(self.output, "{}", ttl).unwrap();
+            if self.unstable_ttls.contains(&ttl) {
+                write!(self.output, " (unstable)").unwrap();
+            }
             if let Some(hops) = hops_by_ttl.get(&ttl) {
                 let all_probes = hops
                     .into_iter()
@@ -101,6 +179,9 @@ where
                     found_dst |= ip == self.dst_addr;
 
                     write!(self.output, "   {} ({})", ip, ip).unwrap();
+                    if let Some(origin) = self.asn.origin(ip) {
+                        write!(self.output, " [{}]", origin).unwrap();
+                    }
                     let mean_rtt = rtts.iter().sum::<f64>() / rtts.len() as f64;
                     write!(
                         self.output,
@@ -109,6 +190,21 @@ where
                         rtts.len()
                     )
                     .unwrap();
+                    if let Some(load_balancing) = self.load_balancers.get(&(ttl, ip)) {
+                        write!(self.output, " [{} load balancer]", load_balancing).unwrap();
+                    }
+                    if let Some(annotation) = self.tunnel_annotation(ttl, ip) {
+                        write!(self.output, " [{}]", annotation).unwrap();
+                    }
+                    let from: BTreeSet<u32> = self
+                        .asn
+                        .boundaries_to(ttl, ip)
+                        .map(|boundary| boundary.near_asn)
+                        .collect();
+                    if !from.is_empty() {
+                        let from = from.iter().map(|asn| format!("AS{}", asn)).join(" ");
+                        write!(self.output, " [AS boundary from {}]", from).unwrap();
+                    }
                     write!(self.output, "\n").unwrap();
                 }
                 // write!(self.output, "\n").unwrap();
@@ -0,0 +1,135 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+
+use serde::Serialize;
+
+use crate::load_balancing::is_uniform;
+use crate::topology::{Diamond, Topology};
+use crate::types::TTL;
+
+/// Characteristics of a diamond, as defined by Augustin et al. in
+/// "Measuring Load-balanced Paths in the Internet" (IMC 2007).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DiamondMetrics {
+    pub divergence: (TTL, IpAddr),
+    pub convergence: Option<(TTL, IpAddr)>,
+    /// Largest number of interfaces at a single TTL between the divergence and convergence points.
+    pub max_width: usize,
+    /// Number of hops of the shortest path from the divergence to the convergence point.
+    pub min_length: u8,
+    /// Number of hops of the longest path from the divergence to the convergence point.
+    pub max_length: u8,
+    /// Difference between the longest and the shortest paths.
+    pub length_asymmetry: u8,
+    /// Whether some hop pair has an interface with several successors
+    /// and an interface with several predecessors.
+    pub meshed: bool,
+    /// Load balancers spreading flows evenly over their successors.
+    pub uniform_load_balancers: usize,
+    /// Load balancers favouring some of their successors.
+    pub non_uniform_load_balancers: usize,
+}
+
+impl DiamondMetrics {
+    pub fn new(topology: &Topology, diamond: &Diamond) -> Self {
+        let (divergence_ttl, divergence_addr) = diamond.divergence;
+
+        // every interface of the diamond by TTL, from the divergence point
+        // to the convergence point, excluded then included
+        let mut hops = diamond.hops.clone();
+        hops.insert(divergence_ttl, BTreeSet::from([divergence_addr]));
+        let (mut uniform_load_balancers, mut non_uniform_load_balancers) = (0, 0);
+        for (ttl, addr) in load_balancers(topology, &hops) {
+            if is_uniform(&topology.flows_by_successor(ttl, addr)) {
+                uniform_load_balancers += 1;
+            } else {
+                non_uniform_load_balancers += 1;
+            }
+        }
+        if let Some((ttl, addr)) = diamond.convergence {
+            hops.insert(ttl, BTreeSet::from([addr]));
+        }
+
+        let last_ttl = *hops.keys().next_back().unwrap();
+        let max_length = last_ttl - divergence_ttl;
+        // a path reaching the convergence point before the others is shorter
+        let min_length = diamond
+            .convergence
+            .and_then(|(_, addr)| {
+                diamond
+                    .hops
+                    .iter()
+                    .find(|(_, addrs)| addrs.contains(&addr))
+                    .map(|(&ttl, _)| ttl - divergence_ttl)
+            })
+            .unwrap_or(max_length);
+
+        Self {
+            divergence: diamond.divergence,
+            convergence: diamond.convergence,
+            max_width: diamond
+                .hops
+                .values()
+                .map(|addrs| addrs.len())
+                .max()
+                .unwrap_or(0),
+            min_length,
+            max_length,
+            length_asymmetry: max_length - min_length,
+            meshed: is_meshed(topology, &hops),
+            uniform_load_balancers,
+            non_uniform_load_balancers,
+        }
+    }
+
+    /// Share of the load balancers of the diamond that spread flows evenly,
+    /// or None if none of them were counted.
+    pub fn uniform_ratio(&self) -> Option<f64> {
+        let total = self.uniform_load_balancers + self.non_uniform_load_balancers;
+        (total > 0).then(|| self.uniform_load_balancers as f64 / total as f64)
+    }
+}
+
+/// The metrics of the outermost diamonds of `topology`, sorted by TTL.
+pub fn diamond_metrics(topology: &Topology) -> Vec<DiamondMetrics> {
+    topology
+        .diamonds()
+        .iter()
+        .map(|diamond| DiamondMetrics::new(topology, diamond))
+        .collect()
+}
+
+// the interfaces of the diamond with several successors
+fn load_balancers<'a>(
+    topology: &'a Topology,
+    hops: &'a BTreeMap<TTL, BTreeSet<IpAddr>>,
+) -> impl Iterator<Item = (TTL, IpAddr)> + 'a {
+    hops.iter()
+        .flat_map(|(&ttl, addrs)| addrs.iter().map(move |&addr| (ttl, addr)))
+        .filter(|&(ttl, addr)| topology.successors(ttl, addr).count() > 1)
+}
+
+fn is_meshed(topology: &Topology, hops: &BTreeMap<TTL, BTreeSet<IpAddr>>) -> bool {
+    hops.iter().any(|(&ttl, addrs)| {
+        let Some(next_addrs) = ttl.checked_add(1).and_then(|ttl| hops.get(&ttl)) else {
+            return false;
+        };
+        let n_successors = |&addr: &IpAddr| {
+            topology
+                .successors(ttl, addr)
+                .filter(|far| next_addrs.contains(far))
+                .count()
+        };
+        let n_predecessors = |&addr: &IpAddr| {
+            topology
+                .predecessors(ttl + 1, addr)
+                .filter(|near| addrs.contains(near))
+                .count()
+        };
+        addrs.iter().any(|addr| n_successors(addr) > 1)
+            && next_addrs.iter().any(|addr| n_predecessors(addr) > 1)
+    })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,105 @@
+use caracat::models::Reply;
+
+use crate::helpers::{addr, reply};
+
+use super::*;
+
+const IP: [&str; 6] = [
+    "192.168.0.2",
+    "192.168.0.3",
+    "192.168.0.4",
+    "192.168.0.5",
+    "192.168.0.6",
+    "192.168.0.7",
+];
+
+// the replies of the `n_flows` flows going through `path`, one per TTL from 1
+fn flows(path: &[&str], first_flow: usize, n_flows: usize) -> Vec<Reply> {
+    (first_flow..first_flow + n_flows)
+        .flat_map(|flow| {
+            let dst_addr = format!("192.170.{}.{}", flow / 256, flow % 256);
+            path.iter()
+                .enumerate()
+                .map(move |(i, ip)| reply(i as u8 + 1, ip, &dst_addr))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn metrics(replies: &[Reply]) -> Vec<DiamondMetrics> {
+    diamond_metrics(&Topology::from_replies(&replies.iter().collect::<Vec<_>>()))
+}
+
+#[test]
+fn test_simple_diamond() {
+    // [ IP[0] ] -+- [ IP[1] ] -+- [ IP[3] ]
+    //            +- [ IP[2] ] -+
+    let replies = [
+        flows(&[IP[0], IP[1], IP[3]], 0, 6),
+        flows(&[IP[0], IP[2], IP[3]], 6, 5),
+    ]
+    .concat();
+    assert_eq!(
+        metrics(&replies),
+        vec![DiamondMetrics {
+            divergence: (1, addr(IP[0])),
+            convergence: Some((3, addr(IP[3]))),
+            max_width: 2,
+            min_length: 2,
+            max_length: 2,
+            length_asymmetry: 0,
+            meshed: false,
+            uniform_load_balancers: 1,
+            non_uniform_load_balancers: 0,
+        }]
+    );
+}
+
+#[test]
+fn test_meshed_diamond() {
+    // [ IP[0] ] -+- [ IP[1] ] -+- [ IP[3] ] -+- [ IP[5] ]
+    //            |             x             |
+    //            +- [ IP[2] ] -+- [ IP[4] ] -+
+    let replies = [
+        flows(&[IP[0], IP[1], IP[3], IP[5]], 0, 10),
+        flows(&[IP[0], IP[1], IP[4], IP[5]], 10, 10),
+        flows(&[IP[0], IP[2], IP[3], IP[5]], 20, 1),
+        flows(&[IP[0], IP[2], IP[4], IP[5]], 21, 1),
+    ]
+    .concat();
+    let metrics = metrics(&replies);
+    assert_eq!(metrics.len(), 1);
+    assert_eq!(metrics[0].convergence, Some((4, addr(IP[5]))));
+    assert_eq!(metrics[0].max_length, 3);
+    assert!(metrics[0].meshed);
+    // IP[0] favours IP[1], while IP[1] and IP[2] split their flows evenly
+    assert_eq!(metrics[0].uniform_load_balancers, 2);
+    assert_eq!(metrics[0].non_uniform_load_balancers, 1);
+    assert!((metrics[0].uniform_ratio().unwrap() - 2.0 / 3.0).abs() < 1e-9);
+
+    // no load balancer was counted
+    let metrics = DiamondMetrics {
+        uniform_load_balancers: 0,
+        non_uniform_load_balancers: 0,
+        ..metrics[0].clone()
+    };
+    assert_eq!(metrics.uniform_ratio(), None);
+}
+
+#[test]
+fn test_asymmetric_diamond() {
+    // [ IP[0] ] -+- [ IP[1] ] -- [ IP[3] ] -- [ IP[4] ]
+    //            +- [ IP[2] ] -- [ IP[4] ] -- [ IP[5] ]
+    let replies = [
+        flows(&[IP[0], IP[1], IP[3], IP[4]], 0, 4),
+        flows(&[IP[0], IP[2], IP[4], IP[5]], 4, 4),
+    ]
+    .concat();
+    let metrics = metrics(&replies);
+    assert_eq!(metrics[0].convergence, Some((4, addr(IP[4]))));
+    assert_eq!(metrics[0].max_width, 2);
+    assert_eq!(metrics[0].min_length, 2);
+    assert_eq!(metrics[0].max_length, 3);
+    assert_eq!(metrics[0].length_asymmetry, 1);
+    assert!(!metrics[0].meshed);
+}
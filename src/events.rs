@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, HashSet};
+use std::net::IpAddr;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use crate::algorithms::Algorithm;
+use crate::types::TTL;
+
+/// What happens during a trace, sent as it happens on the channel given to
+/// [`Tracer::with_events`](crate::tracer::Tracer::with_events).
+/// In a campaign, the round events are shared by all the destinations.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceEvent {
+    /// The probes of a round are handed to the prober.
+    RoundStarted {
+        round: u32,
+        probes: usize,
+        expected_time: Duration,
+    },
+    /// The probes of the round are on the wire, and the replies are awaited.
+    /// Only sent by the caracat backend.
+    ProbesSent { probes: usize },
+    RepliesReceived {
+        round: u32,
+        replies: usize,
+        late_replies: usize,
+    },
+    /// An interface answered at `ttl` for the first time.
+    NodeFound {
+        dst_addr: IpAddr,
+        ttl: TTL,
+        addr: IpAddr,
+    },
+    /// Enough flows went through the node to find all its successors with the requested confidence,
+    /// or the trace is done.
+    NodeResolved {
+        dst_addr: IpAddr,
+        ttl: TTL,
+        addr: IpAddr,
+    },
+    /// The destination answered, at `ttl` at the earliest.
+    DestinationReached { dst_addr: IpAddr, ttl: TTL },
+    /// The replies of the round were processed and the probes of the next one chosen.
+    RoundFinished {
+        round: u32,
+        links: usize,
+        nodes: usize,
+        next_probes: usize,
+    },
+}
+
+/// Sends the events of a trace, and remembers the ones already sent for each destination.
+pub(crate) struct EventSender {
+    sender: Sender<TraceEvent>,
+    found: HashSet<(IpAddr, TTL, IpAddr)>,
+    resolved: HashSet<(IpAddr, TTL, IpAddr)>,
+    reached: HashSet<IpAddr>,
+}
+
+impl EventSender {
+    pub(crate) fn new(sender: Sender<TraceEvent>) -> Self {
+        Self {
+            sender,
+            found: HashSet::new(),
+            resolved: HashSet::new(),
+            reached: HashSet::new(),
+        }
+    }
+
+    // the trace goes on when nobody listens anymore
+    pub(crate) fn send(&self, event: TraceEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub(crate) fn sender(&self) -> Sender<TraceEvent> {
+        self.sender.clone()
+    }
+
+    /// Sends the nodes found and resolved by `alg` since the last call, and whether it reached its destination.
+    /// Once `alg` is `done`, i.e. has no more probes to send, its nodes are all resolved.
+    pub(crate) fn update(&mut self, alg: &dyn Algorithm, done: bool) {
+        let dst_addr = alg.dst_addr();
+        let topology = alg.topology();
+
+        let mut nodes: Vec<(TTL, IpAddr)> = topology
+            .nodes()
+            .flat_map(|(addr, node)| node.ttls.iter().map(move |&ttl| (ttl, addr)))
+            .collect();
+        nodes.sort();
+        for &(ttl, addr) in &nodes {
+            if self.found.insert((dst_addr, ttl, addr)) {
+                self.send(TraceEvent::NodeFound {
+                    dst_addr,
+                    ttl,
+                    addr,
+                });
+            }
+        }
+
+        // only the nodes the plan lists as resolved, since the plans of some TTLs do not look at
+        // the nodes found, e.g. the non-meshed TTLs of MDA-Lite; None if every node is resolved
+        let resolved: Option<HashSet<(TTL, IpAddr)>> = match alg.round_plan() {
+            // the plan of the last round allowed is empty, and does not tell which nodes are resolved
+            Some(plan) if plan.ttls.is_empty() => Some(HashSet::new()),
+            Some(plan) if !done => Some(
+                plan.ttls
+                    .iter()
+                    .flat_map(|ttl| ttl.resolved_nodes.iter().map(|&addr| (ttl.ttl, addr)))
+                    .collect(),
+            ),
+            // without a plan, e.g. for Paris traceroute, a node is done as soon as it is found
+            _ => None,
+        };
+        for (ttl, addr) in nodes {
+            if resolved
+                .as_ref()
+                .is_none_or(|resolved| resolved.contains(&(ttl, addr)))
+                && self.resolved.insert((dst_addr, ttl, addr))
+            {
+                self.send(TraceEvent::NodeResolved {
+                    dst_addr,
+                    ttl,
+                    addr,
+                });
+            }
+        }
+
+        if !self.reached.contains(&dst_addr) {
+            let ttl = alg
+                .replies()
+                .into_iter()
+                .filter(|reply| reply.reply_src_addr == dst_addr)
+                .map(|reply| reply.probe_ttl)
+                .min();
+            if let Some(ttl) = ttl {
+                self.reached.insert(dst_addr);
+                self.send(TraceEvent::DestinationReached { dst_addr, ttl });
+            }
+        }
+    }
+}
+
+/// An interface in the [`TraceProgress`] of a destination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HopNode {
+    pub addr: IpAddr,
+    pub resolved: bool,
+}
+
+/// The state of the trace towards a destination, as told by its events.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DestinationProgress {
+    /// Interfaces by TTL, in the order they were found. The number of interfaces at a TTL
+    /// is the width of the diamond that spans it.
+    pub hops: BTreeMap<TTL, Vec<HopNode>>,
+    pub reached_at: Option<TTL>,
+}
+
+impl DestinationProgress {
+    pub fn max_width(&self) -> usize {
+        self.hops.values().map(Vec::len).max().unwrap_or(0)
+    }
+}
+
+/// The state of a trace rebuilt from its events, e.g. to display it while it runs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceProgress {
+    pub round: u32,
+    /// Whether the probes of `round` are still waiting for their replies.
+    pub probing: bool,
+    pub probes: usize,
+    pub replies: usize,
+    pub links: usize,
+    pub nodes: usize,
+    pub next_probes: usize,
+    pub destinations: BTreeMap<IpAddr, DestinationProgress>,
+}
+
+impl TraceProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&mut self, event: &TraceEvent) {
+        match *event {
+            TraceEvent::RoundStarted { round, probes, .. } => {
+                self.round = round;
+                self.probing = true;
+                self.probes += probes;
+            }
+            TraceEvent::ProbesSent { .. } => {}
+            TraceEvent::RepliesReceived {
+                replies,
+                late_replies,
+                ..
+            } => {
+                self.probing = false;
+                self.replies += replies + late_replies;
+            }
+            TraceEvent::NodeFound {
+                dst_addr,
+                ttl,
+                addr,
+            } => {
+                let hop = self
+                    .destinations
+                    .entry(dst_addr)
+                    .or_default()
+                    .hops
+                    .entry(ttl)
+                    .or_default();
+                if !hop.iter().any(|node| node.addr == addr) {
+                    hop.push(HopNode {
+                        addr,
+                        resolved: false,
+                    });
+                }
+            }
+            TraceEvent::NodeResolved {
+                dst_addr,
+                ttl,
+                addr,
+            } => {
+                let hop = self
+                    .destinations
+                    .entry(dst_addr)
+                    .or_default()
+                    .hops
+                    .entry(ttl)
+                    .or_default();
+                match hop.iter_mut().find(|node| node.addr == addr) {
+                    Some(node) => node.resolved = true,
+                    None => hop.push(HopNode {
+                        addr,
+                        resolved: true,
+                    }),
+                }
+            }
+            TraceEvent::DestinationReached { dst_addr, ttl } => {
+                self.destinations.entry(dst_addr).or_default().reached_at = Some(ttl);
+            }
+            TraceEvent::RoundFinished {
+                links,
+                nodes,
+                next_probes,
+                ..
+            } => {
+                self.links = links;
+                self.nodes = nodes;
+                self.next_probes = next_probes;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
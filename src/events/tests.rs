@@ -0,0 +1,112 @@
+use std::sync::mpsc::channel;
+
+use itertools::Itertools;
+
+use crate::helpers::{addr, prober, DEST, IP};
+use crate::tracer::{AlgorithmKind, Tracer};
+
+use super::*;
+
+fn trace(algorithm: AlgorithmKind) -> Vec<TraceEvent> {
+    let (sender, receiver) = channel();
+    let result = Tracer::new(addr(DEST))
+        .with_ttl_range(1, 10)
+        .with_confidence(95.0)
+        .with_algorithm(algorithm)
+        .with_events(sender)
+        .trace_with(&mut prober())
+        .unwrap();
+    let events: Vec<TraceEvent> = receiver.iter().collect();
+    let rounds = events
+        .iter()
+        .filter(|event| matches!(event, TraceEvent::RoundFinished { .. }))
+        .count();
+    assert_eq!(rounds as u32, result.statistics.rounds);
+    events
+}
+
+#[test]
+fn test_events() {
+    let events = trace(AlgorithmKind::DiamondMiner);
+
+    assert!(matches!(
+        events[0],
+        TraceEvent::RoundStarted { round: 1, .. }
+    ));
+    assert!(matches!(
+        events[1],
+        TraceEvent::RepliesReceived { round: 1, .. }
+    ));
+    assert!(matches!(
+        events.last().unwrap(),
+        TraceEvent::RoundFinished {
+            links: 4,
+            nodes: 4,
+            next_probes: 0,
+            ..
+        }
+    ));
+    let found = |addr| TraceEvent::NodeFound {
+        dst_addr: DEST.parse().unwrap(),
+        ttl: 2,
+        addr,
+    };
+    let resolved = |addr| TraceEvent::NodeResolved {
+        dst_addr: DEST.parse().unwrap(),
+        ttl: 2,
+        addr,
+    };
+    let position = |event: TraceEvent| events.iter().position(|e| *e == event).unwrap();
+    assert!(position(found(addr(IP[1]))) < position(resolved(addr(IP[1]))));
+
+    let mut progress = TraceProgress::new();
+    for event in &events {
+        progress.apply(event);
+    }
+    let destination = &progress.destinations[&addr(DEST)];
+    let widths: Vec<usize> = destination.hops.values().map(Vec::len).collect();
+    assert_eq!(widths, vec![1, 2, 1]);
+    assert_eq!(destination.max_width(), 2);
+    assert_eq!(destination.reached_at, Some(4));
+    assert!(destination
+        .hops
+        .values()
+        .flatten()
+        .all(|node| node.resolved));
+    assert_eq!(progress.links, 4);
+    assert!(!progress.probing);
+}
+
+#[test]
+fn test_events_without_plan() {
+    let events = trace(AlgorithmKind::ParisTraceroute);
+    let mut progress = TraceProgress::new();
+    for event in &events {
+        progress.apply(event);
+    }
+    let destination = &progress.destinations[&addr(DEST)];
+    let widths: Vec<usize> = destination.hops.values().map(Vec::len).collect();
+    assert_eq!(widths, vec![1, 1, 1]);
+    assert!(destination
+        .hops
+        .values()
+        .flatten()
+        .all(|node| node.resolved));
+}
+
+#[test]
+fn test_events_mda_lite() {
+    // the diamond is not meshed: MDA-Lite plans its TTLs without looking at the nodes found,
+    // so they are only resolved once the trace is done
+    let events = trace(AlgorithmKind::MdaLite);
+    let last_replies = events
+        .iter()
+        .rposition(|event| matches!(event, TraceEvent::RepliesReceived { .. }))
+        .unwrap();
+    let resolved: Vec<usize> = events
+        .iter()
+        .positions(|event| matches!(event, TraceEvent::NodeResolved { .. }))
+        .collect();
+    assert_eq!(resolved.len(), 4);
+    assert!(resolved.iter().all(|&position| position > last_replies));
+}
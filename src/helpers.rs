@@ -1,9 +1,65 @@
+use std::collections::HashSet;
 use std::net::{IpAddr, Ipv4Addr};
 
-use caracat::models::Reply;
+use caracat::models::{Reply, L4};
 use pnet::packet::{icmp, ip::IpNextHeaderProtocols};
 
-use crate::types::ReplyPair;
+use crate::algorithms::diamond_miner::DiamondMiner;
+use crate::probe::Prober;
+use crate::simulator::{Network, SimulatedProber};
+use crate::types::{ReplyPair, TTL};
+
+// the vantage point, destination and interfaces of the diamond simulated by `prober`
+pub const SRC: &str = "10.0.0.1";
+pub const DEST: &str = "192.170.0.2";
+pub const IP: [&str; 4] = ["192.168.0.2", "192.168.0.3", "192.168.0.4", "192.168.0.5"];
+
+pub fn addr(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+// a simulated network seen from `SRC`
+pub fn network(links: &[(&str, &str)]) -> Network {
+    let links: Vec<(IpAddr, IpAddr)> = links
+        .iter()
+        .map(|&(near, far)| (addr(near), addr(far)))
+        .collect();
+    Network::from_links(addr(SRC), &links)
+}
+
+// [ IP[0] ] -+- [ IP[1] ] -+- [ IP[3] ]
+//            +- [ IP[2] ] -+
+pub fn prober() -> SimulatedProber {
+    SimulatedProber::new(network(&[
+        (SRC, IP[0]),
+        (IP[0], IP[1]),
+        (IP[0], IP[2]),
+        (IP[1], IP[3]),
+        (IP[2], IP[3]),
+    ]))
+}
+
+pub fn miner(confidence: f64) -> DiamondMiner {
+    DiamondMiner::new(addr(DEST), 1, 10, 24000, 33434, L4::ICMP, confidence, 10)
+}
+
+// runs `miner` to the end on the probes sent by `prober`
+pub fn run<P: Prober>(prober: &mut P, mut miner: DiamondMiner) -> DiamondMiner {
+    let mut probes = miner.next_round(vec![], false);
+    while !probes.is_empty() {
+        let replies = prober.probe(probes).unwrap();
+        probes = miner.next_round(replies, false);
+    }
+    miner
+}
+
+pub fn links(miner: &DiamondMiner) -> HashSet<(TTL, IpAddr, IpAddr)> {
+    miner
+        .topology()
+        .links()
+        .map(|(ttl, near, far, _)| (ttl, near, far))
+        .collect()
+}
 
 pub fn reply(probe_ttl: u8, reply_src_addr: &str, probe_dst_addr: &str) -> Reply {
     Reply {
@@ -2,14 +2,46 @@ pub mod algorithms;
 
 pub mod links;
 
+pub mod load_balancing;
+
+pub mod topology;
+
+pub mod diamonds;
+
 pub mod types;
 
 pub mod receiver;
 
 pub mod probe;
 
+pub mod async_trace;
+
 pub mod pantrace_builder;
 
-pub mod helpers;
+pub mod mpls;
+
+pub mod alias;
+
+pub mod asn;
+
+#[cfg(test)]
+pub(crate) mod helpers;
 
 pub mod classic_traceroute;
+
+pub mod simulator;
+
+pub mod campaign;
+
+pub mod checkpoint;
+
+pub mod replay;
+
+pub mod reply_dump;
+
+pub mod tracer;
+
+pub mod events;
+
+#[cfg(feature = "tui")]
+pub mod tui;
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use caracat::models::Reply;
-use itertools::iproduct;
+use itertools::{iproduct, Itertools};
 
 use crate::types::{Flow, Link, ReplyPair, TTL};
 
@@ -50,7 +50,14 @@ fn get_pairs_by_flow<'a>(replies: &[&'a Reply]) -> HashMap<Flow, Vec<ReplyPair<'
             let fetch_replies = |ttl| {
                 ttl_replies
                     .get(&ttl)
-                    .map(|replies| replies.iter().map(|&reply| Some(reply)).collect::<Vec<_>>())
+                    .map(|replies| {
+                        // a flow probed twice at the same TTL yields a single link per interface
+                        replies
+                            .iter()
+                            .unique_by(|reply| reply.reply_src_addr)
+                            .map(|&reply| Some(reply))
+                            .collect::<Vec<_>>()
+                    })
                     .unwrap_or(vec![None])
             };
             // we fetch replies that match the ttl (and the flow)
@@ -0,0 +1,132 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::IpAddr;
+
+use caracat::models::Reply;
+
+use crate::links::{get_replies_by_flow, get_replies_by_ttl};
+use crate::types::{LoadBalancing, TTL};
+
+/// What was observed downstream of a node, over the flows that went through it.
+#[derive(Default)]
+struct Observations {
+    successors: HashSet<IpAddr>,
+    // a flow got replies from several successors
+    unstable: bool,
+    // number of flows and successors reached, by destination address
+    by_dst_addr: HashMap<IpAddr, (usize, HashSet<IpAddr>)>,
+}
+
+// for each (ttl, node), what the flows that went through the node reached at ttl + 1
+fn observations(replies: &[&Reply]) -> HashMap<(TTL, IpAddr), Observations> {
+    let mut observations: HashMap<(TTL, IpAddr), Observations> = HashMap::new();
+    for (flow, flow_replies) in get_replies_by_flow(replies) {
+        let addrs_by_ttl: HashMap<TTL, HashSet<IpAddr>> = get_replies_by_ttl(&flow_replies)
+            .into_iter()
+            .map(|(ttl, replies)| (ttl, replies.iter().map(|r| r.reply_src_addr).collect()))
+            .collect();
+        for (&ttl, near_addrs) in &addrs_by_ttl {
+            // the flow must have gone through a single node to attribute its successors
+            let (Some(&near_addr), 1) = (near_addrs.iter().next(), near_addrs.len()) else {
+                continue;
+            };
+            let Some(far_addrs) = addrs_by_ttl.get(&(ttl + 1)) else {
+                continue;
+            };
+            let node = observations.entry((ttl, near_addr)).or_default();
+            node.successors.extend(far_addrs);
+            node.unstable |= far_addrs.len() > 1;
+            let (n_flows, successors) = node.by_dst_addr.entry(flow.dst_address).or_default();
+            *n_flows += 1;
+            successors.extend(far_addrs);
+        }
+    }
+    observations
+}
+
+/// Failure probability of the default 95% confidence, for the algorithms that have none.
+pub const DEFAULT_FAILURE_PROBABILITY: f64 = 0.05;
+
+/// Classifies the nodes with several successors, keyed by (TTL, address).
+///
+/// A node is per-packet if a single flow reached several of its successors,
+/// per-flow if flows towards the same destination address reached different successors,
+/// and per-destination if flows towards the same destination address always reached the same one.
+/// A per-flow load balancer with `k` successors sends the `n` flows of a destination to the same
+/// one with probability `(1/k)^(n-1)`: per-destination is only concluded when this probability,
+/// over all the destinations, is below `failure_probability`. Otherwise per-flow is assumed.
+pub fn classify_load_balancers(
+    replies: &[&Reply],
+    failure_probability: f64,
+) -> HashMap<(TTL, IpAddr), LoadBalancing> {
+    observations(replies)
+        .into_iter()
+        .filter(|(_, node)| node.successors.len() > 1)
+        .map(|(key, node)| {
+            // flows that followed the first flow of their destination
+            let n_followers: usize = node
+                .by_dst_addr
+                .values()
+                .map(|&(n_flows, _)| n_flows - 1)
+                .sum();
+            let same_successor_probability =
+                (1.0 / node.successors.len() as f64).powi(n_followers as i32);
+            let load_balancing = if node.unstable {
+                LoadBalancing::PerPacket
+            } else if node
+                .by_dst_addr
+                .values()
+                .any(|(_, successors)| successors.len() > 1)
+            {
+                LoadBalancing::PerFlow
+            } else if same_successor_probability < failure_probability {
+                LoadBalancing::PerDestination
+            } else {
+                LoadBalancing::PerFlow
+            };
+            (key, load_balancing)
+        })
+        .collect()
+}
+
+/// TTLs at which a single flow got replies from different interfaces.
+pub fn unstable_ttls(replies: &[&Reply]) -> BTreeSet<TTL> {
+    let mut ttls = BTreeSet::new();
+    for (_, flow_replies) in get_replies_by_flow(replies) {
+        for (ttl, replies) in get_replies_by_ttl(&flow_replies) {
+            let addrs: HashSet<IpAddr> = replies.iter().map(|r| r.reply_src_addr).collect();
+            if addrs.len() > 1 {
+                ttls.insert(ttl);
+            }
+        }
+    }
+    ttls
+}
+
+/// Whether a load balancer spreads flows evenly over its successors, given the number of flows
+/// that reached each of them. A chi-squared goodness of fit test against the uniform
+/// distribution is used, with a 5% significance level.
+pub fn is_uniform(flows_by_successor: &[usize]) -> bool {
+    let n_flows: usize = flows_by_successor.iter().sum();
+    if flows_by_successor.len() < 2 || n_flows == 0 {
+        return true;
+    }
+    let expected = n_flows as f64 / flows_by_successor.len() as f64;
+    let statistic: f64 = flows_by_successor
+        .iter()
+        .map(|&n| (n as f64 - expected).powi(2) / expected)
+        .sum();
+    statistic <= chi_squared_critical_value(flows_by_successor.len() - 1)
+}
+
+// upper 5% point of the chi-squared distribution with `degrees` degrees of freedom,
+// from the Wilson-Hilferty approximation
+fn chi_squared_critical_value(degrees: usize) -> f64 {
+    // upper 5% point of the standard normal distribution
+    const Z: f64 = 1.6449;
+    let k = degrees as f64;
+    let a = 2.0 / (9.0 * k);
+    k * (1.0 - a + Z * a.sqrt()).powi(3)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,119 @@
+use caracat::models::Reply;
+
+use crate::helpers::reply;
+
+use super::*;
+
+const IP: [&str; 4] = ["192.168.0.2", "192.168.0.3", "192.168.0.4", "192.168.0.5"];
+const DEST: [&str; 2] = ["192.170.0.2", "192.170.0.4"];
+
+// the replies of a flow from `near` at TTL 1 and `far` at TTL 2
+fn flow(dst: &str, src_port: u16, near: &str, far: &str) -> Vec<Reply> {
+    [reply(1, near, dst), reply(2, far, dst)]
+        .into_iter()
+        .map(|r| Reply {
+            probe_src_port: src_port,
+            ..r
+        })
+        .collect()
+}
+
+fn classify(replies: &[Reply]) -> HashMap<(TTL, IpAddr), LoadBalancing> {
+    classify_load_balancers(
+        &replies.iter().collect::<Vec<_>>(),
+        DEFAULT_FAILURE_PROBABILITY,
+    )
+}
+
+fn balancer() -> (TTL, IpAddr) {
+    (1, IP[0].parse().unwrap())
+}
+
+#[test]
+fn test_per_flow() {
+    let replies = [
+        flow(DEST[0], 24000, IP[0], IP[1]),
+        flow(DEST[1], 24000, IP[0], IP[2]),
+        flow(DEST[0], 24001, IP[0], IP[2]),
+    ]
+    .concat();
+    assert_eq!(
+        classify(&replies),
+        HashMap::from([(balancer(), LoadBalancing::PerFlow)])
+    );
+}
+
+#[test]
+fn test_per_packet() {
+    let replies = [
+        flow(DEST[0], 24000, IP[0], IP[1]),
+        flow(DEST[1], 24000, IP[0], IP[2]),
+        // the first flow, probed again, takes the other branch
+        vec![Reply {
+            probe_src_port: 24000,
+            ..reply(2, IP[2], DEST[0])
+        }],
+    ]
+    .concat();
+    assert_eq!(
+        classify(&replies),
+        HashMap::from([(balancer(), LoadBalancing::PerPacket)])
+    );
+
+    let replies: Vec<&Reply> = replies.iter().collect();
+    assert_eq!(unstable_ttls(&replies), BTreeSet::from([2]));
+}
+
+#[test]
+fn test_per_destination() {
+    // four flows per destination: a per-flow load balancer would keep all of them
+    // on the same successor with probability (1/2)^3 for each destination
+    let replies: Vec<Reply> = (24000..24004)
+        .flat_map(|port| {
+            [
+                flow(DEST[0], port, IP[0], IP[1]),
+                flow(DEST[1], port, IP[0], IP[2]),
+            ]
+        })
+        .flatten()
+        .collect();
+    assert_eq!(
+        classify(&replies),
+        HashMap::from([(balancer(), LoadBalancing::PerDestination)])
+    );
+
+    // two flows per destination are not enough to rule out per-flow load balancing
+    let replies = [
+        flow(DEST[0], 24000, IP[0], IP[1]),
+        flow(DEST[1], 24000, IP[0], IP[2]),
+        flow(DEST[0], 24001, IP[0], IP[1]),
+        flow(DEST[1], 24001, IP[0], IP[2]),
+    ]
+    .concat();
+    assert_eq!(
+        classify(&replies),
+        HashMap::from([(balancer(), LoadBalancing::PerFlow)])
+    );
+}
+
+#[test]
+fn test_single_successor() {
+    let replies = [
+        flow(DEST[0], 24000, IP[0], IP[1]),
+        flow(DEST[1], 24000, IP[0], IP[1]),
+    ]
+    .concat();
+    assert!(classify(&replies).is_empty());
+}
+
+#[test]
+fn test_is_uniform() {
+    assert!(is_uniform(&[]));
+    assert!(is_uniform(&[12]));
+    assert!(is_uniform(&[10, 12]));
+    assert!(is_uniform(&[8, 10, 9, 13]));
+    assert!(!is_uniform(&[30, 6]));
+    assert!(!is_uniform(&[20, 20, 2]));
+    // 5.99 is the exact critical value with two degrees of freedom
+    assert!((chi_squared_critical_value(2) - 5.99).abs() < 0.1);
+}
@@ -1,26 +1,37 @@
 use std::collections::{HashMap, HashSet};
-use std::net::{IpAddr, Ipv4Addr};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "tui")]
+use std::sync::mpsc;
+#[cfg(feature = "tui")]
+use std::thread;
 use std::time::Duration;
 use std::{fmt, vec};
 
-use caracat::high_level::Config;
-
-use chrono::Utc;
-use itertools::Itertools;
 use log::{debug, info};
-use netdev::get_default_interface;
 use pantrace::formats::atlas::AtlasWriter;
-use pantrace::formats::internal::{Protocol, Traceroute};
 use pantrace::traits::TracerouteWriter;
-use voyage::algorithms::diamond_miner::DiamondMiner;
+use serde_json::Value;
+use voyage::algorithms::diamond_miner::{
+    FlowMapper, IntervalFlowMapper, RandomFlowMapper, ReverseByteFlowMapper, SequentialFlowMapper,
+    DEFAULT_PREFIX_SIZE_V4, DEFAULT_PREFIX_SIZE_V6,
+};
+use voyage::algorithms::utils::StoppingRule;
+use voyage::alias::DEFAULT_ALIAS_ROUNDS;
+use voyage::asn::{AsnAnnotations, AsnTable};
+use voyage::campaign::parse_targets;
 use voyage::classic_traceroute::ClassicTracerouteWriter;
-use voyage::pantrace_builder::replies_to_pantrace_flows;
+use voyage::diamonds::diamond_metrics;
+use voyage::pantrace_builder::TracerouteMetadata;
+use voyage::tracer::{AlgorithmKind, Backend, TraceResult, Tracer};
 
-use anyhow::Result;
-use voyage::probe::probe;
-use voyage::types::{Link, TTL};
+use anyhow::{Context, Result};
+use voyage::reply_dump::{ReplyDump, ReplyDumpFormat};
+use voyage::types::TTL;
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 enum OutputFormat {
@@ -30,6 +41,7 @@ enum OutputFormat {
     Internal,
     Traceroute,
     Scamper,
+    Links,
     Quiet,
 }
 
@@ -42,6 +54,7 @@ impl fmt::Display for OutputFormat {
             OutputFormat::Internal => write!(f, "internal"),
             OutputFormat::Traceroute => write!(f, "traceroute"),
             OutputFormat::Scamper => write!(f, "scamper"),
+            OutputFormat::Links => write!(f, "links"),
             OutputFormat::Quiet => write!(f, "quiet"),
         }
     }
@@ -62,12 +75,114 @@ impl From<ProtocolArg> for caracat::models::L4 {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+enum AlgorithmArg {
+    DiamondMiner,
+    MdaLite,
+    ParisTraceroute,
+}
+
+impl From<AlgorithmArg> for AlgorithmKind {
+    fn from(algorithm: AlgorithmArg) -> Self {
+        match algorithm {
+            AlgorithmArg::DiamondMiner => AlgorithmKind::DiamondMiner,
+            AlgorithmArg::MdaLite => AlgorithmKind::MdaLite,
+            AlgorithmArg::ParisTraceroute => AlgorithmKind::ParisTraceroute,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+enum StoppingRuleArg {
+    Uniform,
+    NonUniform,
+}
+
+impl From<StoppingRuleArg> for StoppingRule {
+    fn from(stopping_rule: StoppingRuleArg) -> Self {
+        match stopping_rule {
+            StoppingRuleArg::Uniform => StoppingRule::Uniform,
+            StoppingRuleArg::NonUniform => StoppingRule::NonUniform,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+enum DumpFormatArg {
+    Csv,
+    Jsonl,
+}
+
+impl From<DumpFormatArg> for ReplyDumpFormat {
+    fn from(format: DumpFormatArg) -> Self {
+        match format {
+            DumpFormatArg::Csv => ReplyDumpFormat::Csv,
+            DumpFormatArg::Jsonl => ReplyDumpFormat::JsonLines,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+enum FlowMapperArg {
+    Sequential,
+    Random,
+    Interval,
+    ReverseByte,
+}
+
+impl FlowMapperArg {
+    fn build(&self, prefix_size: u128, seed: u64, step: u128) -> Box<dyn FlowMapper> {
+        match self {
+            FlowMapperArg::Sequential => Box::new(SequentialFlowMapper::new(prefix_size)),
+            FlowMapperArg::Random => Box::new(RandomFlowMapper::new(seed, prefix_size)),
+            FlowMapperArg::Interval => Box::new(IntervalFlowMapper::new(prefix_size, step)),
+            FlowMapperArg::ReverseByte => Box::new(ReverseByteFlowMapper::new(prefix_size)),
+        }
+    }
+}
+
+// the step of the interval flow mapper must divide both the IPv4 and the IPv6 prefix sizes
+fn parse_flow_mapper_step(step: &str) -> Result<u128, String> {
+    let step: u128 = step.parse().map_err(|err| format!("{}", err))?;
+    if step == 0
+        || !DEFAULT_PREFIX_SIZE_V4.is_multiple_of(step)
+        || !DEFAULT_PREFIX_SIZE_V6.is_multiple_of(step)
+    {
+        return Err(format!(
+            "must divide the prefix sizes {} and {}",
+            DEFAULT_PREFIX_SIZE_V4, DEFAULT_PREFIX_SIZE_V6
+        ));
+    }
+    Ok(step)
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the algorithm again on the replies of a previous measurement, without sending probes
+    Replay {
+        /// caracat CSV file or pcap/pcapng capture with the replies of the measurement
+        capture: PathBuf,
+    },
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Destination IP address
-    #[arg(short, long)]
-    dst_addr: String,
+    #[arg(
+        short,
+        long,
+        required_unless_present = "targets_file",
+        conflicts_with = "targets_file"
+    )]
+    dst_addr: Option<String>,
+
+    /// File with one destination address or prefix per line, traced together in a single campaign
+    #[arg(long)]
+    targets_file: Option<PathBuf>,
 
     /// Minimum TTL
     #[arg(long, default_value_t = 1)]
@@ -93,6 +208,39 @@ struct Args {
     #[arg(short, long, default_value_t = 100)]
     max_round: u32,
 
+    /// Multipath detection algorithm
+    #[arg(short, long, value_enum, default_value_t = AlgorithmArg::DiamondMiner)]
+    algorithm: AlgorithmArg,
+
+    /// Number of probes per TTL of the Paris traceroute algorithm
+    #[arg(long, default_value_t = 3)]
+    probes_per_hop: usize,
+
+    /// Consecutive silent hops after which the Paris traceroute algorithm stops
+    #[arg(long, default_value_t = 5)]
+    max_silent_hops: u8,
+
+    /// How flow identifiers are mapped to destination addresses and source ports
+    #[arg(long, value_enum, default_value_t = FlowMapperArg::Sequential)]
+    flow_mapper: FlowMapperArg,
+
+    /// Seed of the random flow mapper
+    #[arg(long, default_value_t = 0)]
+    flow_mapper_seed: u64,
+
+    /// Distance between two consecutive addresses of the interval flow mapper, a power of two up to 256
+    #[arg(long, default_value_t = 32, value_parser = parse_flow_mapper_step)]
+    flow_mapper_step: u128,
+
+    /// Number of flows re-probed behind each load balancer to classify it (0 to disable)
+    #[arg(long, default_value_t = 3)]
+    reprobes: usize,
+
+    /// How many probes are needed to find all the successors of a node: assuming traffic is
+    /// split evenly, or as observed so far for unequal-cost load balancers
+    #[arg(long, value_enum, default_value_t = StoppingRuleArg::Uniform)]
+    stopping_rule: StoppingRuleArg,
+
     /// Estimate successors
     #[arg(short, long, default_value_t = false)]
     estimate_successors: bool,
@@ -101,7 +249,52 @@ struct Args {
     #[arg(short, long, value_enum, default_value_t = OutputFormat::Traceroute)]
     output_format: OutputFormat,
 
-    /// Receiver wait time in seconds
+    /// Write the state of the trace to this file after every round
+    #[arg(long, conflicts_with = "targets_file")]
+    checkpoint: Option<PathBuf>,
+
+    /// Resume the trace from a checkpoint, with the same options as the interrupted trace
+    #[arg(long, conflicts_with = "targets_file")]
+    resume: Option<PathBuf>,
+
+    /// Write the metrics of the diamonds found to this file, as one JSON object per destination
+    #[arg(long)]
+    diamonds_summary: Option<PathBuf>,
+
+    /// Write how the probes of every round were chosen to this file, as one JSON object per round
+    #[arg(long)]
+    round_plans: Option<PathBuf>,
+
+    /// Write the MPLS tunnels crossed by the traces to this file, as one JSON object per destination
+    #[arg(long)]
+    tunnels: Option<PathBuf>,
+
+    /// Send probes at the end of the trace to reveal the hidden hops of opaque and invisible MPLS tunnels
+    #[arg(long, default_value_t = false)]
+    reveal_tunnels: bool,
+
+    /// Resolve the aliases of the interfaces found, and write them with the router-level graph to this file,
+    /// as one JSON object per destination
+    #[arg(long)]
+    aliases: Option<PathBuf>,
+
+    /// Rounds of echo probes sent to the interfaces whose aliases cannot be told from the replies of the trace
+    #[arg(long, default_value_t = DEFAULT_ALIAS_ROUNDS)]
+    alias_rounds: usize,
+
+    /// Annotate the hops with their origin AS and prefix, from a pyasn, CAIDA prefix2as or `bgpdump -m` RIB file
+    #[arg(long)]
+    asn_table: Option<PathBuf>,
+
+    /// Write every reply received to this file during the trace, with the round of its probe
+    #[arg(long)]
+    dump_replies: Option<PathBuf>,
+
+    /// Format of the reply dump, with the columns of caracat's CSV output
+    #[arg(long, value_enum, default_value_t = DumpFormatArg::Csv)]
+    dump_format: DumpFormatArg,
+
+    /// Receiver wait time in seconds: how long to wait for the reply to each probe
     #[arg(long, default_value_t = 1)]
     receiver_wait_time: u64,
 
@@ -120,142 +313,218 @@ struct Args {
     /// Instance ID
     #[arg(long)]
     id: Option<u16>,
-}
-
-fn main() -> Result<()> {
-    env_logger::init();
-    // let dst_addr_str = "12.12.12.12";
-    // let dst_addr_str = "103.37.83.226";
-    // let dst_addr_str = "104.18.32.7";
-    // let dst_addr_str = "157.240.221.35";
-    // let dst_addr_str = "8.8.8.8";
-    // let dst_addr_str = "1.1.1.1";
-    let args = Args::parse();
 
-    let dst_addr = IpAddr::from(args.dst_addr.parse::<Ipv4Addr>()?);
-    let min_ttl = args.min_ttl;
-    let max_ttl = args.max_ttl;
-    let src_port = args.src_port;
-    let dst_port = args.dst_port;
-    let protocol = args.protocol.into();
-    let confidence = args.confidence;
-    let max_round = args.max_round;
-    let estimate_successsors = args.estimate_successors;
-
-    let mut alg = DiamondMiner::new(
-        dst_addr, min_ttl, max_ttl, src_port, dst_port, protocol, confidence, max_round,
-    );
+    /// Measurement name written in the traceroutes
+    #[arg(long, default_value = "diamond_miner")]
+    measurement_name: String,
 
-    let mut round = 0;
+    /// Measurement ID written in the traceroutes
+    #[arg(long, default_value = "0")]
+    measurement_id: String,
 
-    let mut probes = alg.next_round(vec![], estimate_successsors);
-    info!(
-        "round={} links_found={} total_ip={} probes={} expected_time={:.1}s",
-        round,
-        0,
-        0,
-        probes.len(),
-        probes.len() as f64 / (args.probing_rate as f64),
-    );
+    /// Agent ID written in the traceroutes, to tell vantage points apart
+    #[arg(long, default_value = "0")]
+    agent_id: String,
 
-    let start_time = Utc::now();
+    /// Show a live dashboard of the interfaces found at each TTL during the trace
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    tui: bool,
+}
 
-    while !probes.is_empty() {
-        let config = Config {
-            receiver_wait_time: Duration::from_secs(args.receiver_wait_time),
-            probing_rate: args.probing_rate,
-            interface: args
-                .interface
-                .clone()
-                .unwrap_or_else(|| get_default_interface().unwrap().name),
+/// Builds the tracer configured on the command line.
+fn tracer(args: &Args) -> Result<Tracer> {
+    let tracer = match &args.targets_file {
+        Some(targets_file) => {
+            let targets = parse_targets(fs::read_to_string(targets_file)?.lines())?;
+            info!("tracing {} targets from {:?}", targets.len(), targets_file);
+            Tracer::campaign(targets)
+        }
+        None => Tracer::new(args.dst_addr.as_deref().unwrap().parse()?),
+    };
+    let backend = match &args.command {
+        Some(Command::Replay { capture }) => Backend::Replay(capture.clone()),
+        None => Backend::Caracat {
+            interface: args.interface.clone(),
             instance_id: args.id.unwrap_or(0),
-            ..Config::default()
-        };
-        round += 1;
-        let replies = probe(config, probes.into_iter())?;
-        debug!(
-            "received {} replies including {} time exceeded replies",
-            replies.len(),
-            replies.iter().filter(|r| r.is_time_exceeded()).count()
-        );
+        },
+    };
+    let (mapper, seed, step) = (
+        args.flow_mapper,
+        args.flow_mapper_seed,
+        args.flow_mapper_step,
+    );
+    let mut tracer = tracer
+        .with_ttl_range(args.min_ttl, args.max_ttl)
+        .with_ports(args.src_port, args.dst_port)
+        .with_protocol(args.protocol.into())
+        .with_confidence(args.confidence)
+        .with_max_round(args.max_round)
+        .with_algorithm(args.algorithm.into())
+        .with_flow_mappers(move || {
+            (
+                mapper.build(DEFAULT_PREFIX_SIZE_V4, seed, step),
+                mapper.build(DEFAULT_PREFIX_SIZE_V6, seed, step),
+            )
+        })
+        .with_reprobes(args.reprobes)
+        .with_stopping_rule(args.stopping_rule.into())
+        .with_estimate_successors(args.estimate_successors)
+        .with_probes_per_hop(args.probes_per_hop)
+        .with_max_silent_hops(args.max_silent_hops)
+        .with_backend(backend)
+        .with_probing_rate(args.probing_rate)
+        .with_receiver_wait_time(Duration::from_secs(args.receiver_wait_time))
+        .with_tunnel_revelation(args.reveal_tunnels)
+        .with_alias_resolution(args.aliases.is_some())
+        .with_alias_rounds(args.alias_rounds)
+        .with_metadata(TracerouteMetadata {
+            measurement_name: args.measurement_name.clone(),
+            measurement_id: args.measurement_id.clone(),
+            agent_id: args.agent_id.clone(),
+            ..TracerouteMetadata::default()
+        });
+    if let Some(path) = &args.checkpoint {
+        tracer = tracer.with_checkpoint(path.clone());
+    }
+    if let Some(path) = &args.resume {
+        tracer = tracer.with_resume(path.clone());
+    }
+    if let Some(writer) = create_file(&args.dump_replies)? {
+        tracer = tracer.with_reply_dump(ReplyDump::new(writer, args.dump_format.into()));
+    }
+    if let Some(writer) = create_file(&args.round_plans)? {
+        tracer = tracer.with_round_plans(writer);
+    }
+    Ok(tracer)
+}
 
-        let prep_start = Utc::now();
+/// Creates the file at `path`, if any.
+fn create_file(path: &Option<PathBuf>) -> Result<Option<Box<dyn Write>>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let file = File::create(path).with_context(|| format!("cannot create {:?}", path))?;
+    Ok(Some(Box::new(BufWriter::new(file))))
+}
 
-        probes = alg.next_round(replies, estimate_successsors);
+fn main() -> Result<()> {
+    env_logger::init();
+    let args = Args::parse();
 
-        let prep_end = Utc::now();
+    // loaded before the trace, not to probe for nothing if the file is invalid
+    let asn_table = match &args.asn_table {
+        Some(path) => {
+            let table = AsnTable::from_file(path)?;
+            info!("loaded {} prefixes from {:?}", table.len(), path);
+            Some(table)
+        }
+        None => None,
+    };
 
-        debug!(
-            "Preparation time: {:.3}s",
-            (prep_end - prep_start).num_milliseconds() as f64 * 1e-3
-        );
+    let tracer = tracer(&args)?;
+    let campaign = args.targets_file.is_some();
+    #[cfg(feature = "tui")]
+    let results = if args.tui {
+        trace_with_dashboard(tracer, campaign)?
+    } else {
+        trace(tracer, campaign)?
+    };
+    #[cfg(not(feature = "tui"))]
+    let results = trace(tracer, campaign)?;
 
-        let n_probes_per_ttl = probes.iter().group_by(|probe| probe.ttl);
+    for result in &results {
+        write_output(&args, result, asn_table.as_ref())?;
+    }
+    // the metrics of the diamonds, the MPLS tunnels, and the alias sets and router-level graph
+    // found towards each destination
+    if let Some(path) = &args.diamonds_summary {
+        write_json_lines(path, &results, |result| {
+            serde_json::json!({
+                "dst_addr": result.dst_addr(),
+                "diamonds": diamond_metrics(result.topology()),
+            })
+        })?;
+    }
+    if let Some(path) = &args.tunnels {
+        write_json_lines(path, &results, |result| {
+            serde_json::json!({
+                "dst_addr": result.dst_addr(),
+                "tunnels": result.tunnels(),
+            })
+        })?;
+    }
+    if let Some(path) = &args.aliases {
+        write_json_lines(path, &results, |result| {
+            serde_json::json!({
+                "dst_addr": result.dst_addr(),
+                "aliases": result.aliases(),
+                "routers": result.router_graph(),
+            })
+        })?;
+    }
+    Ok(())
+}
+
+fn trace(tracer: Tracer, campaign: bool) -> Result<Vec<TraceResult>> {
+    if campaign {
+        tracer.trace_campaign()
+    } else {
+        Ok(vec![tracer.trace()?])
+    }
+}
 
-        for (ttl, probes) in n_probes_per_ttl.into_iter() {
-            debug!("TTL {}: {} probes", ttl, probes.count());
+/// Runs the trace while its dashboard is drawn from its events on another thread.
+#[cfg(feature = "tui")]
+fn trace_with_dashboard(tracer: Tracer, campaign: bool) -> Result<Vec<TraceResult>> {
+    let (sender, events) = mpsc::channel();
+    let dashboard = thread::spawn(move || {
+        let progress = voyage::tui::run(events);
+        // the trace runs on the main thread and cannot be stopped midway
+        if let Err(err) = &progress {
+            if err.is::<voyage::tui::Interrupted>() {
+                std::process::exit(130);
+            }
         }
-        // for ttl in min_ttl..=max_ttl {
-        //     // number of ips at this ttl
-        //     debug!(
-        //         "TTL {}: {} ips",
-        //         ttl,
-        //         alg.time_exceeded_replies()
-        //             .iter()
-        //             .filter(|r| r.probe_ttl == ttl)
-        //             .map(|r| r.reply_src_addr)
-        //             .unique()
-        //             .count()
-        //     );
-        // }
-
-        // fetch the total number of distinct links in the alg
-
-        let total_links = HashSet::<&Link>::from_iter(
-            alg.links_by_ttl()
-                .values()
-                .flatten()
-                .filter(|link| link.near_ip.is_some() && link.far_ip.is_some())
-                .unique(),
-        )
-        .len();
-
-        // Add the new logging information
-        let total_ips: HashSet<IpAddr> = alg
-            .time_exceeded_replies()
-            .iter()
-            .map(|r| r.reply_src_addr)
-            .collect();
-
-        info!(
-            "round={} links_found={} total_ip={} probes={} expected_time={:.1}s",
-            round,
-            total_links,
-            total_ips.len(),
-            probes.len(),
-            args.receiver_wait_time as f64 + (probes.len() as f64 / (args.probing_rate as f64)),
-        );
+        progress
+    });
+    let results = trace(tracer.with_events(sender), campaign);
+    // the events channel is closed when the tracer is dropped, at the end of the trace
+    dashboard
+        .join()
+        .map_err(|_| anyhow::anyhow!("dashboard thread panicked"))??;
+    results
+}
+
+/// Writes a JSON line built by `line` for each destination to `path`.
+fn write_json_lines(
+    path: &Path,
+    results: &[TraceResult],
+    line: impl Fn(&TraceResult) -> Value,
+) -> Result<()> {
+    let mut lines = String::new();
+    for result in results {
+        lines.push_str(&line(result).to_string());
+        lines.push('\n');
     }
+    fs::write(path, lines)?;
+    Ok(())
+}
 
-    let end_time = Utc::now();
+/// Writes the result of a trace to stdout in the requested output format.
+fn write_output(args: &Args, result: &TraceResult, asn_table: Option<&AsnTable>) -> Result<()> {
+    let dst_addr = result.dst_addr();
+    let asn = asn_table.map(|table| table.annotate(result.topology(), dst_addr));
+    let min_ttl = args.min_ttl;
+    let max_ttl = args.max_ttl;
 
-    let mut ips_by_ttl: HashMap<TTL, HashSet<IpAddr>> = alg
-        .links_by_ttl()
-        .iter()
-        .map(|(&ttl, links)| {
-            (
-                ttl,
-                links
-                    .iter()
-                    .filter_map(|link| link.near_ip)
-                    .collect::<HashSet<_>>(),
-            )
-        })
+    let mut ips_by_ttl: HashMap<TTL, HashSet<IpAddr>> = (min_ttl..=max_ttl)
+        .map(|ttl| (ttl, result.topology().nodes_at_ttl(ttl).collect()))
         .collect();
 
     // add the echo replies to the table if the ip is the destination
 
-    for reply in alg.replies() {
+    for reply in result.replies() {
         if reply.reply_src_addr == dst_addr {
             let ttl = reply.probe_ttl;
             let table = ips_by_ttl.entry(ttl).or_default();
@@ -271,21 +540,7 @@ fn main() -> Result<()> {
         }
     }
 
-    // let pantrace_flows = replies_to_pantrace_flows(&alg.time_exceeded_replies());
-    let pantrace_flows = replies_to_pantrace_flows(&alg.replies());
-
-    let traceroute: Traceroute = Traceroute {
-        measurement_name: "diamond_miner".to_string(),
-        measurement_id: "0".to_string(),
-        agent_id: "0".to_string(),
-        start_time,
-        end_time,
-        protocol: Protocol::ICMP,
-        src_addr: IpAddr::from(Ipv4Addr::new(192, 168, 1, 1)),
-        src_addr_public: None,
-        dst_addr,
-        flows: pantrace_flows,
-    };
+    let traceroute = &result.traceroute;
 
     println!(
         ">>> total probes in flows: {}",
@@ -302,39 +557,74 @@ fn main() -> Result<()> {
             let stdout = std::io::stdout();
             let total_flows = traceroute.flows.len();
             let mut traceroute_writer =
-                ClassicTracerouteWriter::new(stdout, min_ttl, max_ttl, dst_addr, total_flows);
-            traceroute_writer.write_traceroute(&traceroute)?;
+                ClassicTracerouteWriter::new(stdout, min_ttl, max_ttl, dst_addr, total_flows)
+                    .with_load_balancers(result.load_balancers(), result.unstable_ttls())
+                    .with_tunnels(result.tunnels().to_vec())
+                    .with_asn_annotations(asn.unwrap_or_default());
+            traceroute_writer.write_traceroute(traceroute)?;
         }
         OutputFormat::Atlas => {
             debug!("--- ATLAS output ---");
-            let stdout = std::io::stdout();
-            let mut atlas_writer = AtlasWriter::new(stdout);
-            atlas_writer.write_traceroute(&traceroute)?;
+            write_json(asn.as_ref(), |output| {
+                AtlasWriter::new(output).write_traceroute(traceroute)
+            })?;
         }
         OutputFormat::Iris => {
             debug!("--- Iris output ---");
-            let stdout = std::io::stdout();
-            let mut iris_writer = pantrace::formats::iris::IrisWriter::new(stdout);
-            iris_writer.write_traceroute(&traceroute)?;
+            write_json(asn.as_ref(), |output| {
+                pantrace::formats::iris::IrisWriter::new(output).write_traceroute(traceroute)
+            })?;
         }
         OutputFormat::Flat => {
             debug!("--- flat / MetaTrace output ---");
-            let stdout = std::io::stdout();
-            let mut flat_writer = pantrace::formats::flat::FlatWriter::new(stdout);
-            flat_writer.write_traceroute(&traceroute)?;
+            write_json(asn.as_ref(), |output| {
+                pantrace::formats::flat::FlatWriter::new(output).write_traceroute(traceroute)
+            })?;
         }
         OutputFormat::Internal => {
             debug!("--- internal / Pantrace output ---");
-            let stdout = std::io::stdout();
-            let mut internal_writer = pantrace::formats::internal::InternalWriter::new(stdout);
-            internal_writer.write_traceroute(&traceroute)?;
+            write_json(asn.as_ref(), |output| {
+                pantrace::formats::internal::InternalWriter::new(output)
+                    .write_traceroute(traceroute)
+            })?;
         }
         OutputFormat::Scamper => {
             println!("--- Scamper / warts output (binary) ---");
             let stdout = std::io::stdout();
             let mut scamper_writer =
                 pantrace::formats::scamper_trace_warts::ScamperTraceWartsWriter::new(stdout);
-            scamper_writer.write_traceroute(&traceroute)?;
+            scamper_writer.write_traceroute(traceroute)?;
+        }
+        OutputFormat::Links => {
+            // one line per link, with how its near node balances traffic,
+            // and the origins of both ends if a prefix table is given
+            let load_balancers = result.load_balancers();
+            for ttl in min_ttl..=max_ttl {
+                for (near, far) in result.topology().links_at_ttl(ttl) {
+                    let load_balancing = load_balancers
+                        .get(&(ttl, near))
+                        .map(|load_balancing| load_balancing.to_string())
+                        .unwrap_or("-".to_string());
+                    match &asn {
+                        Some(asn) => {
+                            let origin = |addr| {
+                                asn.origin(addr)
+                                    .map_or("-".to_string(), |origin| origin.to_string())
+                            };
+                            println!(
+                                "{} {} {} {} {} {}",
+                                ttl,
+                                near,
+                                far,
+                                load_balancing,
+                                origin(near),
+                                origin(far)
+                            );
+                        }
+                        None => println!("{} {} {} {}", ttl, near, far, load_balancing),
+                    }
+                }
+            }
         }
         OutputFormat::Quiet => {
             debug!("Links");
@@ -346,21 +636,9 @@ fn main() -> Result<()> {
                 } else {
                     break;
                 }
-                for link in alg
-                    .links_by_ttl()
-                    .get(&ttl)
-                    .unwrap_or(&vec![])
-                    .iter()
-                    .unique()
-                {
-                    let (near, far) = match (link.near_ip, link.far_ip) {
-                        (Some(near), Some(far)) => (format!("{}", near), format!("{}", far)),
-                        (Some(near), None) => (format!("{}", near), "*".to_string()),
-                        (None, Some(far)) => ("*".to_string(), format!("{}", far)),
-                        (None, None) => ("*".to_string(), "*".to_string()),
-                    };
+                for (near, far) in result.topology().links_at_ttl(ttl) {
                     debug!("|  {} -- {}", near, far);
-                    if link.far_ip == Some(dst_addr) {
+                    if far == dst_addr {
                         end = true;
                         break;
                     }
@@ -371,3 +649,26 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Writes the JSON lines written by `write` to stdout, with the origins of the addresses they contain, if any.
+fn write_json(
+    asn: Option<&AsnAnnotations>,
+    write: impl FnOnce(&mut Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    let mut output = vec![];
+    write(&mut output)?;
+    let mut stdout = std::io::stdout().lock();
+    let Some(asn) = asn else {
+        stdout.write_all(&output)?;
+        return Ok(());
+    };
+    for line in output.split(|&byte| byte == b'\n') {
+        if line.is_empty() {
+            continue;
+        }
+        let mut value: Value = serde_json::from_slice(line)?;
+        asn.annotate_json(&mut value);
+        writeln!(stdout, "{}", value)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,362 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::net::IpAddr;
+
+use anyhow::Result;
+use caracat::models::{Probe, Reply, L4};
+use serde::Serialize;
+
+use crate::probe::Prober;
+use crate::topology::Topology;
+use crate::types::{initial_ttl, Flow, Port, TTL};
+
+/// Minimum increase of the difference between the return and the forward path lengths
+/// from a hop to the next for the return path to be considered as going through an invisible tunnel.
+pub const FRPLA_THRESHOLD: u8 = 3;
+
+/// Hidden LSRs probed for when their number cannot be estimated.
+pub const MAX_HIDDEN_HOPS: u8 = 8;
+
+/// How an MPLS tunnel shows up in a traceroute, after the taxonomy of TNT
+/// (Vanaubel et al., "Through the Wormhole: Tracking Invisible MPLS Tunnels", IMC 2017).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TunnelKind {
+    /// The ingress propagates the IP TTL, and the LSRs quote the label stack (RFC 4950).
+    Explicit,
+    /// The ingress propagates the IP TTL, but the LSRs do not quote the label stack.
+    /// Their quoted TTL is larger than 1, as the IP TTL is not decremented inside the tunnel.
+    Implicit,
+    /// The LSRs are hidden, and the egress quotes a label stack whose TTL tells how many there are.
+    Opaque,
+    /// The LSRs are hidden and no label is quoted. The egress is given away by a return path
+    /// longer than the forward path, or by appearing at two consecutive TTLs.
+    Invisible,
+}
+
+impl fmt::Display for TunnelKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TunnelKind::Explicit => write!(f, "explicit"),
+            TunnelKind::Implicit => write!(f, "implicit"),
+            TunnelKind::Opaque => write!(f, "opaque"),
+            TunnelKind::Invisible => write!(f, "invisible"),
+        }
+    }
+}
+
+/// An interface at the edge of or inside a tunnel.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct TunnelHop {
+    pub ttl: TTL,
+    pub addr: IpAddr,
+    /// The labels it quoted, from the top of the stack.
+    pub labels: Vec<u32>,
+}
+
+/// An MPLS tunnel inferred from the replies of a trace.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Tunnel {
+    pub kind: TunnelKind,
+    /// The interfaces before the tunnel.
+    pub ingress: Vec<TunnelHop>,
+    /// The LSRs seen in the trace, for explicit and implicit tunnels.
+    pub lsrs: Vec<TunnelHop>,
+    /// The interfaces after the tunnel. For opaque and invisible tunnels,
+    /// the interfaces that gave the tunnel away.
+    pub egress: Vec<TunnelHop>,
+    /// Estimated number of hidden LSRs, for opaque and invisible tunnels.
+    pub hidden_hops: Option<u8>,
+    /// Hidden LSRs found by [`reveal_tunnels`], from the ingress to the egress.
+    pub revealed: Vec<IpAddr>,
+}
+
+impl Tunnel {
+    fn hops(&self) -> impl Iterator<Item = &TunnelHop> {
+        self.ingress.iter().chain(&self.lsrs).chain(&self.egress)
+    }
+
+    /// Whether the interface is an LSR or an egress of the tunnel.
+    pub fn contains_node(&self, ttl: TTL, addr: IpAddr) -> bool {
+        self.lsrs
+            .iter()
+            .chain(&self.egress)
+            .any(|hop| hop.ttl == ttl && hop.addr == addr)
+    }
+
+    /// Whether the link from `near` at `ttl` to `far` at `ttl + 1` goes through the tunnel.
+    pub fn contains_link(&self, ttl: TTL, near: IpAddr, far: IpAddr) -> bool {
+        let is_near = self
+            .ingress
+            .iter()
+            .chain(&self.lsrs)
+            .any(|hop| hop.ttl == ttl && hop.addr == near);
+        is_near && self.contains_node(ttl + 1, far)
+    }
+}
+
+/// What the time exceeded replies of an interface tell about MPLS.
+#[derive(Default)]
+struct Signature {
+    labels: Vec<u32>,
+    // TTL of the top label stack entry
+    lse_ttl: Option<u8>,
+    quoted_ttl: u8,
+    // return minus forward path lengths, over the replies
+    frpla: Option<i16>,
+    // the interface also answered at the next TTL for the same flow
+    duplicate: bool,
+}
+
+fn return_path_length(reply_ttl: u8) -> u8 {
+    initial_ttl(reply_ttl) - reply_ttl + 1
+}
+
+fn signatures(replies: &[&Reply]) -> HashMap<(TTL, IpAddr), Signature> {
+    let mut signatures: HashMap<(TTL, IpAddr), Signature> = HashMap::new();
+    let mut addrs_by_flow: HashMap<(Flow, TTL), HashSet<IpAddr>> = HashMap::new();
+    for reply in replies.iter().filter(|reply| reply.is_time_exceeded()) {
+        addrs_by_flow
+            .entry((Flow::from(*reply), reply.probe_ttl))
+            .or_default()
+            .insert(reply.reply_src_addr);
+
+        let signature = signatures
+            .entry((reply.probe_ttl, reply.reply_src_addr))
+            .or_default();
+        if signature.labels.is_empty() && !reply.reply_mpls_labels.is_empty() {
+            signature.labels = reply.reply_mpls_labels.iter().map(|l| l.label).collect();
+            signature.lse_ttl = Some(reply.reply_mpls_labels[0].ttl);
+        }
+        signature.quoted_ttl = signature.quoted_ttl.max(reply.quoted_ttl);
+        // a TTL of 0 means that it was not recorded
+        if reply.reply_ttl > 0 {
+            let frpla = return_path_length(reply.reply_ttl) as i16 - reply.probe_ttl as i16;
+            signature.frpla = Some(signature.frpla.map_or(frpla, |min| min.min(frpla)));
+        }
+    }
+    for ((flow, ttl), addrs) in &addrs_by_flow {
+        let Some(next) = addrs_by_flow.get(&(*flow, ttl + 1)) else {
+            continue;
+        };
+        for addr in addrs.intersection(next) {
+            if let Some(signature) = signatures.get_mut(&(*ttl, *addr)) {
+                signature.duplicate = true;
+            }
+        }
+    }
+    signatures
+}
+
+// the kind of tunnel an interface belongs to, and the estimated number of hidden LSRs before it,
+// given the largest difference between the return and forward path lengths of its predecessors
+fn classify(
+    signature: &Signature,
+    previous_frpla: Option<i16>,
+) -> Option<(TunnelKind, Option<u8>)> {
+    match signature.lse_ttl {
+        // the label TTL expired at this LSR, so it was propagated from the IP TTL
+        Some(lse_ttl) if lse_ttl <= 1 => Some((TunnelKind::Explicit, None)),
+        // the ingress set the label TTL to 255, and each hidden LSR decremented it
+        Some(lse_ttl) => Some((TunnelKind::Opaque, Some(255 - lse_ttl))),
+        None if signature.quoted_ttl > 1 => Some((TunnelKind::Implicit, None)),
+        None => {
+            // the hops after an invisible tunnel also have a longer return path, only the first one counts
+            let frpla = signature
+                .frpla
+                .zip(previous_frpla)
+                .map(|(frpla, previous)| frpla - previous)
+                .filter(|&increase| increase >= FRPLA_THRESHOLD as i16)
+                .map(|increase| increase.min(u8::MAX as i16) as u8);
+            (frpla.is_some() || signature.duplicate).then_some((TunnelKind::Invisible, frpla))
+        }
+    }
+}
+
+/// Infers the MPLS tunnels crossed by a trace from the label stacks, the quoted TTLs and the
+/// reply TTLs of its time exceeded replies. Consecutive LSRs of explicit or implicit tunnels
+/// are grouped into a single tunnel. The destination is never part of a tunnel.
+pub fn detect_tunnels(replies: &[&Reply], topology: &Topology, dst_addr: IpAddr) -> Vec<Tunnel> {
+    let signatures = signatures(replies);
+    // the first hop is compared to a path without tunnel
+    let previous_frpla = |ttl: TTL, addr: IpAddr| -> Option<i16> {
+        if ttl <= 1 {
+            return Some(0);
+        }
+        topology
+            .predecessors(ttl, addr)
+            .filter_map(|near| signatures.get(&(ttl - 1, near))?.frpla)
+            .max()
+    };
+    let kinds: BTreeMap<(TTL, IpAddr), (TunnelKind, Option<u8>)> = signatures
+        .iter()
+        .filter(|((_, addr), _)| *addr != dst_addr)
+        .filter_map(|(&(ttl, addr), signature)| {
+            let kind = classify(signature, previous_frpla(ttl, addr))?;
+            Some(((ttl, addr), kind))
+        })
+        .collect();
+    let labels = |ttl: TTL, addr: IpAddr| TunnelHop {
+        ttl,
+        addr,
+        labels: signatures
+            .get(&(ttl, addr))
+            .map(|signature| signature.labels.clone())
+            .unwrap_or_default(),
+    };
+    let predecessors = |ttl: TTL, addr: IpAddr| -> Vec<TunnelHop> {
+        topology
+            .predecessors(ttl, addr)
+            .map(|near| labels(ttl - 1, near))
+            .collect()
+    };
+
+    let mut tunnels = vec![];
+    let mut visited: HashSet<(TTL, IpAddr)> = HashSet::new();
+    for (&(ttl, addr), &(kind, hidden_hops)) in &kinds {
+        if !visited.insert((ttl, addr)) {
+            continue;
+        }
+        if matches!(kind, TunnelKind::Opaque | TunnelKind::Invisible) {
+            tunnels.push(Tunnel {
+                kind,
+                ingress: predecessors(ttl, addr),
+                lsrs: vec![],
+                egress: vec![labels(ttl, addr)],
+                hidden_hops,
+                revealed: vec![],
+            });
+            continue;
+        }
+
+        // the LSRs of the same kind linked to this one, by increasing TTL
+        let mut lsrs: BTreeSet<(TTL, IpAddr)> = BTreeSet::from([(ttl, addr)]);
+        let mut stack = vec![(ttl, addr)];
+        while let Some((ttl, addr)) = stack.pop() {
+            let successors = topology.successors(ttl, addr).map(|far| (ttl + 1, far));
+            let predecessors = topology.predecessors(ttl, addr).map(|near| (ttl - 1, near));
+            for hop in successors.chain(predecessors).collect::<Vec<_>>() {
+                if kinds.get(&hop).is_some_and(|&(other, _)| other == kind) && visited.insert(hop) {
+                    lsrs.insert(hop);
+                    stack.push(hop);
+                }
+            }
+        }
+        let ingress: BTreeSet<TunnelHop> = lsrs
+            .iter()
+            .flat_map(|&(ttl, addr)| predecessors(ttl, addr))
+            .filter(|hop| !lsrs.contains(&(hop.ttl, hop.addr)))
+            .collect();
+        let egress: BTreeSet<TunnelHop> = lsrs
+            .iter()
+            .flat_map(|&(ttl, addr)| {
+                topology
+                    .successors(ttl, addr)
+                    .map(move |far| (ttl + 1, far))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|hop| !lsrs.contains(hop))
+            .map(|(ttl, addr)| labels(ttl, addr))
+            .collect();
+        tunnels.push(Tunnel {
+            kind,
+            ingress: ingress.into_iter().collect(),
+            lsrs: lsrs
+                .into_iter()
+                .map(|(ttl, addr)| labels(ttl, addr))
+                .collect(),
+            egress: egress.into_iter().collect(),
+            hidden_hops,
+            revealed: vec![],
+        });
+    }
+    tunnels
+}
+
+/// The probes of a Paris traceroute towards `target` from `min_ttl` to `max_ttl`.
+fn revelation_probes(
+    target: IpAddr,
+    min_ttl: TTL,
+    max_ttl: TTL,
+    src_port: Port,
+    dst_port: Port,
+    protocol: L4,
+) -> Vec<Probe> {
+    let protocol = match (protocol, target) {
+        (L4::ICMP, IpAddr::V6(_)) => L4::ICMPv6,
+        (protocol, _) => protocol,
+    };
+    (min_ttl..=max_ttl)
+        .map(|ttl| Probe {
+            dst_addr: target,
+            src_port,
+            dst_port,
+            protocol,
+            ttl,
+        })
+        .collect()
+}
+
+/// Sends probes towards the egress of the opaque and invisible tunnels to reveal their LSRs,
+/// as TNT does. Tunnels usually carry the traffic towards the loopback addresses of the routers
+/// only: a traceroute towards the egress interface goes around the tunnel and shows all the LSRs
+/// at once (direct path revelation), or the last one, which is traced in turn until no new LSR
+/// is found (backward recursive path revelation). Returns the number of probes sent.
+pub fn reveal_tunnels<P: Prober>(
+    prober: &mut P,
+    tunnels: &mut [Tunnel],
+    src_port: Port,
+    dst_port: Port,
+    protocol: L4,
+) -> Result<usize> {
+    let mut n_probes = 0;
+    for tunnel in tunnels
+        .iter_mut()
+        .filter(|tunnel| matches!(tunnel.kind, TunnelKind::Opaque | TunnelKind::Invisible))
+    {
+        let (Some(ingress), Some(egress)) = (tunnel.ingress.first(), tunnel.egress.first()) else {
+            continue;
+        };
+        let known: HashSet<IpAddr> = tunnel.hops().map(|hop| hop.addr).collect();
+        let min_ttl = ingress.ttl + 1;
+        let max_ttl = egress
+            .ttl
+            .saturating_add(tunnel.hidden_hops.unwrap_or(MAX_HIDDEN_HOPS));
+
+        let mut target = egress.addr;
+        let mut revealed: Vec<IpAddr> = vec![];
+        for _ in 0..MAX_HIDDEN_HOPS {
+            let probes = revelation_probes(target, min_ttl, max_ttl, src_port, dst_port, protocol);
+            n_probes += probes.len();
+            let replies = prober.probe(probes)?;
+
+            // the interfaces before the target, by TTL
+            let mut path: BTreeMap<TTL, IpAddr> = BTreeMap::new();
+            for reply in replies
+                .iter()
+                .filter(|reply| reply.probe_dst_addr == target)
+            {
+                path.insert(reply.probe_ttl, reply.reply_src_addr);
+            }
+            let new: Vec<IpAddr> = path
+                .into_values()
+                .take_while(|&addr| addr != target)
+                .filter(|addr| !known.contains(addr) && !revealed.contains(addr))
+                .collect();
+            let Some(&last) = new.last() else {
+                break;
+            };
+            let direct = new.len() > 1;
+            revealed.splice(0..0, new);
+            if direct {
+                break;
+            }
+            target = last;
+        }
+        tunnel.revealed = revealed;
+    }
+    Ok(n_probes)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,209 @@
+use caracat::models::MPLSLabel;
+
+use crate::helpers::{addr, reply, DEST};
+
+use super::*;
+
+const IP: [&str; 6] = [
+    "192.168.0.1",
+    "192.168.0.2",
+    "192.168.0.3",
+    "192.168.0.4",
+    "192.168.0.5",
+    "192.168.0.6",
+];
+
+fn label(label: u32, ttl: u8) -> MPLSLabel {
+    MPLSLabel {
+        label,
+        experimental: 0,
+        bottom_of_stack: true,
+        ttl,
+    }
+}
+
+// a time exceeded reply from `ip` at `ttl`, sent with an initial TTL of 255
+// over a return path as long as the forward path
+fn hop(ttl: u8, ip: &str) -> Reply {
+    Reply {
+        reply_ttl: 255 - (ttl - 1),
+        quoted_ttl: 1,
+        ..reply(ttl, ip, DEST)
+    }
+}
+
+// the replies of a flow going through IP[0..4] at TTLs 1 to 4
+fn path(replies: [Reply; 4]) -> Vec<Tunnel> {
+    let replies: Vec<&Reply> = replies.iter().collect();
+    let topology = Topology::from_replies(&replies);
+    detect_tunnels(&replies, &topology, addr(DEST))
+}
+
+fn hops(ttls: &[(u8, &str)]) -> Vec<(u8, IpAddr)> {
+    ttls.iter().map(|&(ttl, ip)| (ttl, addr(ip))).collect()
+}
+
+fn tunnel_hops(hops: &[TunnelHop]) -> Vec<(u8, IpAddr)> {
+    hops.iter().map(|hop| (hop.ttl, hop.addr)).collect()
+}
+
+#[test]
+fn test_no_tunnel() {
+    let tunnels = path([hop(1, IP[0]), hop(2, IP[1]), hop(3, IP[2]), hop(4, IP[3])]);
+    assert!(tunnels.is_empty());
+}
+
+#[test]
+fn test_explicit() {
+    let tunnels = path([
+        hop(1, IP[0]),
+        Reply {
+            reply_mpls_labels: vec![label(24005, 1)],
+            ..hop(2, IP[1])
+        },
+        Reply {
+            reply_mpls_labels: vec![label(24006, 1)],
+            ..hop(3, IP[2])
+        },
+        hop(4, IP[3]),
+    ]);
+    assert_eq!(tunnels.len(), 1);
+    let tunnel = &tunnels[0];
+    assert_eq!(tunnel.kind, TunnelKind::Explicit);
+    assert_eq!(tunnel_hops(&tunnel.ingress), hops(&[(1, IP[0])]));
+    assert_eq!(tunnel_hops(&tunnel.lsrs), hops(&[(2, IP[1]), (3, IP[2])]));
+    assert_eq!(tunnel_hops(&tunnel.egress), hops(&[(4, IP[3])]));
+    assert_eq!(tunnel.lsrs[0].labels, vec![24005]);
+    assert!(tunnel.contains_link(1, addr(IP[0]), addr(IP[1])));
+    assert!(tunnel.contains_link(3, addr(IP[2]), addr(IP[3])));
+    assert!(!tunnel.contains_node(1, addr(IP[0])));
+}
+
+#[test]
+fn test_implicit() {
+    let tunnels = path([
+        hop(1, IP[0]),
+        Reply {
+            quoted_ttl: 2,
+            ..hop(2, IP[1])
+        },
+        Reply {
+            quoted_ttl: 3,
+            ..hop(3, IP[2])
+        },
+        hop(4, IP[3]),
+    ]);
+    assert_eq!(tunnels.len(), 1);
+    assert_eq!(tunnels[0].kind, TunnelKind::Implicit);
+    assert_eq!(
+        tunnel_hops(&tunnels[0].lsrs),
+        hops(&[(2, IP[1]), (3, IP[2])])
+    );
+}
+
+#[test]
+fn test_opaque() {
+    let tunnels = path([
+        hop(1, IP[0]),
+        hop(2, IP[1]),
+        // three LSRs decremented the label TTL
+        Reply {
+            reply_mpls_labels: vec![label(24005, 252)],
+            ..hop(3, IP[2])
+        },
+        hop(4, IP[3]),
+    ]);
+    assert_eq!(tunnels.len(), 1);
+    let tunnel = &tunnels[0];
+    assert_eq!(tunnel.kind, TunnelKind::Opaque);
+    assert_eq!(tunnel.hidden_hops, Some(3));
+    assert_eq!(tunnel_hops(&tunnel.ingress), hops(&[(2, IP[1])]));
+    assert!(tunnel.lsrs.is_empty());
+    assert_eq!(tunnel_hops(&tunnel.egress), hops(&[(3, IP[2])]));
+}
+
+#[test]
+fn test_invisible() {
+    // the return path goes through four hidden LSRs from the third hop on
+    let tunnels = path([
+        hop(1, IP[0]),
+        hop(2, IP[1]),
+        Reply {
+            reply_ttl: 255 - 2 - 4,
+            ..hop(3, IP[2])
+        },
+        Reply {
+            reply_ttl: 255 - 3 - 4,
+            ..hop(4, IP[3])
+        },
+    ]);
+    assert_eq!(tunnels.len(), 1);
+    assert_eq!(tunnels[0].kind, TunnelKind::Invisible);
+    assert_eq!(tunnels[0].hidden_hops, Some(4));
+    assert_eq!(tunnel_hops(&tunnels[0].egress), hops(&[(3, IP[2])]));
+
+    // the egress answers at two consecutive TTLs
+    let tunnels = path([hop(1, IP[0]), hop(2, IP[1]), hop(3, IP[2]), hop(4, IP[2])]);
+    assert_eq!(tunnels.len(), 1);
+    assert_eq!(tunnels[0].kind, TunnelKind::Invisible);
+    assert_eq!(tunnels[0].hidden_hops, None);
+    assert_eq!(tunnel_hops(&tunnels[0].egress), hops(&[(3, IP[2])]));
+}
+
+// answers the probes towards each target along its path, then from the target itself
+struct PathProber {
+    paths: HashMap<IpAddr, Vec<&'static str>>,
+}
+
+impl Prober for PathProber {
+    fn probe(&mut self, probes: Vec<Probe>) -> Result<Vec<Reply>> {
+        Ok(probes
+            .iter()
+            .filter_map(|probe| {
+                let path = &self.paths[&probe.dst_addr];
+                let ip = match path.get(probe.ttl as usize - 1) {
+                    Some(ip) => addr(ip),
+                    None if probe.ttl as usize == path.len() + 1 => probe.dst_addr,
+                    None => return None,
+                };
+                Some(Reply {
+                    reply_src_addr: ip,
+                    probe_dst_addr: probe.dst_addr,
+                    probe_ttl: probe.ttl,
+                    ..Default::default()
+                })
+            })
+            .collect())
+    }
+}
+
+fn invisible_tunnel() -> Vec<Tunnel> {
+    path([hop(1, IP[0]), hop(2, IP[1]), hop(3, IP[2]), hop(4, IP[2])])
+}
+
+#[test]
+fn test_direct_path_revelation() {
+    // the egress interface is not reached through the tunnel
+    let mut prober = PathProber {
+        paths: HashMap::from([(addr(IP[2]), vec![IP[0], IP[1], IP[4], IP[5]])]),
+    };
+    let mut tunnels = invisible_tunnel();
+    let probes = reveal_tunnels(&mut prober, &mut tunnels, 24000, 33434, L4::ICMP).unwrap();
+    assert_eq!(tunnels[0].revealed, vec![addr(IP[4]), addr(IP[5])]);
+    assert_eq!(probes, (3..=3 + MAX_HIDDEN_HOPS).count());
+}
+
+#[test]
+fn test_backward_recursive_path_revelation() {
+    // each LSR is only reached through the tunnel up to the previous one
+    let mut prober = PathProber {
+        paths: HashMap::from([
+            (addr(IP[2]), vec![IP[0], IP[1], IP[5]]),
+            (addr(IP[5]), vec![IP[0], IP[1], IP[4]]),
+            (addr(IP[4]), vec![IP[0], IP[1]]),
+        ]),
+    };
+    let mut tunnels = invisible_tunnel();
+    reveal_tunnels(&mut prober, &mut tunnels, 24000, 33434, L4::ICMP).unwrap();
+    assert_eq!(tunnels[0].revealed, vec![addr(IP[4]), addr(IP[5])]);
+}
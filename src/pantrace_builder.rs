@@ -1,8 +1,11 @@
-use caracat::models::Reply;
-use chrono::DateTime;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use caracat::models::{Reply, L4};
+use chrono::{DateTime, Utc};
 use itertools::Itertools;
 use pantrace::formats::internal::{
-    MplsEntry, TracerouteFlow, TracerouteHop, TracerouteProbe, TracerouteReply,
+    MplsEntry, Protocol, Traceroute, TracerouteFlow, TracerouteHop, TracerouteProbe,
+    TracerouteReply,
 };
 
 use crate::links::get_replies_by_flow;
@@ -68,3 +71,93 @@ pub fn replies_to_pantrace_flows(replies: &[&Reply]) -> Vec<TracerouteFlow> {
         .map(|replies| generate_pantrace_traceroute_flow(&replies))
         .collect()
 }
+
+/// What the replies alone do not tell about a traceroute.
+#[derive(Clone, Debug)]
+pub struct TracerouteMetadata {
+    pub measurement_name: String,
+    pub measurement_id: String,
+    pub agent_id: String,
+    pub protocol: L4,
+    /// Addresses of the interface the probes were sent from, the one of the same family
+    /// as the destination is used. If there is none, the address the replies were sent to is used.
+    pub src_addrs: Vec<IpAddr>,
+}
+
+impl Default for TracerouteMetadata {
+    fn default() -> Self {
+        Self {
+            measurement_name: "diamond_miner".to_string(),
+            measurement_id: "0".to_string(),
+            agent_id: "0".to_string(),
+            protocol: L4::ICMP,
+            src_addrs: vec![],
+        }
+    }
+}
+
+// ICMP probes towards IPv6 destinations are sent with ICMPv6
+fn pantrace_protocol(protocol: L4, dst_addr: IpAddr) -> Protocol {
+    match (protocol, dst_addr) {
+        (L4::ICMP, IpAddr::V4(_)) => Protocol::ICMP,
+        (L4::ICMP | L4::ICMPv6, _) => Protocol::ICMPv6,
+        (L4::UDP, _) => Protocol::UDP,
+    }
+}
+
+fn most_common(addrs: impl Iterator<Item = IpAddr>) -> Option<IpAddr> {
+    addrs
+        .counts()
+        .into_iter()
+        .max_by_key(|&(addr, count)| (count, addr))
+        .map(|(addr, _)| addr)
+}
+
+/// The source address of the probes quoted by the routers in their replies, when it is not `src_addr`.
+///
+/// This only reveals the public address behind NATs that leave the quoted header of ICMP errors
+/// untouched: NATs following RFC 5508 translate it back to the private address, and then no
+/// public address is found.
+pub fn public_src_addr(replies: &[&Reply], src_addr: IpAddr) -> Option<IpAddr> {
+    most_common(
+        replies
+            .iter()
+            .filter(|reply| reply.is_time_exceeded())
+            .map(|reply| reply.probe_src_addr),
+    )
+    .filter(|&addr| addr != src_addr)
+}
+
+pub fn replies_to_pantrace_traceroute(
+    replies: &[&Reply],
+    dst_addr: IpAddr,
+    metadata: &TracerouteMetadata,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Traceroute {
+    let src_addr = metadata
+        .src_addrs
+        .iter()
+        .copied()
+        .find(|addr| addr.is_ipv4() == dst_addr.is_ipv4())
+        .or_else(|| most_common(replies.iter().map(|reply| reply.reply_dst_addr)))
+        .unwrap_or(match dst_addr {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        });
+    Traceroute {
+        measurement_name: metadata.measurement_name.clone(),
+        measurement_id: metadata.measurement_id.clone(),
+        agent_id: metadata.agent_id.clone(),
+        start_time,
+        end_time,
+        protocol: pantrace_protocol(metadata.protocol, dst_addr),
+        src_addr,
+        src_addr_public: public_src_addr(replies, src_addr),
+        dst_addr,
+        flows: replies_to_pantrace_flows(replies),
+    }
+}
+
+#[cfg(test)]
+mod tests;
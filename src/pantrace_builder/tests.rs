@@ -0,0 +1,82 @@
+use crate::helpers::{addr, reply, DEST};
+
+use super::*;
+
+const PRIVATE: &str = "192.168.1.10";
+const PUBLIC: &str = "203.0.113.7";
+const IP: [&str; 2] = ["10.0.0.1", "10.0.0.2"];
+
+// replies to probes sent from `PRIVATE`, quoted by the routers with the source address `quoted`
+fn replies(quoted: &str) -> Vec<Reply> {
+    IP.iter()
+        .enumerate()
+        .map(|(index, ip)| Reply {
+            probe_src_addr: addr(quoted),
+            reply_dst_addr: addr(PRIVATE),
+            capture_timestamp: 1_700_000_000_000_000,
+            ..reply(index as u8 + 1, ip, DEST)
+        })
+        .collect()
+}
+
+#[test]
+fn test_traceroute_metadata() {
+    let replies = replies(PRIVATE);
+    let replies: Vec<&Reply> = replies.iter().collect();
+    let metadata = TracerouteMetadata {
+        measurement_name: "campaign".to_string(),
+        agent_id: "vp1".to_string(),
+        protocol: L4::UDP,
+        src_addrs: vec![addr("2001:db8::1"), addr(PRIVATE)],
+        ..TracerouteMetadata::default()
+    };
+    let now = Utc::now();
+    let traceroute = replies_to_pantrace_traceroute(&replies, addr(DEST), &metadata, now, now);
+    assert_eq!(traceroute.measurement_name, "campaign");
+    assert_eq!(traceroute.measurement_id, "0");
+    assert_eq!(traceroute.agent_id, "vp1");
+    assert!(matches!(traceroute.protocol, Protocol::UDP));
+    // the address of the same family as the destination
+    assert_eq!(traceroute.src_addr, addr(PRIVATE));
+    assert_eq!(traceroute.src_addr_public, None);
+    assert_eq!(traceroute.flows.len(), 1);
+
+    // without the addresses of the interface, e.g. for a replay
+    let traceroute = replies_to_pantrace_traceroute(
+        &replies,
+        addr(DEST),
+        &TracerouteMetadata::default(),
+        now,
+        now,
+    );
+    assert!(matches!(traceroute.protocol, Protocol::ICMP));
+    assert_eq!(traceroute.src_addr, addr(PRIVATE));
+
+    let traceroute = replies_to_pantrace_traceroute(
+        &replies,
+        addr("2001:db8::2"),
+        &TracerouteMetadata::default(),
+        now,
+        now,
+    );
+    assert!(matches!(traceroute.protocol, Protocol::ICMPv6));
+
+    // without any reply either
+    let traceroute = replies_to_pantrace_traceroute(
+        &[],
+        addr("2001:db8::2"),
+        &TracerouteMetadata::default(),
+        now,
+        now,
+    );
+    assert_eq!(traceroute.src_addr, addr("::"));
+}
+
+#[test]
+fn test_public_src_addr() {
+    let natted = replies(PUBLIC);
+    let natted: Vec<&Reply> = natted.iter().collect();
+    assert_eq!(public_src_addr(&natted, addr(PRIVATE)), Some(addr(PUBLIC)));
+    assert_eq!(public_src_addr(&natted, addr(PUBLIC)), None);
+    assert_eq!(public_src_addr(&[], addr(PRIVATE)), None);
+}
@@ -1,6 +1,9 @@
-use std::thread::sleep;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
-use log::info;
+use log::{debug, info};
 
 use anyhow::Result;
 use caracat::{
@@ -11,67 +14,189 @@ use caracat::{
     utilities::prefix_filter_from_file,
 };
 
-use crate::receiver::ReceiveCache;
-
-pub fn probe<T: Iterator<Item = Probe>>(config: Config, probes: T) -> Result<Vec<Reply>> {
-    let allowed_prefixes = match config.allowed_prefixes_file {
-        None => None,
-        Some(path) => Some(prefix_filter_from_file(&path)?),
-    };
-
-    let blocked_prefixes = match config.blocked_prefixes_file {
-        None => None,
-        Some(path) => Some(prefix_filter_from_file(&path)?),
-    };
-
-    let rate_limiter = RateLimiter::new(
-        config.probing_rate,
-        config.batch_size,
-        config.rate_limiting_method,
-    );
-    // let rate_statistics = rate_limiter.statistics().clone();
-
-    // let receiver = ReceiveLoop::new(
-    let mut receiver = ReceiveCache::new(
-        config.interface.clone(),
-        // config.output_file_csv,
-        // config.instance_id,
-        // config.extra_string,
-        // config.integrity_check,
-    );
-    // let receiver_statistics = receiver.statistics().clone();
-
-    let mut prober = SendLoop::new(
-        config.batch_size,
-        config.instance_id,
-        config.min_ttl,
-        config.max_ttl,
-        config.max_probes,
-        config.packets,
-        allowed_prefixes,
-        blocked_prefixes,
-        rate_limiter,
-        Sender::new(&config.interface, config.instance_id, config.dry_run)?,
-    );
-    // let prober_statistics = prober.statistics().clone();
-
-    // let logger = StatisticsLogger::new(prober_statistics, rate_statistics, receiver_statistics);
-
-    prober.probe(probes)?;
-    info!(
-        "Waiting {:?} for last replies...",
-        config.receiver_wait_time
-    );
-    sleep(config.receiver_wait_time);
-
-    // TODO: Cleaner way?
-    // let final_prober_statistics = *prober.statistics().lock().unwrap();
-    // let final_receiver_statistics = receiver.statistics().lock().unwrap().clone();
-
-    let replies = receiver.stop();
-    // logger.stop();
-
-    Ok(replies)
-
-    // Ok((final_prober_statistics, final_receiver_statistics))
+use crate::events::TraceEvent;
+use crate::receiver::{
+    probe_key, reply_key, OutstandingProbes, ProbeKey, ReceiveCache, RoundReplies,
+};
+
+/// A probing backend: sends a batch of probes and returns the replies it got back.
+pub trait Prober {
+    fn probe(&mut self, probes: Vec<Probe>) -> Result<Vec<Reply>>;
+
+    /// Takes the replies that arrived after their round was closed, with the round
+    /// (1 for the first call to [`Prober::probe`]) in which their probe was sent.
+    fn late_replies(&mut self) -> Vec<(u32, Reply)> {
+        vec![]
+    }
+
+    /// Ends the rounds of a trace: returns the late replies received since the last round,
+    /// including the replies to the last round itself. The prober can still be used afterwards.
+    fn close(&mut self) -> Vec<(u32, Reply)> {
+        self.late_replies()
+    }
+}
+
+/// How long the probes of a round are remembered after the round is over:
+/// replies arriving later are dropped as unsolicited.
+pub const LATE_REPLY_WINDOW: Duration = Duration::from_secs(10);
+
+/// A probing session over caracat that lives for a whole trace: the sender and the pcap
+/// receiver are opened once, so that replies arriving between rounds are not lost.
+/// Requires raw socket privileges.
+pub struct ProbingSession {
+    interface: String,
+    // None once the session is closed, until the next round
+    receiver: Option<ReceiveCache>,
+    send_loop: SendLoop,
+    probing_rate: u64,
+    receiver_wait_time: Duration,
+    round: u32,
+    // round in which each probe was sent, and end of the round
+    sent: HashMap<ProbeKey, (u32, Option<Instant>)>,
+    late_replies: Vec<(u32, Reply)>,
+    events: Option<mpsc::Sender<TraceEvent>>,
+}
+
+impl ProbingSession {
+    pub fn new(config: Config) -> Result<Self> {
+        let allowed_prefixes = match config.allowed_prefixes_file {
+            None => None,
+            Some(path) => Some(prefix_filter_from_file(&path)?),
+        };
+
+        let blocked_prefixes = match config.blocked_prefixes_file {
+            None => None,
+            Some(path) => Some(prefix_filter_from_file(&path)?),
+        };
+
+        let rate_limiter = RateLimiter::new(
+            config.probing_rate,
+            config.batch_size,
+            config.rate_limiting_method,
+        );
+
+        let receiver = ReceiveCache::new(config.interface.clone());
+
+        let send_loop = SendLoop::new(
+            config.batch_size,
+            config.instance_id,
+            config.min_ttl,
+            config.max_ttl,
+            config.max_probes,
+            config.packets,
+            allowed_prefixes,
+            blocked_prefixes,
+            rate_limiter,
+            Sender::new(&config.interface, config.instance_id, config.dry_run)?,
+        );
+
+        Ok(Self {
+            interface: config.interface,
+            receiver: Some(receiver),
+            send_loop,
+            probing_rate: config.probing_rate,
+            receiver_wait_time: config.receiver_wait_time,
+            round: 0,
+            sent: HashMap::new(),
+            late_replies: vec![],
+            events: None,
+        })
+    }
+
+    /// Sends a [`TraceEvent::ProbesSent`] event to `events` once the probes of a round are sent.
+    pub fn with_events(mut self, events: mpsc::Sender<TraceEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Sends a round of probes and waits until each of them is answered or timed out.
+    /// Late replies to the probes of previous rounds are kept aside, see [`Prober::late_replies`].
+    pub fn probe_round(&mut self, probes: Vec<Probe>) -> Result<RoundReplies> {
+        let interface = &self.interface;
+        let receiver = self
+            .receiver
+            .get_or_insert_with(|| ReceiveCache::new(interface.clone()));
+        self.round += 1;
+        for probe in &probes {
+            self.sent.insert(probe_key(probe), (self.round, None));
+        }
+
+        let mut outstanding = OutstandingProbes::new(
+            &probes,
+            Instant::now(),
+            self.probing_rate,
+            self.receiver_wait_time,
+        );
+
+        let n_probes = probes.len();
+        self.send_loop.probe(probes.into_iter())?;
+        if let Some(events) = &self.events {
+            let _ = events.send(TraceEvent::ProbesSent { probes: n_probes });
+        }
+        info!(
+            "Waiting up to {:?} for {} outstanding probes...",
+            self.receiver_wait_time,
+            outstanding.len()
+        );
+
+        let round = receiver.collect(&mut outstanding);
+        debug!(
+            "{} replies, {} late replies, {} probes timed out",
+            round.replies.len(),
+            round.late_replies.len(),
+            round.timed_out
+        );
+        self.attribute(&round.late_replies);
+
+        let now = Instant::now();
+        self.sent
+            .retain(|_, (_, end)| now.duration_since(*end.get_or_insert(now)) < LATE_REPLY_WINDOW);
+
+        Ok(round)
+    }
+
+    fn attribute(&mut self, replies: &[Reply]) {
+        for reply in replies {
+            match self.sent.get(&reply_key(reply)) {
+                Some(&(round, _)) => self.late_replies.push((round, reply.clone())),
+                None => debug!("dropping unsolicited reply from {}", reply.reply_src_addr),
+            }
+        }
+    }
+
+    /// Stops the receiver. Replies received since the last round are returned as late replies.
+    /// The receiver is started again if more probes are sent.
+    pub fn close(&mut self) -> Vec<(u32, Reply)> {
+        if let Some(mut receiver) = self.receiver.take() {
+            let replies = receiver.stop();
+            self.attribute(&replies);
+        }
+        mem::take(&mut self.late_replies)
+    }
+}
+
+impl Prober for ProbingSession {
+    fn probe(&mut self, probes: Vec<Probe>) -> Result<Vec<Reply>> {
+        Ok(self.probe_round(probes)?.replies)
+    }
+
+    fn late_replies(&mut self) -> Vec<(u32, Reply)> {
+        mem::take(&mut self.late_replies)
+    }
+
+    fn close(&mut self) -> Vec<(u32, Reply)> {
+        ProbingSession::close(self)
+    }
+}
+
+/// Sends a single batch of probes in a short-lived session and waits for their replies.
+pub fn probe(config: Config, probes: Vec<Probe>) -> Result<RoundReplies> {
+    let mut session = ProbingSession::new(config)?;
+    let mut round = session.probe_round(probes)?;
+    round.late_replies = session
+        .close()
+        .into_iter()
+        .map(|(_, reply)| reply)
+        .collect();
+    Ok(round)
 }
@@ -1,54 +1,194 @@
 use std::{
-    sync::{Arc, Mutex},
+    collections::{HashMap, HashSet, VecDeque},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use caracat::{models::Reply, receiver::Receiver};
+use caracat::{
+    models::{Probe, Reply},
+    receiver::Receiver,
+};
+use log::warn;
+
+use crate::types::{Port, TTL};
+
+/// Identifies the probe a reply answers: (destination address, source port, TTL).
+pub type ProbeKey = (IpAddr, Port, TTL);
+
+pub fn probe_key(probe: &Probe) -> ProbeKey {
+    (probe.dst_addr, probe.src_port, probe.ttl)
+}
+
+pub fn reply_key(reply: &Reply) -> ProbeKey {
+    (reply.probe_dst_addr, reply.probe_src_port, reply.probe_ttl)
+}
+
+/// The probes of a round that are still waiting for a reply, each with its own deadline.
+/// Probes sent several times in a round, e.g. by Paris traceroute, wait for as many replies.
+pub struct OutstandingProbes {
+    // deadlines of the probes with each key, in emission order
+    deadlines: HashMap<ProbeKey, VecDeque<Instant>>,
+    answered: HashSet<ProbeKey>,
+}
+
+impl OutstandingProbes {
+    /// Tracks `probes`, sent in order at `probing_rate` packets per second from `start`.
+    /// Each probe times out `timeout` after its own (estimated) emission time.
+    pub fn new(probes: &[Probe], start: Instant, probing_rate: u64, timeout: Duration) -> Self {
+        let interval = Duration::from_secs_f64(1.0 / probing_rate.max(1) as f64);
+        let mut deadlines: HashMap<ProbeKey, VecDeque<Instant>> = HashMap::new();
+        for (i, probe) in probes.iter().enumerate() {
+            deadlines
+                .entry(probe_key(probe))
+                .or_default()
+                .push_back(start + interval * i as u32 + timeout);
+        }
+        Self {
+            deadlines,
+            answered: HashSet::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deadlines.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.deadlines.values().map(VecDeque::len).sum()
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadlines
+            .values()
+            .filter_map(|d| d.front())
+            .min()
+            .copied()
+    }
+
+    /// Marks the first probe still waiting among the ones answered by `reply` as done.
+    /// Returns false if the reply does not answer a probe of the round that is still waiting,
+    /// or a probe that was already answered (duplicates).
+    pub fn match_reply(&mut self, reply: &Reply) -> bool {
+        let key = reply_key(reply);
+        if let Some(deadlines) = self.deadlines.get_mut(&key) {
+            deadlines.pop_front();
+            if deadlines.is_empty() {
+                self.deadlines.remove(&key);
+            }
+            self.answered.insert(key);
+            return true;
+        }
+        self.answered.contains(&key)
+    }
+
+    /// Gives up on the probes whose deadline is past, and returns how many there were.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let before = self.len();
+        self.deadlines.retain(|_, deadlines| {
+            deadlines.retain(|deadline| *deadline > now);
+            !deadlines.is_empty()
+        });
+        before - self.len()
+    }
+}
+
+/// The outcome of waiting for the replies of a round.
+#[derive(Debug, Default)]
+pub struct RoundReplies {
+    /// Replies to the probes of the round, received before their deadline.
+    pub replies: Vec<Reply>,
+    /// Replies that arrived after the deadline of their probe, or that do not match a probe of the round.
+    pub late_replies: Vec<Reply>,
+    /// Number of probes of the round that did not get a reply before their deadline.
+    pub timed_out: usize,
+}
 
-#[allow(dead_code)]
 pub struct ReceiveCache {
-    handle: JoinHandle<()>,
-    stopped: Arc<Mutex<bool>>,
-    pub replies: Arc<Mutex<Vec<Reply>>>,
+    handle: Option<JoinHandle<()>>,
+    stopped: Arc<AtomicBool>,
+    replies: mpsc::Receiver<Reply>,
 }
 
 impl ReceiveCache {
     pub fn new(interface: String) -> Self {
-        let stopped = Arc::new(Mutex::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
         let stopped_thr = stopped.clone();
-        let replies = Arc::new(Mutex::new(Vec::new()));
-        let replies_thread = replies.clone();
+        let (sender, replies) = mpsc::channel();
 
         let handle = thread::spawn(move || {
             let mut receiver = Receiver::new_batch(&interface).unwrap();
 
-            loop {
-                let reply = receiver.next_reply();
-                if let Ok(reply) = reply {
-                    replies_thread.lock().unwrap().push(reply);
-                }
-
-                if *stopped_thr.lock().unwrap() {
-                    break;
+            // next_reply returns periodically even without traffic, so the flag is checked often
+            while !stopped_thr.load(Ordering::Relaxed) {
+                if let Ok(reply) = receiver.next_reply() {
+                    if sender.send(reply).is_err() {
+                        break;
+                    }
                 }
             }
         });
 
         ReceiveCache {
-            handle,
+            handle: Some(handle),
             stopped,
             replies,
         }
     }
 
+    /// Waits for the replies to the `outstanding` probes.
+    /// Returns as soon as every probe is either answered or timed out.
+    pub fn collect(&self, outstanding: &mut OutstandingProbes) -> RoundReplies {
+        let mut round = RoundReplies::default();
+        loop {
+            round.timed_out += outstanding.expire(Instant::now());
+            let Some(deadline) = outstanding.next_deadline() else {
+                break;
+            };
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.replies.recv_timeout(timeout) {
+                Ok(reply) => {
+                    if outstanding.match_reply(&reply) {
+                        round.replies.push(reply);
+                    } else {
+                        round.late_replies.push(reply);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    warn!("receiver thread stopped before the end of the round");
+                    round.timed_out += outstanding.len();
+                    break;
+                }
+            }
+        }
+        round
+    }
+
+    /// Stops and joins the receiver thread, and returns the replies received in the meantime.
     pub fn stop(&mut self) -> Vec<Reply> {
-        *self.stopped.lock().unwrap() = true;
-        //  // Wait for the thread to finish
-        //  if let Some(handle) = self.handle.take() {
-        //     handle.join().unwrap();
-        // }
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                warn!("receiver thread panicked");
+            }
+        }
 
-        // Drain the replies vector
-        self.replies.lock().unwrap().drain(..).collect()
+        // Drain the replies channel
+        self.replies.try_iter().collect()
     }
 }
+
+impl Drop for ReceiveCache {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,79 @@
+use caracat::models::L4;
+
+use crate::helpers::reply;
+
+use super::*;
+
+const DEST: [&str; 3] = ["192.170.0.2", "192.170.0.3", "192.170.0.4"];
+
+fn probe(dst_addr: &str, ttl: TTL) -> Probe {
+    Probe {
+        dst_addr: dst_addr.parse().unwrap(),
+        src_port: 0,
+        dst_port: 33434,
+        ttl,
+        protocol: L4::ICMP,
+    }
+}
+
+#[test]
+fn test_outstanding_probes_matching() {
+    let probes = vec![probe(DEST[0], 1), probe(DEST[1], 1), probe(DEST[0], 2)];
+    let mut outstanding =
+        OutstandingProbes::new(&probes, Instant::now(), 100, Duration::from_secs(1));
+    assert_eq!(outstanding.len(), 3);
+
+    assert!(outstanding.match_reply(&reply(1, "192.168.0.2", DEST[0])));
+    assert_eq!(outstanding.len(), 2);
+
+    // a duplicate reply still belongs to the round
+    assert!(outstanding.match_reply(&reply(1, "192.168.0.2", DEST[0])));
+    assert_eq!(outstanding.len(), 2);
+
+    // a reply to a probe that was not sent in this round
+    assert!(!outstanding.match_reply(&reply(3, "192.168.0.3", DEST[0])));
+    assert!(!outstanding.match_reply(&reply(1, "192.168.0.3", DEST[2])));
+
+    assert!(outstanding.match_reply(&reply(1, "192.168.0.2", DEST[1])));
+    assert!(outstanding.match_reply(&reply(2, "192.168.0.3", DEST[0])));
+    assert!(outstanding.is_empty());
+}
+
+#[test]
+fn test_outstanding_probes_deadlines() {
+    let start = Instant::now();
+    // 10 probes per second, the last probe is sent 200ms after the first one
+    let probes = vec![probe(DEST[0], 1), probe(DEST[1], 1), probe(DEST[2], 1)];
+    let mut outstanding = OutstandingProbes::new(&probes, start, 10, Duration::from_secs(1));
+
+    assert_eq!(
+        outstanding.next_deadline(),
+        Some(start + Duration::from_secs(1))
+    );
+
+    assert_eq!(outstanding.expire(start + Duration::from_millis(500)), 0);
+    assert_eq!(outstanding.expire(start + Duration::from_millis(1150)), 2);
+    assert_eq!(outstanding.len(), 1);
+
+    // the reply to an expired probe is late
+    assert!(!outstanding.match_reply(&reply(1, "192.168.0.2", DEST[0])));
+    assert!(outstanding.match_reply(&reply(1, "192.168.0.2", DEST[2])));
+    assert!(outstanding.is_empty());
+    assert_eq!(outstanding.next_deadline(), None);
+}
+
+#[test]
+fn test_outstanding_probes_repeated() {
+    // the same probe sent three times, as Paris traceroute does
+    let probes = vec![probe(DEST[0], 1); 3];
+    let mut outstanding =
+        OutstandingProbes::new(&probes, Instant::now(), 100, Duration::from_secs(1));
+    assert_eq!(outstanding.len(), 3);
+
+    assert!(outstanding.match_reply(&reply(1, "192.168.0.2", DEST[0])));
+    assert_eq!(outstanding.len(), 2);
+    assert!(!outstanding.is_empty());
+    assert!(outstanding.match_reply(&reply(1, "192.168.0.2", DEST[0])));
+    assert!(outstanding.match_reply(&reply(1, "192.168.0.2", DEST[0])));
+    assert!(outstanding.is_empty());
+}
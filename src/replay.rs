@@ -0,0 +1,76 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use caracat::models::{Probe, Reply};
+
+use crate::probe::Prober;
+use crate::receiver::{probe_key, reply_key, ProbeKey};
+use crate::reply_dump::ReplyRow;
+
+mod pcap;
+
+/// Reads the replies of a caracat CSV file, with a header line,
+/// or of a pcap or pcapng capture of the replies to caracat probes.
+pub fn read_replies<R: Read>(reader: R) -> Result<Vec<Reply>> {
+    let mut reader = BufReader::new(reader);
+    if reader.fill_buf()?.get(..4).is_some_and(pcap::is_capture) {
+        let mut capture = vec![];
+        reader.read_to_end(&mut capture)?;
+        return pcap::read_capture(&capture);
+    }
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut replies = vec![];
+    for (line, row) in reader.deserialize::<ReplyRow>().enumerate() {
+        // line 1 is the header
+        let row = row.with_context(|| format!("invalid reply at line {}", line + 2))?;
+        replies.push(row.into_reply()?);
+    }
+    Ok(replies)
+}
+
+/// Answers probes with the replies of a previous measurement instead of sending them,
+/// to run an algorithm again offline with other parameters.
+/// Probes that were not sent in the original measurement get no reply.
+pub struct ReplayProber {
+    // several replies to the same probe are handed out one at a time, in capture order
+    replies: HashMap<ProbeKey, VecDeque<Reply>>,
+}
+
+impl ReplayProber {
+    pub fn new(replies: Vec<Reply>) -> Self {
+        let mut by_probe: HashMap<ProbeKey, VecDeque<Reply>> = HashMap::new();
+        for reply in replies {
+            by_probe
+                .entry(reply_key(&reply))
+                .or_default()
+                .push_back(reply);
+        }
+        Self { replies: by_probe }
+    }
+
+    /// Replays the replies of a caracat CSV file or of a packet capture.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("cannot open {:?}", path))?;
+        Ok(Self::new(read_replies(file)?))
+    }
+
+    /// Number of replies that were not handed out yet.
+    pub fn n_remaining(&self) -> usize {
+        self.replies.values().map(|replies| replies.len()).sum()
+    }
+}
+
+impl Prober for ReplayProber {
+    fn probe(&mut self, probes: Vec<Probe>) -> Result<Vec<Reply>> {
+        Ok(probes
+            .iter()
+            .filter_map(|probe| self.replies.get_mut(&probe_key(probe))?.pop_front())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests;
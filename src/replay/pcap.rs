@@ -0,0 +1,421 @@
+use std::net::IpAddr;
+
+use anyhow::{bail, Context, Result};
+use caracat::models::{MPLSLabel, Reply};
+use pnet::packet::icmp::IcmpTypes;
+use pnet::packet::icmpv6::Icmpv6Types;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::ipv6::Ipv6Packet;
+use pnet::util::checksum;
+
+// first bytes of pcap files (microsecond and nanosecond timestamps, both byte orders)
+const PCAP_MAGIC: [[u8; 4]; 4] = [
+    [0xd4, 0xc3, 0xb2, 0xa1],
+    [0xa1, 0xb2, 0xc3, 0xd4],
+    [0x4d, 0x3c, 0xb2, 0xa1],
+    [0xa1, 0xb2, 0x3c, 0x4d],
+];
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_TSRESOL_OPTION: u16 = 9;
+
+const LINKTYPE_NULL: u16 = 0;
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_IPV4: u16 = 228;
+const LINKTYPE_IPV6: u16 = 229;
+const LINKTYPE_LINUX_SLL2: u16 = 276;
+
+// caracat pads its probes with as many bytes as their TTL, plus two bytes that set the checksum
+const PAYLOAD_TWEAK_BYTES: usize = 2;
+
+/// Whether `magic`, the first bytes of a file, are the ones of a pcap or pcapng capture.
+pub(super) fn is_capture(magic: &[u8]) -> bool {
+    PCAP_MAGIC.iter().any(|pcap| pcap == magic) || magic == PCAPNG_SECTION_HEADER.to_le_bytes()
+}
+
+/// Rebuilds the replies to caracat probes from the ICMP packets of a pcap or pcapng capture,
+/// as caracat does when it captures them: the probes carry their TTL in the length of their
+/// payload, their flow in the ICMP checksum or UDP source port, and their send time, in tenths
+/// of milliseconds, in the ICMP sequence number or UDP checksum.
+/// Other packets are skipped.
+pub(super) fn read_capture(data: &[u8]) -> Result<Vec<Reply>> {
+    if data.get(..4) == Some(&PCAPNG_SECTION_HEADER.to_le_bytes()) {
+        read_pcapng(data)
+    } else {
+        read_pcap(data)
+    }
+}
+
+// reads integers in the byte order of the capture
+struct Bytes<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Bytes<'_> {
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+fn read_pcap(data: &[u8]) -> Result<Vec<Reply>> {
+    let magic = PCAP_MAGIC
+        .iter()
+        .position(|pcap| data.get(..4) == Some(pcap))
+        .context("not a pcap capture")?;
+    let bytes = Bytes {
+        data,
+        big_endian: magic % 2 == 1,
+    };
+    let nanoseconds = magic >= 2;
+    let linktype = bytes.u32(20).context("truncated pcap header")? as u16;
+    check_linktype(linktype)?;
+
+    let mut replies = vec![];
+    let mut offset = 24;
+    while offset < data.len() {
+        let (seconds, fraction, length) = bytes
+            .u32(offset)
+            .zip(bytes.u32(offset + 4))
+            .zip(bytes.u32(offset + 8))
+            .map(|((seconds, fraction), length)| (seconds, fraction, length as usize))
+            .context("truncated pcap record")?;
+        let packet = data
+            .get(offset + 16..offset + 16 + length)
+            .context("truncated pcap record")?;
+        let fraction = if nanoseconds {
+            fraction / 1000
+        } else {
+            fraction
+        };
+        let timestamp = seconds as u64 * 1_000_000 + fraction as u64;
+        replies.extend(parse_packet(linktype, packet, timestamp));
+        offset += 16 + length;
+    }
+    Ok(replies)
+}
+
+fn read_pcapng(data: &[u8]) -> Result<Vec<Reply>> {
+    let mut big_endian = false;
+    // link type and timestamp units per second of the interfaces of the current section
+    let mut interfaces: Vec<(u16, u64)> = vec![];
+    let mut replies = vec![];
+    let mut offset = 0;
+    while offset < data.len() {
+        let block_type = Bytes { data, big_endian }
+            .u32(offset)
+            .context("truncated pcapng block")?;
+        if block_type == PCAPNG_SECTION_HEADER {
+            big_endian = match data.get(offset + 8..offset + 12) {
+                Some([0x1a, 0x2b, 0x3c, 0x4d]) => true,
+                Some([0x4d, 0x3c, 0x2b, 0x1a]) => false,
+                _ => bail!("invalid pcapng section header"),
+            };
+            interfaces.clear();
+        }
+        let length = Bytes { data, big_endian }
+            .u32(offset + 4)
+            .context("truncated pcapng block")? as usize;
+        let block = Bytes {
+            data: data
+                .get(offset..offset + length)
+                .filter(|_| length >= 12)
+                .context("truncated pcapng block")?,
+            big_endian,
+        };
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                let linktype = block.u16(8).context("truncated pcapng interface")?;
+                check_linktype(linktype)?;
+                interfaces.push((linktype, timestamp_units(&block)));
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let (interface, high, low, captured) = block
+                    .u32(8)
+                    .zip(block.u32(12))
+                    .zip(block.u32(16))
+                    .zip(block.u32(20))
+                    .map(|(((interface, high), low), captured)| {
+                        (interface as usize, high, low, captured as usize)
+                    })
+                    .context("truncated pcapng packet")?;
+                let &(linktype, units) = interfaces
+                    .get(interface)
+                    .context("pcapng packet on an undescribed interface")?;
+                let packet = block
+                    .data
+                    .get(28..28 + captured)
+                    .context("truncated pcapng packet")?;
+                let timestamp = ((high as u64) << 32) | low as u64;
+                let timestamp = (timestamp as u128 * 1_000_000 / units as u128) as u64;
+                replies.extend(parse_packet(linktype, packet, timestamp));
+            }
+            // without a timestamp, on the first interface
+            PCAPNG_SIMPLE_PACKET => {
+                let &(linktype, _) = interfaces
+                    .first()
+                    .context("pcapng packet on an undescribed interface")?;
+                let original = block.u32(8).context("truncated pcapng packet")? as usize;
+                let packet = &block.data[12.min(length)..(12 + original).min(length - 4)];
+                replies.extend(parse_packet(linktype, packet, 0));
+            }
+            _ => {}
+        }
+        offset += length;
+    }
+    Ok(replies)
+}
+
+// units per second of the timestamps of an interface description block, from its options
+fn timestamp_units(block: &Bytes) -> u64 {
+    let mut offset = 16;
+    while let Some((code, length)) = block.u16(offset).zip(block.u16(offset + 2)) {
+        if code == PCAPNG_TSRESOL_OPTION {
+            return match block.data.get(offset + 4) {
+                Some(&resolution) if resolution & 0x80 != 0 => 1 << (resolution & 0x7f).min(63),
+                Some(&resolution) => 10u64.pow(resolution.min(19) as u32),
+                None => break,
+            };
+        }
+        if code == 0 {
+            break;
+        }
+        // options are padded to 32 bits
+        offset += 4 + (length as usize).div_ceil(4) * 4;
+    }
+    1_000_000
+}
+
+fn check_linktype(linktype: u16) -> Result<()> {
+    match linktype {
+        LINKTYPE_NULL | LINKTYPE_ETHERNET | LINKTYPE_RAW | LINKTYPE_LINUX_SLL | LINKTYPE_IPV4
+        | LINKTYPE_IPV6 | LINKTYPE_LINUX_SLL2 => Ok(()),
+        _ => bail!("unsupported capture link type {}", linktype),
+    }
+}
+
+// the IP packet of a captured frame
+fn ip_packet(linktype: u16, frame: &[u8]) -> Option<&[u8]> {
+    match linktype {
+        LINKTYPE_NULL => frame.get(4..),
+        LINKTYPE_ETHERNET => {
+            let mut start = 12;
+            // VLAN tags
+            while matches!(
+                u16::from_be_bytes(frame.get(start..start + 2)?.try_into().ok()?),
+                0x8100 | 0x88a8
+            ) {
+                start += 4;
+            }
+            frame.get(start + 2..)
+        }
+        LINKTYPE_LINUX_SLL => frame.get(16..),
+        LINKTYPE_LINUX_SLL2 => frame.get(20..),
+        _ => Some(frame),
+    }
+}
+
+fn parse_packet(linktype: u16, frame: &[u8], timestamp: u64) -> Option<Reply> {
+    let packet = ip_packet(linktype, frame)?;
+    match packet.first()? >> 4 {
+        4 => parse_ipv4(packet, timestamp),
+        6 => parse_ipv6(packet, timestamp),
+        _ => None,
+    }
+}
+
+fn parse_ipv4(packet: &[u8], timestamp: u64) -> Option<Reply> {
+    let outer = Ipv4Packet::new(packet)?;
+    if outer.get_next_level_protocol() != IpNextHeaderProtocols::Icmp {
+        return None;
+    }
+    let header_length = outer.get_header_length() as usize * 4;
+    let icmp = packet.get(header_length..(outer.get_total_length() as usize).min(packet.len()))?;
+    let mut reply = Reply {
+        capture_timestamp: timestamp,
+        reply_src_addr: IpAddr::V4(outer.get_source()),
+        reply_dst_addr: IpAddr::V4(outer.get_destination()),
+        reply_id: outer.get_identification(),
+        reply_size: outer.get_total_length(),
+        reply_ttl: outer.get_ttl(),
+        reply_protocol: IpNextHeaderProtocols::Icmp.0,
+        reply_icmp_type: *icmp.first()?,
+        reply_icmp_code: *icmp.get(1)?,
+        ..Default::default()
+    };
+
+    if reply.reply_icmp_type == IcmpTypes::EchoReply.0 {
+        // the probe came back as it was sent
+        reply.probe_src_addr = reply.reply_dst_addr;
+        reply.probe_dst_addr = reply.reply_src_addr;
+        reply.probe_protocol = IpNextHeaderProtocols::Icmp.0;
+        parse_probe(&mut reply, icmp, icmp.len().checked_sub(8)?)?;
+        // the type went from 8 to 0, which added 0x0800 to the checksum
+        reply.probe_src_port = ones_complement_add(reply.probe_src_port, !0x0800);
+        return Some(reply);
+    }
+    if reply.reply_icmp_type != IcmpTypes::TimeExceeded.0
+        && reply.reply_icmp_type != IcmpTypes::DestinationUnreachable.0
+    {
+        return None;
+    }
+
+    let quoted = Ipv4Packet::new(icmp.get(8..)?)?;
+    let quoted_header_length = quoted.get_header_length() as usize * 4;
+    reply.probe_src_addr = IpAddr::V4(quoted.get_source());
+    reply.probe_dst_addr = IpAddr::V4(quoted.get_destination());
+    reply.probe_id = quoted.get_identification();
+    reply.probe_size = quoted.get_total_length();
+    reply.probe_protocol = quoted.get_next_level_protocol().0;
+    reply.quoted_ttl = quoted.get_ttl();
+    let payload_length =
+        (quoted.get_total_length() as usize).checked_sub(quoted_header_length + 8)?;
+    parse_probe(
+        &mut reply,
+        icmp.get(8 + quoted_header_length..)?,
+        payload_length,
+    )?;
+    // the length of the quoted datagram is given in 32-bit words
+    reply.reply_mpls_labels = mpls_labels(icmp, *icmp.get(5)? as usize * 4);
+    Some(reply)
+}
+
+fn parse_ipv6(packet: &[u8], timestamp: u64) -> Option<Reply> {
+    let outer = Ipv6Packet::new(packet)?;
+    if outer.get_next_header() != IpNextHeaderProtocols::Icmpv6 {
+        return None;
+    }
+    let icmp = packet.get(40..(40 + outer.get_payload_length() as usize).min(packet.len()))?;
+    let mut reply = Reply {
+        capture_timestamp: timestamp,
+        reply_src_addr: IpAddr::V6(outer.get_source()),
+        reply_dst_addr: IpAddr::V6(outer.get_destination()),
+        reply_size: 40 + outer.get_payload_length(),
+        reply_ttl: outer.get_hop_limit(),
+        reply_protocol: IpNextHeaderProtocols::Icmpv6.0,
+        reply_icmp_type: *icmp.first()?,
+        reply_icmp_code: *icmp.get(1)?,
+        ..Default::default()
+    };
+
+    if reply.reply_icmp_type == Icmpv6Types::EchoReply.0 {
+        reply.probe_src_addr = reply.reply_dst_addr;
+        reply.probe_dst_addr = reply.reply_src_addr;
+        reply.probe_protocol = IpNextHeaderProtocols::Icmpv6.0;
+        parse_probe(&mut reply, icmp, icmp.len().checked_sub(8)?)?;
+        // the type went from 128 to 129, which took 0x0100 from the checksum
+        reply.probe_src_port = ones_complement_add(reply.probe_src_port, 0x0100);
+        return Some(reply);
+    }
+    if reply.reply_icmp_type != Icmpv6Types::TimeExceeded.0
+        && reply.reply_icmp_type != Icmpv6Types::DestinationUnreachable.0
+    {
+        return None;
+    }
+
+    let quoted = Ipv6Packet::new(icmp.get(8..)?)?;
+    reply.probe_src_addr = IpAddr::V6(quoted.get_source());
+    reply.probe_dst_addr = IpAddr::V6(quoted.get_destination());
+    reply.probe_size = 40 + quoted.get_payload_length();
+    reply.probe_protocol = quoted.get_next_header().0;
+    reply.quoted_ttl = quoted.get_hop_limit();
+    let payload_length = (quoted.get_payload_length() as usize).checked_sub(8)?;
+    parse_probe(&mut reply, icmp.get(8 + 40..)?, payload_length)?;
+    // the length of the quoted datagram is given in 64-bit words
+    reply.reply_mpls_labels = mpls_labels(icmp, *icmp.get(4)? as usize * 8);
+    Some(reply)
+}
+
+// the fields caracat encodes in the first 8 bytes of the ICMP or UDP header of a probe,
+// whose payload after this header is `payload_length` bytes long
+fn parse_probe(reply: &mut Reply, header: &[u8], payload_length: usize) -> Option<()> {
+    let field = |offset: usize| -> Option<u16> {
+        Some(u16::from_be_bytes(
+            header.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    };
+    reply.probe_ttl = u8::try_from(payload_length.checked_sub(PAYLOAD_TWEAK_BYTES)?).ok()?;
+    if reply.probe_protocol == IpNextHeaderProtocols::Udp.0 {
+        reply.probe_src_port = field(0)?;
+        reply.probe_dst_port = field(2)?;
+    } else if reply.probe_protocol == IpNextHeaderProtocols::Icmp.0
+        || reply.probe_protocol == IpNextHeaderProtocols::Icmpv6.0
+    {
+        reply.probe_src_port = field(2)?;
+    } else {
+        return None;
+    }
+    // the ICMP sequence number or the UDP checksum, in tenths of milliseconds modulo 2^16
+    let sent = field(6)?;
+    reply.rtt = ((reply.capture_timestamp / 100) as u16).wrapping_sub(sent);
+    Some(())
+}
+
+fn ones_complement_add(a: u16, b: u16) -> u16 {
+    let (sum, carry) = a.overflowing_add(b);
+    sum + carry as u16
+}
+
+// the labels of the MPLS extension object of an ICMP message, if any (RFC 4950), after
+// the original datagram of `quoted_length` bytes; routers that do not give this length
+// put the extension structure right after 128 bytes (RFC 4884)
+fn mpls_labels(icmp: &[u8], quoted_length: usize) -> Vec<MPLSLabel> {
+    let start = 8 + if quoted_length > 0 {
+        quoted_length
+    } else {
+        128
+    };
+    let extension = match icmp.get(start..) {
+        Some(extension) if extension.len() >= 4 && extension[0] >> 4 == 2 => extension,
+        _ => return vec![],
+    };
+    // a full quote of the probe can also be longer than 128 bytes
+    let stored = u16::from_be_bytes([extension[2], extension[3]]);
+    if stored != 0 && checksum(extension, 1) != stored {
+        return vec![];
+    }
+
+    let mut labels = vec![];
+    let mut objects = &extension[4..];
+    while objects.len() >= 4 {
+        let length = u16::from_be_bytes([objects[0], objects[1]]) as usize;
+        if length < 4 || length > objects.len() {
+            break;
+        }
+        // class 1, type 1: an MPLS label stack
+        if objects[2] == 1 && objects[3] == 1 {
+            for entry in objects[4..length].chunks_exact(4) {
+                let entry = u32::from_be_bytes(entry.try_into().unwrap());
+                labels.push(MPLSLabel {
+                    label: entry >> 12,
+                    experimental: ((entry >> 9) & 0b111) as u8,
+                    bottom_of_stack: (entry >> 8) & 1 == 1,
+                    ttl: entry as u8,
+                });
+            }
+        }
+        objects = &objects[length..];
+    }
+    labels
+}
@@ -0,0 +1,114 @@
+use crate::helpers::{addr, links, miner, prober, run, DEST, IP, SRC};
+
+use super::*;
+
+const CSV: &str = "\
+capture_timestamp,probe_protocol,probe_src_addr,probe_dst_addr,probe_src_port,probe_dst_port,probe_ttl,quoted_ttl,reply_src_addr,reply_protocol,reply_icmp_type,reply_icmp_code,reply_ttl,reply_size,reply_mpls_labels,rtt,round
+1700000000000000,1,::ffff:10.0.0.1,::ffff:192.170.0.2,24000,33434,1,1,::ffff:192.168.0.2,1,11,0,254,56,[],10,1
+1700000000001000,17,::ffff:10.0.0.1,::ffff:192.170.0.2,24001,33434,2,1,::ffff:192.168.0.3,1,11,0,253,56,\"[(24005, 0, 1, 1)]\",20,1
+1700000000002000,58,2001:db8::1,2001:db8::2,24000,33434,1,1,2001:db8::3,58,3,0,63,56,[],5,1
+";
+
+const PCAP: &[u8] = include_bytes!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/replies.pcap"
+));
+const PCAPNG: &[u8] = include_bytes!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/replies.pcapng"
+));
+
+#[test]
+fn test_read_replies() {
+    let replies = read_replies(CSV.as_bytes()).unwrap();
+    assert_eq!(replies.len(), 3);
+
+    assert_eq!(replies[0].probe_dst_addr, addr(DEST));
+    assert_eq!(replies[0].reply_src_addr, addr(IP[0]));
+    assert_eq!(replies[0].reply_dst_addr, addr(SRC));
+    assert!(replies[0].is_time_exceeded());
+    assert!(replies[0].reply_mpls_labels.is_empty());
+
+    assert_eq!(replies[1].probe_src_port, 24001);
+    assert_eq!(replies[1].rtt, 20);
+    assert_eq!(replies[1].reply_mpls_labels.len(), 1);
+    assert_eq!(replies[1].reply_mpls_labels[0].label, 24005);
+    assert!(replies[1].reply_mpls_labels[0].bottom_of_stack);
+
+    assert_eq!(replies[2].reply_src_addr, addr("2001:db8::3"));
+    assert!(replies[2].is_time_exceeded());
+
+    assert!(read_replies("capture_timestamp\nnot a number\n".as_bytes()).is_err());
+    // the start of the global header of a pcap file
+    let pcap = [0xd4, 0xc3, 0xb2, 0xa1, 0x02, 0x00, 0x04, 0x00];
+    let err = read_replies(&pcap[..]).unwrap_err();
+    assert!(err.to_string().contains("pcap"));
+}
+
+#[test]
+fn test_read_capture() {
+    // an Ethernet pcap capture: a time exceeded with an MPLS extension, an echo reply, and an ARP
+    // packet, captured 20 ms after their probes
+    let replies = read_replies(PCAP).unwrap();
+    assert_eq!(replies.len(), 2);
+
+    assert_eq!(replies[0].capture_timestamp, 1700000000001000);
+    assert_eq!(replies[0].reply_src_addr, addr(IP[0]));
+    assert_eq!(replies[0].reply_dst_addr, addr(SRC));
+    assert!(replies[0].is_time_exceeded());
+    assert_eq!(replies[0].probe_dst_addr, addr(DEST));
+    assert_eq!(replies[0].probe_protocol, 17);
+    assert_eq!(replies[0].probe_src_port, 24000);
+    assert_eq!(replies[0].probe_dst_port, 33434);
+    assert_eq!(replies[0].probe_ttl, 3);
+    assert_eq!(replies[0].quoted_ttl, 1);
+    assert_eq!(replies[0].rtt, 200);
+    assert_eq!(replies[0].reply_mpls_labels.len(), 1);
+    assert_eq!(replies[0].reply_mpls_labels[0].label, 24005);
+    assert!(replies[0].reply_mpls_labels[0].bottom_of_stack);
+
+    assert_eq!(replies[1].reply_src_addr, addr(DEST));
+    assert_eq!(replies[1].reply_icmp_type, 0);
+    assert_eq!(replies[1].probe_dst_addr, addr(DEST));
+    assert_eq!(replies[1].probe_protocol, 1);
+    assert_eq!(replies[1].probe_src_port, 24001);
+    assert_eq!(replies[1].probe_ttl, 6);
+    assert_eq!(replies[1].rtt, 200);
+    assert!(replies[1].reply_mpls_labels.is_empty());
+
+    // a big-endian pcapng capture of raw IPv6 packets with nanosecond timestamps
+    let replies = read_replies(PCAPNG).unwrap();
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0].capture_timestamp, 1700000000001000);
+    assert_eq!(replies[0].reply_src_addr, addr("2001:db8::3"));
+    assert!(replies[0].is_time_exceeded());
+    assert_eq!(replies[0].probe_dst_addr, addr("2001:db8::2"));
+    assert_eq!(replies[0].probe_protocol, 58);
+    assert_eq!(replies[0].probe_src_port, 24000);
+    assert_eq!(replies[0].probe_ttl, 2);
+    assert_eq!(replies[0].rtt, 200);
+
+    // a capture cut in the middle of a packet
+    let err = read_replies(&PCAP[..PCAP.len() - 1]).unwrap_err();
+    assert!(err.to_string().contains("truncated"));
+}
+
+#[test]
+fn test_replay() {
+    let mut simulator = prober();
+    let measured = run(&mut simulator, miner(99.0));
+    let capture: Vec<Reply> = measured.replies().into_iter().cloned().collect();
+
+    // the same parameters replay the whole measurement
+    let mut replay = ReplayProber::new(capture.clone());
+    let replayed = run(&mut replay, miner(99.0));
+    assert_eq!(links(&replayed), links(&measured));
+    assert_eq!(replayed.replies().len(), measured.replies().len());
+    assert_eq!(replay.n_remaining(), 0);
+
+    // a lower confidence needs fewer probes, and still finds the diamond here
+    let mut replay = ReplayProber::new(capture);
+    let replayed = run(&mut replay, miner(95.0));
+    assert!(replayed.replies().len() < measured.replies().len());
+    assert_eq!(links(&replayed), links(&measured));
+}
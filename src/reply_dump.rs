@@ -0,0 +1,198 @@
+use std::io::Write;
+use std::net::IpAddr;
+
+use anyhow::{bail, Context, Result};
+use caracat::models::{MPLSLabel, Reply};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// File formats of the reply dumps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplyDumpFormat {
+    /// caracat's CSV format, with a header line.
+    #[default]
+    Csv,
+    /// One JSON object per reply, with the same fields as the CSV columns.
+    JsonLines,
+}
+
+/// A reply with the columns of caracat's CSV output, and the round of its probe.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ReplyRow {
+    capture_timestamp: u64,
+    probe_protocol: u8,
+    probe_src_addr: IpAddr,
+    probe_dst_addr: IpAddr,
+    probe_src_port: u16,
+    probe_dst_port: u16,
+    probe_ttl: u8,
+    quoted_ttl: u8,
+    reply_src_addr: IpAddr,
+    reply_protocol: u8,
+    reply_icmp_type: u8,
+    reply_icmp_code: u8,
+    reply_ttl: u8,
+    reply_size: u16,
+    #[serde(default)]
+    reply_mpls_labels: String,
+    rtt: u16,
+    #[serde(default)]
+    round: u32,
+}
+
+// caracat stores IPv4 addresses as IPv4-mapped IPv6 addresses
+fn map(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => IpAddr::V6(v4.to_ipv6_mapped()),
+        v6 => v6,
+    }
+}
+
+fn unmap(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    }
+}
+
+// labels are written as a list of (label, experimental, bottom of stack, ttl) tuples,
+// e.g. "[(24005, 0, 1, 254), (16, 0, 1, 253)]"
+fn format_mpls_labels(labels: &[MPLSLabel]) -> String {
+    let labels = labels
+        .iter()
+        .map(|label| {
+            format!(
+                "({}, {}, {}, {})",
+                label.label, label.experimental, label.bottom_of_stack as u8, label.ttl
+            )
+        })
+        .join(", ");
+    format!("[{}]", labels)
+}
+
+fn parse_mpls_labels(labels: &str) -> Result<Vec<MPLSLabel>> {
+    let numbers: Vec<u32> = labels
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|number| !number.is_empty())
+        .map(|number| number.parse())
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("invalid MPLS labels {:?}", labels))?;
+    if !numbers.len().is_multiple_of(4) {
+        bail!("invalid MPLS labels {:?}", labels);
+    }
+    Ok(numbers
+        .chunks(4)
+        .map(|label| MPLSLabel {
+            label: label[0],
+            experimental: label[1] as u8,
+            bottom_of_stack: label[2] != 0,
+            ttl: label[3] as u8,
+        })
+        .collect())
+}
+
+impl ReplyRow {
+    pub(crate) fn new(reply: &Reply, round: u32) -> Self {
+        Self {
+            capture_timestamp: reply.capture_timestamp,
+            probe_protocol: reply.probe_protocol,
+            probe_src_addr: map(reply.probe_src_addr),
+            probe_dst_addr: map(reply.probe_dst_addr),
+            probe_src_port: reply.probe_src_port,
+            probe_dst_port: reply.probe_dst_port,
+            probe_ttl: reply.probe_ttl,
+            quoted_ttl: reply.quoted_ttl,
+            reply_src_addr: map(reply.reply_src_addr),
+            reply_protocol: reply.reply_protocol,
+            reply_icmp_type: reply.reply_icmp_type,
+            reply_icmp_code: reply.reply_icmp_code,
+            reply_ttl: reply.reply_ttl,
+            reply_size: reply.reply_size,
+            reply_mpls_labels: format_mpls_labels(&reply.reply_mpls_labels),
+            rtt: reply.rtt,
+            round,
+        }
+    }
+
+    pub(crate) fn into_reply(self) -> Result<Reply> {
+        Ok(Reply {
+            capture_timestamp: self.capture_timestamp,
+            reply_src_addr: unmap(self.reply_src_addr),
+            reply_dst_addr: unmap(self.probe_src_addr),
+            reply_size: self.reply_size,
+            reply_ttl: self.reply_ttl,
+            reply_protocol: self.reply_protocol,
+            reply_icmp_type: self.reply_icmp_type,
+            reply_icmp_code: self.reply_icmp_code,
+            reply_mpls_labels: parse_mpls_labels(&self.reply_mpls_labels)?,
+            probe_src_addr: unmap(self.probe_src_addr),
+            probe_dst_addr: unmap(self.probe_dst_addr),
+            probe_protocol: self.probe_protocol,
+            probe_ttl: self.probe_ttl,
+            probe_src_port: self.probe_src_port,
+            probe_dst_port: self.probe_dst_port,
+            quoted_ttl: self.quoted_ttl,
+            rtt: self.rtt,
+            ..Default::default()
+        })
+    }
+}
+
+enum Writer<W: Write> {
+    // boxed, as the CSV writer holds its own buffer
+    Csv(Box<csv::Writer<W>>),
+    JsonLines(W),
+}
+
+/// Streams the replies of a trace to a file as they are received, each with the round of its probe.
+pub struct ReplyDump<W: Write> {
+    writer: Writer<W>,
+}
+
+impl<W: Write> ReplyDump<W> {
+    pub fn new(writer: W, format: ReplyDumpFormat) -> Self {
+        let writer = match format {
+            ReplyDumpFormat::Csv => Writer::Csv(Box::new(csv::Writer::from_writer(writer))),
+            ReplyDumpFormat::JsonLines => Writer::JsonLines(writer),
+        };
+        Self { writer }
+    }
+
+    /// Writes a reply to a probe sent in `round`. It may be buffered until the next flush.
+    pub fn write(&mut self, round: u32, reply: &Reply) -> Result<()> {
+        let row = ReplyRow::new(reply, round);
+        match &mut self.writer {
+            Writer::Csv(writer) => writer.serialize(row)?,
+            Writer::JsonLines(writer) => {
+                serde_json::to_writer(&mut *writer, &row)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        match &mut self.writer {
+            Writer::Csv(writer) => writer.flush()?,
+            Writer::JsonLines(writer) => writer.flush()?,
+        }
+        Ok(())
+    }
+
+    /// Flushes the dump and returns the underlying writer.
+    pub fn into_inner(self) -> Result<W> {
+        match self.writer {
+            Writer::Csv(writer) => Ok(writer.into_inner().map_err(|err| err.into_error())?),
+            Writer::JsonLines(mut writer) => {
+                writer.flush()?;
+                Ok(writer)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
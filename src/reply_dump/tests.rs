@@ -0,0 +1,90 @@
+use std::net::Ipv4Addr;
+
+use crate::helpers::reply;
+use crate::replay::read_replies;
+
+use super::*;
+
+const SRC: &str = "10.0.0.1";
+const DEST: &str = "192.170.0.2";
+const IP: [&str; 2] = ["192.168.0.2", "192.168.0.3"];
+
+fn replies() -> Vec<Reply> {
+    let src_addr = IpAddr::from(SRC.parse::<Ipv4Addr>().unwrap());
+    vec![
+        Reply {
+            probe_src_addr: src_addr,
+            reply_dst_addr: src_addr,
+            probe_src_port: 24000,
+            rtt: 10,
+            ..reply(1, IP[0], DEST)
+        },
+        Reply {
+            probe_src_addr: src_addr,
+            reply_dst_addr: src_addr,
+            probe_src_port: 24001,
+            reply_mpls_labels: vec![MPLSLabel {
+                label: 24005,
+                experimental: 0,
+                bottom_of_stack: true,
+                ttl: 1,
+            }],
+            ..reply(2, IP[1], DEST)
+        },
+    ]
+}
+
+fn dump(format: ReplyDumpFormat) -> String {
+    let replies = replies();
+    let mut dump = ReplyDump::new(vec![], format);
+    dump.write(1, &replies[0]).unwrap();
+    dump.write(2, &replies[1]).unwrap();
+    String::from_utf8(dump.into_inner().unwrap()).unwrap()
+}
+
+#[test]
+fn test_csv_dump() {
+    let csv = dump(ReplyDumpFormat::Csv);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0],
+        "capture_timestamp,probe_protocol,probe_src_addr,probe_dst_addr,probe_src_port,\
+         probe_dst_port,probe_ttl,quoted_ttl,reply_src_addr,reply_protocol,reply_icmp_type,\
+         reply_icmp_code,reply_ttl,reply_size,reply_mpls_labels,rtt,round"
+    );
+    assert!(lines[1].contains("::ffff:192.168.0.2"));
+    assert!(lines[1].ends_with(",[],10,1"));
+    assert!(lines[2].contains("\"[(24005, 0, 1, 1)]\""));
+
+    // the dump can be replayed
+    let read = read_replies(csv.as_bytes()).unwrap();
+    let expected = replies();
+    assert_eq!(read.len(), expected.len());
+    for (read, expected) in read.iter().zip(&expected) {
+        assert_eq!(read.reply_src_addr, expected.reply_src_addr);
+        assert_eq!(read.reply_dst_addr, expected.reply_dst_addr);
+        assert_eq!(read.probe_dst_addr, expected.probe_dst_addr);
+        assert_eq!(read.probe_src_port, expected.probe_src_port);
+        assert_eq!(read.probe_ttl, expected.probe_ttl);
+        assert_eq!(read.rtt, expected.rtt);
+        assert_eq!(
+            read.reply_mpls_labels.len(),
+            expected.reply_mpls_labels.len()
+        );
+    }
+}
+
+#[test]
+fn test_json_lines_dump() {
+    let json = dump(ReplyDumpFormat::JsonLines);
+    let rows: Vec<serde_json::Value> = json
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["reply_src_addr"], "::ffff:192.168.0.2");
+    assert_eq!(rows[0]["round"], 1);
+    assert_eq!(rows[1]["reply_mpls_labels"], "[(24005, 0, 1, 1)]");
+    assert_eq!(rows[1]["round"], 2);
+}
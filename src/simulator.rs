@@ -0,0 +1,291 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+
+use anyhow::Result;
+use caracat::models::{Probe, Reply, L4};
+use pnet::packet::icmp::IcmpTypes;
+use pnet::packet::icmpv6::Icmpv6Types;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::probe::Prober;
+pub use crate::types::LoadBalancing;
+
+// arbitrary capture time of the first simulated reply, in microseconds
+const BASE_TIMESTAMP: u64 = 1_700_000_000_000_000;
+
+/// A router of the virtual network, with the next hops it balances traffic over.
+pub struct Router {
+    pub addr: IpAddr,
+    pub successors: Vec<IpAddr>,
+    pub load_balancing: LoadBalancing,
+    /// Share of the traffic sent to each successor, in the same order. Traffic is split evenly if empty.
+    pub weights: Vec<usize>,
+    /// Whether the router sends ICMP time exceeded messages at all.
+    pub responsive: bool,
+    /// Maximum number of ICMP messages the router sends per batch of probes.
+    pub icmp_rate_limit: Option<usize>,
+}
+
+impl Router {
+    pub fn new(addr: IpAddr) -> Self {
+        Self {
+            addr,
+            successors: vec![],
+            load_balancing: LoadBalancing::default(),
+            weights: vec![],
+            responsive: true,
+            icmp_rate_limit: None,
+        }
+    }
+
+    pub fn with_load_balancing(mut self, load_balancing: LoadBalancing) -> Self {
+        self.load_balancing = load_balancing;
+        self
+    }
+
+    /// Splits traffic unevenly, as unequal-cost load balancers do.
+    pub fn with_weights(mut self, weights: Vec<usize>) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    // the successor picked for a packet hashed to `index`
+    fn successor(&self, index: usize) -> IpAddr {
+        if self.weights.is_empty() {
+            return self.successors[index % self.successors.len()];
+        }
+        let mut point = index % self.weights.iter().sum::<usize>();
+        for (&successor, &weight) in self.successors.iter().zip(&self.weights) {
+            if point < weight {
+                return successor;
+            }
+            point -= weight;
+        }
+        unreachable!()
+    }
+
+    pub fn silent(mut self) -> Self {
+        self.responsive = false;
+        self
+    }
+
+    pub fn with_icmp_rate_limit(mut self, icmp_rate_limit: usize) -> Self {
+        self.icmp_rate_limit = Some(icmp_rate_limit);
+        self
+    }
+}
+
+/// A virtual topology rooted at the vantage point `src_addr`.
+/// Routers without successors are directly connected to the destination,
+/// which answers every probe that reaches it, unless it is made silent.
+pub struct Network {
+    src_addr: IpAddr,
+    routers: HashMap<IpAddr, Router>,
+    destination_responsive: bool,
+    rng: ChaCha8Rng,
+    icmp_sent: HashMap<IpAddr, usize>,
+}
+
+impl Network {
+    pub fn new(src_addr: IpAddr) -> Self {
+        let mut routers = HashMap::new();
+        routers.insert(src_addr, Router::new(src_addr));
+        Self {
+            src_addr,
+            routers,
+            destination_responsive: true,
+            rng: ChaCha8Rng::seed_from_u64(42),
+            icmp_sent: HashMap::new(),
+        }
+    }
+
+    /// Builds a network of per-flow, responsive routers from a list of `(near, far)` links.
+    pub fn from_links(src_addr: IpAddr, links: &[(IpAddr, IpAddr)]) -> Self {
+        let mut network = Self::new(src_addr);
+        for &(near, far) in links {
+            network.add_link(near, far);
+        }
+        network
+    }
+
+    /// Adds `router`, or replaces the configuration of an existing router while keeping its links.
+    pub fn add_router(&mut self, mut router: Router) {
+        if let Some(existing) = self.routers.remove(&router.addr) {
+            router.successors.extend(existing.successors);
+        }
+        self.routers.insert(router.addr, router);
+    }
+
+    /// Adds a link between `near` and `far`. Use the source address as `near` to add a first hop.
+    pub fn add_link(&mut self, near: IpAddr, far: IpAddr) {
+        self.routers
+            .entry(near)
+            .or_insert_with(|| Router::new(near))
+            .successors
+            .push(far);
+        self.routers.entry(far).or_insert_with(|| Router::new(far));
+    }
+
+    pub fn set_destination_responsive(&mut self, responsive: bool) {
+        self.destination_responsive = responsive;
+    }
+
+    /// Resets the ICMP rate limiters, called at the start of every batch of probes.
+    pub fn reset_rate_limits(&mut self) {
+        self.icmp_sent.clear();
+    }
+
+    /// All the router-level paths from the vantage point to the destination.
+    pub fn paths(&self) -> Vec<Vec<IpAddr>> {
+        fn walk(
+            network: &Network,
+            addr: IpAddr,
+            path: &mut Vec<IpAddr>,
+            paths: &mut Vec<Vec<IpAddr>>,
+        ) {
+            let router = &network.routers[&addr];
+            if router.successors.is_empty() {
+                paths.push(path.clone());
+            }
+            for &next in &router.successors {
+                path.push(next);
+                walk(network, next, path, paths);
+                path.pop();
+            }
+        }
+        let mut paths = vec![];
+        walk(self, self.src_addr, &mut vec![], &mut paths);
+        paths
+    }
+
+    /// Routers traversed by the probe flow, excluding the vantage point and the destination.
+    pub fn path(&mut self, probe: &Probe) -> Vec<IpAddr> {
+        let mut path = vec![];
+        let mut current = &self.routers[&self.src_addr];
+        while !current.successors.is_empty() && path.len() < u8::MAX as usize {
+            let index = match current.load_balancing {
+                LoadBalancing::PerFlow => {
+                    let mut hasher = DefaultHasher::new();
+                    (
+                        probe.dst_addr,
+                        probe.src_port,
+                        probe.dst_port,
+                        protocol_number(probe.protocol),
+                        current.addr,
+                    )
+                        .hash(&mut hasher);
+                    hasher.finish() as usize
+                }
+                LoadBalancing::PerPacket => self.rng.gen(),
+                LoadBalancing::PerDestination => {
+                    let mut hasher = DefaultHasher::new();
+                    (probe.dst_addr, current.addr).hash(&mut hasher);
+                    hasher.finish() as usize
+                }
+            };
+            let next = current.successor(index);
+            path.push(next);
+            current = &self.routers[&next];
+        }
+        path
+    }
+
+    /// The reply the network sends back for `probe`, if any.
+    pub fn reply(&mut self, probe: &Probe) -> Option<Reply> {
+        if probe.ttl == 0 {
+            return None;
+        }
+        let path = self.path(probe);
+        let hop = probe.ttl as usize;
+
+        let (reply_src_addr, reply_icmp_type, reply_icmp_code, hops) = if hop <= path.len() {
+            let router = &self.routers[&path[hop - 1]];
+            let sent = self.icmp_sent.entry(router.addr).or_insert(0);
+            if !router.responsive || router.icmp_rate_limit.is_some_and(|limit| *sent >= limit) {
+                return None;
+            }
+            *sent += 1;
+            let icmp_type = match probe.dst_addr {
+                IpAddr::V4(_) => IcmpTypes::TimeExceeded.0,
+                IpAddr::V6(_) => Icmpv6Types::TimeExceeded.0,
+            };
+            (path[hop - 1], icmp_type, 0, hop)
+        } else {
+            if !self.destination_responsive {
+                return None;
+            }
+            let (icmp_type, icmp_code) = match (probe.protocol, probe.dst_addr) {
+                (L4::UDP, IpAddr::V4(_)) => (IcmpTypes::DestinationUnreachable.0, 3),
+                (L4::UDP, IpAddr::V6(_)) => (Icmpv6Types::DestinationUnreachable.0, 4),
+                (_, IpAddr::V4(_)) => (IcmpTypes::EchoReply.0, 0),
+                (_, IpAddr::V6(_)) => (Icmpv6Types::EchoReply.0, 0),
+            };
+            (probe.dst_addr, icmp_type, icmp_code, path.len() + 1)
+        };
+
+        let reply_protocol = match probe.dst_addr {
+            IpAddr::V4(_) => IpNextHeaderProtocols::Icmp.0,
+            IpAddr::V6(_) => IpNextHeaderProtocols::Icmpv6.0,
+        };
+
+        // one millisecond per hop, rtt is expressed in tenths of milliseconds
+        let rtt = (hops * 10) as u16;
+
+        Some(Reply {
+            capture_timestamp: BASE_TIMESTAMP + rtt as u64 * 100,
+            reply_src_addr,
+            reply_dst_addr: self.src_addr,
+            reply_size: 56,
+            reply_ttl: u8::MAX - hops as u8,
+            reply_protocol,
+            reply_icmp_type,
+            reply_icmp_code,
+            probe_src_addr: self.src_addr,
+            probe_dst_addr: probe.dst_addr,
+            probe_size: 36,
+            probe_protocol: protocol_number(probe.protocol),
+            probe_ttl: probe.ttl,
+            probe_src_port: probe.src_port,
+            probe_dst_port: probe.dst_port,
+            quoted_ttl: 1,
+            rtt,
+            ..Default::default()
+        })
+    }
+}
+
+fn protocol_number(protocol: L4) -> u8 {
+    match protocol {
+        L4::ICMP => IpNextHeaderProtocols::Icmp.0,
+        L4::ICMPv6 => IpNextHeaderProtocols::Icmpv6.0,
+        L4::UDP => IpNextHeaderProtocols::Udp.0,
+    }
+}
+
+/// Answers probes from a virtual [`Network`] instead of sending them on the wire.
+pub struct SimulatedProber {
+    network: Network,
+}
+
+impl SimulatedProber {
+    pub fn new(network: Network) -> Self {
+        Self { network }
+    }
+}
+
+impl Prober for SimulatedProber {
+    fn probe(&mut self, probes: Vec<Probe>) -> Result<Vec<Reply>> {
+        self.network.reset_rate_limits();
+        Ok(probes
+            .iter()
+            .filter_map(|probe| self.network.reply(probe))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests;
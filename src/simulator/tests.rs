@@ -0,0 +1,383 @@
+use std::collections::HashSet;
+
+use crate::algorithms::diamond_miner::{
+    DiamondMiner, RandomFlowMapper, DEFAULT_PREFIX_SIZE_V4, DEFAULT_PREFIX_SIZE_V6,
+};
+use crate::algorithms::utils::StoppingRule;
+use crate::helpers::{addr, links, network, run, DEST, SRC};
+use crate::types::TTL;
+
+use super::*;
+
+const IP: [&str; 10] = [
+    "192.168.0.2",
+    "192.168.0.3",
+    "192.168.0.4",
+    "192.168.0.5",
+    "192.168.0.6",
+    "192.168.0.7",
+    "192.168.0.8",
+    "192.168.0.9",
+    "192.168.0.10",
+    "192.168.0.11",
+];
+
+fn probe(ttl: TTL, src_port: u16) -> Probe {
+    Probe {
+        dst_addr: addr(DEST),
+        src_port,
+        dst_port: 33434,
+        ttl,
+        protocol: L4::ICMP,
+    }
+}
+
+fn trace(network: Network, protocol: L4, confidence: f64) -> DiamondMiner {
+    let miner = DiamondMiner::new(addr(DEST), 1, 10, 24000, 33434, protocol, confidence, 10);
+    run(&mut SimulatedProber::new(network), miner)
+}
+
+// every pair of consecutive routers on a path from the vantage point to the destination
+fn expected_links(network: &Network) -> HashSet<(TTL, IpAddr, IpAddr)> {
+    network
+        .paths()
+        .iter()
+        .flat_map(|path| {
+            path.windows(2)
+                .enumerate()
+                .map(|(i, pair)| ((i + 1) as TTL, pair[0], pair[1]))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[test]
+fn test_simulated_diamond() {
+    // [ IP[0] ] -+- [ IP[1] ] -+- [ IP[3] ] -.-.-.- [ DEST ]
+    //            +- [ IP[2] ] -+
+    let network = network(&[
+        (SRC, IP[0]),
+        (IP[0], IP[1]),
+        (IP[0], IP[2]),
+        (IP[1], IP[3]),
+        (IP[2], IP[3]),
+    ]);
+
+    let expected = HashSet::from([
+        (1, addr(IP[0]), addr(IP[1])),
+        (1, addr(IP[0]), addr(IP[2])),
+        (2, addr(IP[1]), addr(IP[3])),
+        (2, addr(IP[2]), addr(IP[3])),
+    ]);
+    assert_eq!(expected_links(&network), expected);
+
+    let miner = trace(network, L4::ICMP, 95.0);
+
+    assert_eq!(links(&miner), expected);
+
+    // the destination answers every probe that goes past the last router
+    assert!(miner
+        .replies()
+        .iter()
+        .any(|r| r.probe_ttl == 4 && !r.is_time_exceeded()));
+}
+
+#[test]
+fn test_unmeshed_diamond() {
+    //            +- [ IP[1] ] -- [ IP[4] ] -+
+    // [ IP[0] ] -+- [ IP[2] ] -- [ IP[5] ] -+- [ IP[7] ]
+    //            +- [ IP[3] ] -- [ IP[6] ] -+
+    let network = network(&[
+        (SRC, IP[0]),
+        (IP[0], IP[1]),
+        (IP[0], IP[2]),
+        (IP[0], IP[3]),
+        (IP[1], IP[4]),
+        (IP[2], IP[5]),
+        (IP[3], IP[6]),
+        (IP[4], IP[7]),
+        (IP[5], IP[7]),
+        (IP[6], IP[7]),
+    ]);
+    let expected = expected_links(&network);
+    assert_eq!(expected.len(), 9);
+
+    let miner = trace(network, L4::ICMP, 99.0);
+
+    assert_eq!(links(&miner), expected);
+}
+
+#[test]
+fn test_meshed_diamond() {
+    //            +- [ IP[1] ] -+- [ IP[3] ] -+
+    // [ IP[0] ] -+             X             +- [ IP[6] ]
+    //            +- [ IP[2] ] -+- [ IP[4] ] -+
+    //                          +- [ IP[5] ] -+
+    let network = network(&[
+        (SRC, IP[0]),
+        (IP[0], IP[1]),
+        (IP[0], IP[2]),
+        (IP[1], IP[3]),
+        (IP[1], IP[4]),
+        (IP[2], IP[3]),
+        (IP[2], IP[4]),
+        (IP[2], IP[5]),
+        (IP[3], IP[6]),
+        (IP[4], IP[6]),
+        (IP[5], IP[6]),
+    ]);
+    let expected = expected_links(&network);
+    assert_eq!(expected.len(), 10);
+
+    let miner = trace(network, L4::ICMP, 99.0);
+
+    assert_eq!(links(&miner), expected);
+}
+
+#[test]
+fn test_asymmetric_diamond() {
+    // the lower branch is one hop longer than the upper one
+    //            +- [ IP[1] ] ------------- [ IP[3] ] -- [ IP[4] ]
+    // [ IP[0] ] -+                        /
+    //            +- [ IP[2] ] -- [ IP[5] ]
+    let network = network(&[
+        (SRC, IP[0]),
+        (IP[0], IP[1]),
+        (IP[0], IP[2]),
+        (IP[1], IP[3]),
+        (IP[2], IP[5]),
+        (IP[5], IP[3]),
+        (IP[3], IP[4]),
+    ]);
+    let expected = expected_links(&network);
+    assert!(expected.contains(&(3, addr(IP[3]), addr(IP[4]))));
+    assert!(expected.contains(&(4, addr(IP[3]), addr(IP[4]))));
+
+    let miner = trace(network, L4::ICMP, 99.0);
+
+    assert_eq!(links(&miner), expected);
+}
+
+#[test]
+fn test_silent_router() {
+    // [ IP[0] ] -- [ * ] -- [ IP[2] ]
+    let mut network = network(&[(SRC, IP[0]), (IP[0], IP[1]), (IP[1], IP[2])]);
+    network.add_router(Router::new(addr(IP[1])).silent());
+
+    let miner = trace(network, L4::ICMP, 95.0);
+
+    assert!(links(&miner).is_empty());
+    assert!(miner
+        .replies()
+        .iter()
+        .all(|r| r.reply_src_addr != addr(IP[1])));
+    assert!(miner
+        .replies()
+        .iter()
+        .any(|r| r.reply_src_addr == addr(IP[2])));
+}
+
+#[test]
+fn test_rate_limited_router() {
+    let mut network = network(&[(SRC, IP[0])]);
+    network.add_router(Router::new(addr(IP[0])).with_icmp_rate_limit(2));
+    let mut prober = SimulatedProber::new(network);
+
+    let probes = (0..5).map(|i| probe(1, 24000 + i)).collect();
+    assert_eq!(prober.probe(probes).unwrap().len(), 2);
+
+    // the limit is reset for the next batch
+    let probes = (0..5).map(|i| probe(1, 24000 + i)).collect();
+    assert_eq!(prober.probe(probes).unwrap().len(), 2);
+}
+
+#[test]
+fn test_per_packet_load_balancer() {
+    let mut network = network(&[(SRC, IP[0]), (IP[0], IP[1]), (IP[0], IP[2])]);
+    network.add_router(Router::new(addr(IP[0])).with_load_balancing(LoadBalancing::PerPacket));
+    let mut prober = SimulatedProber::new(network);
+
+    // the same flow reaches both successors
+    let probes = (0..20).map(|_| probe(2, 24000)).collect();
+    let interfaces: HashSet<IpAddr> = prober
+        .probe(probes)
+        .unwrap()
+        .iter()
+        .map(|r| r.reply_src_addr)
+        .collect();
+    assert_eq!(interfaces, HashSet::from([addr(IP[1]), addr(IP[2])]));
+}
+
+#[test]
+fn test_per_flow_load_balancer() {
+    let network = network(&[(SRC, IP[0]), (IP[0], IP[1]), (IP[0], IP[2])]);
+    let mut prober = SimulatedProber::new(network);
+
+    let probes = (0..20).map(|_| probe(2, 24000)).collect();
+    let interfaces: HashSet<IpAddr> = prober
+        .probe(probes)
+        .unwrap()
+        .iter()
+        .map(|r| r.reply_src_addr)
+        .collect();
+    assert_eq!(interfaces.len(), 1);
+}
+
+#[test]
+fn test_destination_replies() {
+    let mut network = network(&[(SRC, IP[0])]);
+
+    let reply = network.reply(&probe(2, 24000)).unwrap();
+    assert_eq!(reply.reply_src_addr, addr(DEST));
+    assert_eq!(reply.reply_icmp_type, IcmpTypes::EchoReply.0);
+
+    let udp_probe = Probe {
+        protocol: L4::UDP,
+        ..probe(2, 24000)
+    };
+    let reply = network.reply(&udp_probe).unwrap();
+    assert_eq!(reply.reply_src_addr, addr(DEST));
+    assert_eq!(
+        (reply.reply_icmp_type, reply.reply_icmp_code),
+        (IcmpTypes::DestinationUnreachable.0, 3)
+    );
+
+    network.set_destination_responsive(false);
+    assert!(network.reply(&probe(2, 24000)).is_none());
+    assert!(network.reply(&probe(1, 24000)).is_some());
+}
+
+#[test]
+fn test_simulated_diamond_v6() {
+    let edges: Vec<(IpAddr, IpAddr)> = [
+        ("2001:db8::1", "2001:db8:1::1"),
+        ("2001:db8:1::1", "2001:db8:2::1"),
+        ("2001:db8:1::1", "2001:db8:2::2"),
+        ("2001:db8:1::1", "2001:db8:2::3"),
+        ("2001:db8:2::1", "2001:db8:3::1"),
+        ("2001:db8:2::2", "2001:db8:3::1"),
+        ("2001:db8:2::3", "2001:db8:3::1"),
+    ]
+    .iter()
+    .map(|&(near, far)| (addr(near), addr(far)))
+    .collect();
+    let network = Network::from_links(addr("2001:db8::1"), &edges);
+    let expected = expected_links(&network);
+
+    let dst_addr = addr("2001:db8:ffff::");
+    let miner = DiamondMiner::new(dst_addr, 1, 10, 24000, 33434, L4::ICMP, 99.0, 10);
+    let miner = run(&mut SimulatedProber::new(network), miner);
+
+    assert_eq!(links(&miner), expected);
+    assert!(miner
+        .replies()
+        .iter()
+        .any(|r| r.probe_ttl == 4 && r.reply_icmp_type == Icmpv6Types::EchoReply.0));
+}
+
+#[test]
+fn test_load_balancer_classification() {
+    // [ IP[0] ] -+- [ IP[1] ] -+- [ IP[8] ] -+- [ IP[3] ] -+- [ IP[9] ] -+- [ IP[5] ] -+- [ IP[7] ]
+    //            +- [ IP[2] ] -+             +- [ IP[4] ] -+             +- [ IP[6] ] -+
+    // the balancers at TTL 1, 3 and 5 are per-flow, per-packet and per-destination
+    let mut network = network(&[
+        (SRC, IP[0]),
+        (IP[0], IP[1]),
+        (IP[0], IP[2]),
+        (IP[1], IP[8]),
+        (IP[2], IP[8]),
+        (IP[8], IP[3]),
+        (IP[8], IP[4]),
+        (IP[3], IP[9]),
+        (IP[4], IP[9]),
+        (IP[9], IP[5]),
+        (IP[9], IP[6]),
+        (IP[5], IP[7]),
+        (IP[6], IP[7]),
+    ]);
+    network.add_router(Router::new(addr(IP[8])).with_load_balancing(LoadBalancing::PerPacket));
+    network.add_router(Router::new(addr(IP[9])).with_load_balancing(LoadBalancing::PerDestination));
+
+    let miner =
+        DiamondMiner::new(addr(DEST), 1, 10, 24000, 33434, L4::ICMP, 95.0, 10).with_reprobes(3);
+    let miner = run(&mut SimulatedProber::new(network), miner);
+
+    let load_balancers = miner.load_balancers();
+    assert_eq!(load_balancers[&(1, addr(IP[0]))], LoadBalancing::PerFlow);
+    assert_eq!(load_balancers[&(3, addr(IP[8]))], LoadBalancing::PerPacket);
+    assert_eq!(
+        load_balancers[&(5, addr(IP[9]))],
+        LoadBalancing::PerDestination
+    );
+    assert!(miner.unstable_ttls().contains(&4));
+}
+
+#[test]
+fn test_unequal_cost_load_balancer() {
+    // [ IP[0] ] -+- [ IP[1] ] -+- [ IP[4] ]
+    //            +- [ IP[2] ] -+
+    //            +- [ IP[3] ] -+
+    // IP[0] sends 80% of the flows to IP[1], 15% to IP[2] and 5% to IP[3]
+    let network = || {
+        let mut network = network(&[
+            (SRC, IP[0]),
+            (IP[0], IP[1]),
+            (IP[0], IP[2]),
+            (IP[0], IP[3]),
+            (IP[1], IP[4]),
+            (IP[2], IP[4]),
+            (IP[3], IP[4]),
+        ]);
+        network.add_router(Router::new(addr(IP[0])).with_weights(vec![16, 3, 1]));
+        network
+    };
+    let miner = |stopping_rule| {
+        DiamondMiner::new(addr(DEST), 1, 10, 24000, 33434, L4::ICMP, 95.0, 10)
+            .with_stopping_rule(stopping_rule)
+    };
+
+    let uniform = run(
+        &mut SimulatedProber::new(network()),
+        miner(StoppingRule::Uniform),
+    );
+    let non_uniform = run(
+        &mut SimulatedProber::new(network()),
+        miner(StoppingRule::NonUniform),
+    );
+
+    // the skew seen between IP[1] and IP[2] keeps the miner probing until it finds IP[3],
+    // possibly with a flow that was not sent at TTL 1, so without its link from IP[0]
+    assert!(non_uniform.nodes_at_ttl(2).any(|node| node == addr(IP[3])));
+    assert!(links(&non_uniform).is_subset(&expected_links(&network())));
+    assert!(non_uniform.replies().len() > uniform.replies().len());
+}
+
+#[test]
+fn test_random_flow_mapper() {
+    // [ IP[0] ] -+- 16 interfaces -+- [ IP[1] ]
+    let middle: Vec<String> = (0..16).map(|i| format!("192.168.1.{}", i)).collect();
+    let mut edges = vec![(SRC, IP[0])];
+    for interface in &middle {
+        edges.push((IP[0], interface.as_str()));
+        edges.push((interface.as_str(), IP[1]));
+    }
+    let network = network(&edges);
+    let miner = DiamondMiner::new(addr(DEST), 1, 10, 24000, 33434, L4::ICMP, 99.0, 10)
+        .with_flow_mappers(
+            Box::new(RandomFlowMapper::new(0, DEFAULT_PREFIX_SIZE_V4)),
+            Box::new(RandomFlowMapper::new(0, DEFAULT_PREFIX_SIZE_V6)),
+        );
+
+    let expected = expected_links(&network);
+    let miner = run(&mut SimulatedProber::new(network), miner);
+
+    // the flow that the affine map sends to the first address of the prefix is sent
+    assert!(miner.replies().iter().filter(|r| r.probe_ttl == 2).count() > 66);
+    assert_eq!(links(&miner), expected);
+    // the destination is not the first address of its prefix, and the probes stay within it
+    let prefix = ip_network::IpNetwork::new_truncate(addr(DEST), 24).unwrap();
+    assert!(miner
+        .replies()
+        .iter()
+        .all(|r| prefix.contains(r.probe_dst_addr)));
+}
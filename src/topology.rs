@@ -0,0 +1,283 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::net::IpAddr;
+
+use caracat::models::Reply;
+
+use crate::types::{Flow, TTL};
+
+/// An interface that sent time exceeded replies, with the TTLs it was seen at.
+#[derive(Clone, Debug, Default)]
+pub struct Node {
+    pub ttls: BTreeSet<TTL>,
+}
+
+/// A link between two interfaces at consecutive TTLs, with the flows that went through both.
+#[derive(Clone, Debug, Default)]
+pub struct Edge {
+    pub flows: HashSet<Flow>,
+}
+
+type Hops = BTreeMap<TTL, BTreeSet<IpAddr>>;
+
+/// The part of the topology between a divergence point, where traffic is split over several
+/// successors, and the convergence point, the first TTL at which all its paths meet again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diamond {
+    pub divergence: (TTL, IpAddr),
+    /// None if the paths do not meet again within the probed TTLs.
+    pub convergence: Option<(TTL, IpAddr)>,
+    /// Interfaces between the divergence and the convergence points, by TTL.
+    pub hops: Hops,
+}
+
+/// The graph of the interfaces found by a trace, built incrementally from its replies.
+/// Two interfaces are linked when a flow got a reply from the first at some TTL
+/// and from the second at the next one.
+#[derive(Clone, Debug, Default)]
+pub struct Topology {
+    nodes: HashMap<IpAddr, Node>,
+    nodes_by_ttl: HashMap<TTL, HashSet<IpAddr>>,
+    edges: HashMap<(TTL, IpAddr, IpAddr), Edge>,
+    // keyed by the TTL of the near interface
+    successors: HashMap<(TTL, IpAddr), HashSet<IpAddr>>,
+    // keyed by the TTL of the far interface
+    predecessors: HashMap<(TTL, IpAddr), HashSet<IpAddr>>,
+    // interfaces that answered each flow, by TTL
+    hops_by_flow: HashMap<Flow, HashMap<TTL, HashSet<IpAddr>>>,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_replies(replies: &[&Reply]) -> Self {
+        let mut topology = Self::new();
+        for reply in replies {
+            topology.add_reply(reply);
+        }
+        topology
+    }
+
+    /// Adds the interface that sent `reply`, linked to the interfaces that answered
+    /// the same flow at the adjacent TTLs. Replies other than time exceeded are ignored.
+    pub fn add_reply(&mut self, reply: &Reply) {
+        if !reply.is_time_exceeded() {
+            return;
+        }
+        let flow = Flow::from(reply);
+        let (ttl, addr) = (reply.probe_ttl, reply.reply_src_addr);
+
+        let hops = self.hops_by_flow.entry(flow).or_default();
+        if !hops.entry(ttl).or_default().insert(addr) {
+            return;
+        }
+        let adjacent = |ttl: Option<TTL>| -> Vec<(TTL, IpAddr)> {
+            ttl.and_then(|ttl| hops.get(&ttl).map(|addrs| (ttl, addrs)))
+                .into_iter()
+                .flat_map(|(ttl, addrs)| addrs.iter().map(move |&addr| (ttl, addr)))
+                .collect()
+        };
+        let near = adjacent(ttl.checked_sub(1));
+        let far = adjacent(ttl.checked_add(1));
+
+        self.nodes.entry(addr).or_default().ttls.insert(ttl);
+        self.nodes_by_ttl.entry(ttl).or_default().insert(addr);
+        for (near_ttl, near_addr) in near {
+            self.add_edge(near_ttl, near_addr, addr, flow);
+        }
+        for (_, far_addr) in far {
+            self.add_edge(ttl, addr, far_addr, flow);
+        }
+    }
+
+    fn add_edge(&mut self, ttl: TTL, near: IpAddr, far: IpAddr, flow: Flow) {
+        self.edges
+            .entry((ttl, near, far))
+            .or_default()
+            .flows
+            .insert(flow);
+        self.successors.entry((ttl, near)).or_default().insert(far);
+        self.predecessors
+            .entry((ttl + 1, far))
+            .or_default()
+            .insert(near);
+    }
+
+    pub fn node(&self, addr: IpAddr) -> Option<&Node> {
+        self.nodes.get(&addr)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (IpAddr, &Node)> {
+        self.nodes.iter().map(|(&addr, node)| (addr, node))
+    }
+
+    pub fn n_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn nodes_at_ttl(&self, ttl: TTL) -> impl Iterator<Item = IpAddr> + '_ {
+        self.nodes_by_ttl.get(&ttl).into_iter().flatten().copied()
+    }
+
+    /// The link from `near` at `ttl` to `far` at `ttl + 1`, if any.
+    pub fn edge(&self, ttl: TTL, near: IpAddr, far: IpAddr) -> Option<&Edge> {
+        self.edges.get(&(ttl, near, far))
+    }
+
+    /// Every link, as (TTL of the near interface, near interface, far interface, evidence).
+    pub fn links(&self) -> impl Iterator<Item = (TTL, IpAddr, IpAddr, &Edge)> {
+        self.edges
+            .iter()
+            .map(|(&(ttl, near, far), edge)| (ttl, near, far, edge))
+    }
+
+    /// The links from the interfaces at `ttl`, sorted.
+    pub fn links_at_ttl(&self, ttl: TTL) -> Vec<(IpAddr, IpAddr)> {
+        let mut links: Vec<(IpAddr, IpAddr)> = self
+            .nodes_at_ttl(ttl)
+            .flat_map(|near| self.successors(ttl, near).map(move |far| (near, far)))
+            .collect();
+        links.sort();
+        links
+    }
+
+    pub fn n_links(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Interfaces at `ttl + 1` linked to `addr` at `ttl`.
+    pub fn successors(&self, ttl: TTL, addr: IpAddr) -> impl Iterator<Item = IpAddr> + '_ {
+        self.successors
+            .get(&(ttl, addr))
+            .into_iter()
+            .flatten()
+            .copied()
+    }
+
+    /// Interfaces at `ttl - 1` linked to `addr` at `ttl`.
+    pub fn predecessors(&self, ttl: TTL, addr: IpAddr) -> impl Iterator<Item = IpAddr> + '_ {
+        self.predecessors
+            .get(&(ttl, addr))
+            .into_iter()
+            .flatten()
+            .copied()
+    }
+
+    /// Number of flows that went through `addr` at `ttl` and got a reply at the next TTL,
+    /// counted once per successor they reached.
+    pub fn n_flows_through(&self, ttl: TTL, addr: IpAddr) -> usize {
+        self.flows_by_successor(ttl, addr).iter().sum()
+    }
+
+    /// Number of flows that reached each successor of `addr` at `ttl`.
+    pub fn flows_by_successor(&self, ttl: TTL, addr: IpAddr) -> Vec<usize> {
+        self.successors(ttl, addr)
+            .filter_map(|far| self.edge(ttl, addr, far))
+            .map(|edge| edge.flows.len())
+            .collect()
+    }
+
+    /// The interfaces with several successors, sorted by TTL.
+    pub fn divergence_points(&self) -> Vec<(TTL, IpAddr)> {
+        let mut points: Vec<(TTL, IpAddr)> = self
+            .successors
+            .iter()
+            .filter(|(_, successors)| successors.len() > 1)
+            .map(|(&key, _)| key)
+            .collect();
+        points.sort();
+        points
+    }
+
+    // the interfaces reachable from `addr` at `ttl`, by TTL, up to the convergence point,
+    // the first interface all the paths go through. When paths have different lengths, it is
+    // reached at several TTLs: the last one is returned and the earlier ones are kept in the hops,
+    // but not the interfaces past it on the shortest paths
+    fn walk(&self, ttl: TTL, addr: IpAddr) -> (Hops, Option<(TTL, IpAddr)>) {
+        // for each interface, the interfaces all the paths to it went through
+        type Dominators = BTreeMap<IpAddr, BTreeSet<IpAddr>>;
+
+        let into_hops = |walked: BTreeMap<TTL, Dominators>, convergence: Option<IpAddr>| {
+            walked
+                .into_iter()
+                .map(|(ttl, frontier)| {
+                    let addrs = frontier
+                        .into_iter()
+                        .filter(|(addr, through)| match convergence {
+                            Some(convergence) => {
+                                *addr == convergence || !through.contains(&convergence)
+                            }
+                            None => true,
+                        })
+                        .map(|(addr, _)| addr)
+                        .collect();
+                    (ttl, addrs)
+                })
+                .collect()
+        };
+
+        let mut walked: BTreeMap<TTL, Dominators> = BTreeMap::new();
+        let mut frontier: Dominators = BTreeMap::from([(addr, BTreeSet::new())]);
+        let mut ttl = ttl;
+        while let Some(next_ttl) = ttl.checked_add(1) {
+            let mut next: Dominators = BTreeMap::new();
+            for (&near, through) in &frontier {
+                for far in self.successors(ttl, near) {
+                    let mut through = through.clone();
+                    through.insert(far);
+                    next.entry(far)
+                        .and_modify(|common| common.retain(|addr| through.contains(addr)))
+                        .or_insert(through);
+                }
+            }
+            let Some(common) = next
+                .values()
+                .cloned()
+                .reduce(|common, through| common.intersection(&through).copied().collect())
+            else {
+                return (into_hops(walked, None), None);
+            };
+            if let Some(&convergence) = common.first() {
+                return (
+                    into_hops(walked, Some(convergence)),
+                    Some((next_ttl, convergence)),
+                );
+            }
+            walked.insert(next_ttl, next.clone());
+            frontier = next;
+            ttl = next_ttl;
+        }
+        (into_hops(walked, None), None)
+    }
+
+    /// The first TTL, after `ttl`, at which all the paths from `addr` go through a single interface.
+    pub fn convergence_point(&self, ttl: TTL, addr: IpAddr) -> Option<(TTL, IpAddr)> {
+        self.walk(ttl, addr).1
+    }
+
+    /// The outermost diamonds, sorted by TTL. The diamonds nested in another one are not listed.
+    pub fn diamonds(&self) -> Vec<Diamond> {
+        let mut diamonds: Vec<Diamond> = vec![];
+        let mut inside: HashSet<(TTL, IpAddr)> = HashSet::new();
+        for (ttl, addr) in self.divergence_points() {
+            if inside.contains(&(ttl, addr)) {
+                continue;
+            }
+            let (hops, convergence) = self.walk(ttl, addr);
+            inside.extend(
+                hops.iter()
+                    .flat_map(|(&ttl, addrs)| addrs.iter().map(move |&addr| (ttl, addr))),
+            );
+            diamonds.push(Diamond {
+                divergence: (ttl, addr),
+                convergence,
+                hops,
+            });
+        }
+        diamonds
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,130 @@
+use crate::helpers::{addr, reply};
+
+use super::*;
+
+const IP: [&str; 6] = [
+    "192.168.0.2",
+    "192.168.0.3",
+    "192.168.0.4",
+    "192.168.0.5",
+    "192.168.0.6",
+    "192.168.0.7",
+];
+const DEST: [&str; 3] = ["192.170.0.2", "192.170.0.4", "192.170.0.6"];
+
+// [ IP[0] ] -+- [ IP[1] ] -+- [ IP[3] ] -- [ IP[4] ]
+//            +- [ IP[2] ] -+
+fn diamond() -> Vec<Reply> {
+    vec![
+        reply(1, IP[0], DEST[0]),
+        reply(2, IP[1], DEST[0]),
+        reply(3, IP[3], DEST[0]),
+        reply(4, IP[4], DEST[0]),
+        reply(1, IP[0], DEST[1]),
+        reply(2, IP[2], DEST[1]),
+        reply(3, IP[3], DEST[1]),
+        reply(1, IP[0], DEST[2]),
+        reply(2, IP[2], DEST[2]),
+    ]
+}
+
+#[test]
+fn test_incremental_links() {
+    let mut topology = Topology::new();
+    // the link only appears once both ends of the flow answered
+    topology.add_reply(&reply(2, IP[1], DEST[0]));
+    assert_eq!(topology.n_links(), 0);
+    topology.add_reply(&reply(1, IP[0], DEST[0]));
+    assert_eq!(topology.links_at_ttl(1), vec![(addr(IP[0]), addr(IP[1]))]);
+
+    // duplicates and destination replies do not add anything
+    topology.add_reply(&reply(2, IP[1], DEST[0]));
+    topology.add_reply(&Reply {
+        reply_icmp_type: 0,
+        ..reply(3, DEST[0], DEST[0])
+    });
+    assert_eq!(topology.n_links(), 1);
+    assert_eq!(topology.n_nodes(), 2);
+    assert_eq!(
+        topology
+            .edge(1, addr(IP[0]), addr(IP[1]))
+            .unwrap()
+            .flows
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn test_neighbours() {
+    let replies = diamond();
+    let topology = Topology::from_replies(&replies.iter().collect::<Vec<_>>());
+
+    let successors: HashSet<IpAddr> = topology.successors(1, addr(IP[0])).collect();
+    assert_eq!(successors, HashSet::from([addr(IP[1]), addr(IP[2])]));
+    let predecessors: HashSet<IpAddr> = topology.predecessors(3, addr(IP[3])).collect();
+    assert_eq!(predecessors, HashSet::from([addr(IP[1]), addr(IP[2])]));
+    assert_eq!(topology.predecessors(2, addr(IP[3])).count(), 0);
+
+    // two flows went through IP[0] towards IP[2]
+    assert_eq!(topology.n_flows_through(1, addr(IP[0])), 3);
+    assert_eq!(
+        topology.node(addr(IP[3])).unwrap().ttls,
+        BTreeSet::from([3])
+    );
+}
+
+#[test]
+fn test_diamonds() {
+    let replies = diamond();
+    let topology = Topology::from_replies(&replies.iter().collect::<Vec<_>>());
+
+    assert_eq!(topology.divergence_points(), vec![(1, addr(IP[0]))]);
+    assert_eq!(
+        topology.convergence_point(1, addr(IP[0])),
+        Some((3, addr(IP[3])))
+    );
+    assert_eq!(
+        topology.diamonds(),
+        vec![Diamond {
+            divergence: (1, addr(IP[0])),
+            convergence: Some((3, addr(IP[3]))),
+            hops: BTreeMap::from([(2, BTreeSet::from([addr(IP[1]), addr(IP[2])]))]),
+        }]
+    );
+
+    // without IP[3], the paths never meet again
+    let replies: Vec<&Reply> = replies.iter().filter(|r| r.probe_ttl < 3).collect();
+    let topology = Topology::from_replies(&replies);
+    assert_eq!(topology.diamonds()[0].convergence, None);
+}
+
+#[test]
+fn test_asymmetric_diamond() {
+    // [ IP[0] ] -+- [ IP[1] ] -- [ IP[3] ] -- [ IP[4] ]
+    //            +- [ IP[2] ] -- [ IP[4] ] -- [ IP[5] ]
+    let replies = [
+        reply(1, IP[0], DEST[0]),
+        reply(2, IP[1], DEST[0]),
+        reply(3, IP[3], DEST[0]),
+        reply(4, IP[4], DEST[0]),
+        reply(1, IP[0], DEST[1]),
+        reply(2, IP[2], DEST[1]),
+        reply(3, IP[4], DEST[1]),
+        reply(4, IP[5], DEST[1]),
+    ];
+    let topology = Topology::from_replies(&replies.iter().collect::<Vec<_>>());
+
+    // the paths meet at IP[4], reached one hop earlier on the shortest one
+    assert_eq!(
+        topology.diamonds(),
+        vec![Diamond {
+            divergence: (1, addr(IP[0])),
+            convergence: Some((4, addr(IP[4]))),
+            hops: BTreeMap::from([
+                (2, BTreeSet::from([addr(IP[1]), addr(IP[2])])),
+                (3, BTreeSet::from([addr(IP[3]), addr(IP[4])])),
+            ]),
+        }]
+    );
+}
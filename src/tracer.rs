@@ -0,0 +1,908 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use caracat::high_level::Config;
+use caracat::models::{Probe, Reply, L4};
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use log::{debug, info};
+use netdev::{get_default_interface, get_interfaces, Interface};
+use pantrace::formats::internal::Traceroute;
+
+use crate::algorithms::diamond_miner::{
+    DiamondMiner, FlowMapper, SequentialFlowMapper, DEFAULT_PREFIX_SIZE_V4, DEFAULT_PREFIX_SIZE_V6,
+};
+use crate::algorithms::mda_lite::MdaLite;
+use crate::algorithms::paris_traceroute::{
+    ParisTraceroute, DEFAULT_MAX_SILENT_HOPS, DEFAULT_PROBES_PER_HOP,
+};
+use crate::algorithms::utils::StoppingRule;
+use crate::algorithms::Algorithm;
+use crate::alias::{resolve_aliases, AliasResolver, RouterGraph, DEFAULT_ALIAS_ROUNDS};
+use crate::async_trace::{AsyncProber, AsyncTrace};
+use crate::campaign::Campaign;
+use crate::checkpoint::{Checkpoint, CheckpointLog};
+use crate::events::{EventSender, TraceEvent};
+use crate::mpls::{detect_tunnels, reveal_tunnels, Tunnel};
+use crate::pantrace_builder::{replies_to_pantrace_traceroute, TracerouteMetadata};
+use crate::probe::{Prober, ProbingSession};
+use crate::replay::ReplayProber;
+use crate::reply_dump::ReplyDump;
+use crate::topology::Topology;
+use crate::types::{LoadBalancing, Port, TTL};
+
+/// The algorithms a [`Tracer`] can run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlgorithmKind {
+    #[default]
+    DiamondMiner,
+    MdaLite,
+    ParisTraceroute,
+}
+
+/// Where the probes of a [`Tracer`] go.
+#[derive(Clone, Debug)]
+pub enum Backend {
+    /// Sends the probes with caracat, from `interface` or the default one. Requires raw socket privileges.
+    Caracat {
+        interface: Option<String>,
+        instance_id: u16,
+    },
+    /// Answers the probes with the replies of a caracat CSV file, see [`ReplayProber`].
+    Replay(PathBuf),
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Caracat {
+            interface: None,
+            instance_id: 0,
+        }
+    }
+}
+
+/// Counters of a trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceStatistics {
+    pub rounds: u32,
+    /// Probes requested by the algorithm. In a campaign, a probe requested by several
+    /// destinations is sent once, but counted for each of them.
+    pub probes: usize,
+    pub replies: usize,
+    pub nodes: usize,
+    pub links: usize,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+}
+
+/// The outcome of the trace towards a destination.
+pub struct TraceResult {
+    algorithm: Box<dyn Algorithm>,
+    tunnels: Vec<Tunnel>,
+    aliases: Vec<BTreeSet<IpAddr>>,
+    pub traceroute: Traceroute,
+    pub statistics: TraceStatistics,
+}
+
+impl TraceResult {
+    fn new(
+        algorithm: Box<dyn Algorithm>,
+        metadata: &TracerouteMetadata,
+        rounds: u32,
+        probes: usize,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Self {
+        let replies = algorithm.replies();
+        let traceroute = replies_to_pantrace_traceroute(
+            &replies,
+            algorithm.dst_addr(),
+            metadata,
+            start_time,
+            end_time,
+        );
+        let statistics = TraceStatistics {
+            rounds,
+            probes,
+            replies: replies.len(),
+            nodes: algorithm.topology().n_nodes(),
+            links: algorithm.topology().n_links(),
+            start_time,
+            end_time,
+        };
+        let tunnels = detect_tunnels(&replies, algorithm.topology(), algorithm.dst_addr());
+        Self {
+            algorithm,
+            tunnels,
+            aliases: vec![],
+            traceroute,
+            statistics,
+        }
+    }
+
+    pub fn dst_addr(&self) -> IpAddr {
+        self.algorithm.dst_addr()
+    }
+
+    pub fn topology(&self) -> &Topology {
+        self.algorithm.topology()
+    }
+
+    pub fn replies(&self) -> Vec<&Reply> {
+        self.algorithm.replies()
+    }
+
+    pub fn load_balancers(&self) -> HashMap<(TTL, IpAddr), LoadBalancing> {
+        self.algorithm.load_balancers()
+    }
+
+    pub fn unstable_ttls(&self) -> BTreeSet<TTL> {
+        self.algorithm.unstable_ttls()
+    }
+
+    /// The MPLS tunnels crossed by the trace, with their hidden LSRs if they were revealed.
+    pub fn tunnels(&self) -> &[Tunnel] {
+        &self.tunnels
+    }
+
+    /// The interfaces found to belong to the same router, if aliases were resolved.
+    pub fn aliases(&self) -> &[BTreeSet<IpAddr>] {
+        &self.aliases
+    }
+
+    /// The topology with the aliases of each router merged.
+    pub fn router_graph(&self) -> RouterGraph {
+        RouterGraph::new(self.topology(), &self.aliases)
+    }
+
+    /// The algorithm at the end of the trace.
+    pub fn algorithm(&self) -> &dyn Algorithm {
+        self.algorithm.as_ref()
+    }
+}
+
+type FlowMappers = Box<dyn Fn() -> (Box<dyn FlowMapper>, Box<dyn FlowMapper>)>;
+
+/// Runs a trace towards a destination, or a campaign towards many, from the probing rounds
+/// to the pantrace [`Traceroute`]. The defaults are the ones of the command line.
+///
+/// ```no_run
+/// use voyage::tracer::{AlgorithmKind, Tracer};
+///
+/// let result = Tracer::new("8.8.8.8".parse().unwrap())
+///     .with_algorithm(AlgorithmKind::MdaLite)
+///     .with_confidence(95.0)
+///     .trace()
+///     .unwrap();
+/// println!("{} links", result.statistics.links);
+/// ```
+pub struct Tracer {
+    targets: Vec<IpAddr>,
+    min_ttl: TTL,
+    max_ttl: TTL,
+    src_port: Port,
+    dst_port: Port,
+    protocol: L4,
+    confidence: f64,
+    max_round: u32,
+    algorithm: AlgorithmKind,
+    flow_mappers: FlowMappers,
+    reprobes: usize,
+    stopping_rule: StoppingRule,
+    estimate_successors: bool,
+    probes_per_hop: usize,
+    max_silent_hops: TTL,
+    backend: Backend,
+    probing_rate: u64,
+    receiver_wait_time: Duration,
+    metadata: TracerouteMetadata,
+    checkpoint: Option<CheckpointLog>,
+    resume: Option<PathBuf>,
+    reply_dump: Option<ReplyDump<Box<dyn Write>>>,
+    round_plans: Option<Box<dyn Write>>,
+    events: Option<EventSender>,
+    reveal_tunnels: bool,
+    resolve_aliases: bool,
+    alias_rounds: usize,
+}
+
+impl Tracer {
+    pub fn new(dst_addr: IpAddr) -> Self {
+        Self::campaign(vec![dst_addr])
+    }
+
+    /// A tracer for many destinations, probed together, see [`Tracer::trace_campaign`].
+    pub fn campaign(targets: Vec<IpAddr>) -> Self {
+        Self {
+            targets,
+            min_ttl: 1,
+            max_ttl: 32,
+            src_port: 24000,
+            dst_port: 33434,
+            protocol: L4::ICMP,
+            confidence: 99.0,
+            max_round: 100,
+            algorithm: AlgorithmKind::default(),
+            flow_mappers: Box::new(|| {
+                (
+                    Box::new(SequentialFlowMapper::new(DEFAULT_PREFIX_SIZE_V4)),
+                    Box::new(SequentialFlowMapper::new(DEFAULT_PREFIX_SIZE_V6)),
+                )
+            }),
+            reprobes: 3,
+            stopping_rule: StoppingRule::default(),
+            estimate_successors: false,
+            probes_per_hop: DEFAULT_PROBES_PER_HOP,
+            max_silent_hops: DEFAULT_MAX_SILENT_HOPS,
+            backend: Backend::default(),
+            probing_rate: 100,
+            receiver_wait_time: Duration::from_secs(1),
+            metadata: TracerouteMetadata::default(),
+            checkpoint: None,
+            resume: None,
+            reply_dump: None,
+            round_plans: None,
+            events: None,
+            reveal_tunnels: false,
+            resolve_aliases: false,
+            alias_rounds: DEFAULT_ALIAS_ROUNDS,
+        }
+    }
+
+    pub fn with_ttl_range(mut self, min_ttl: TTL, max_ttl: TTL) -> Self {
+        self.min_ttl = min_ttl;
+        self.max_ttl = max_ttl;
+        self
+    }
+
+    pub fn with_ports(mut self, src_port: Port, dst_port: Port) -> Self {
+        self.src_port = src_port;
+        self.dst_port = dst_port;
+        self
+    }
+
+    /// ICMP probes towards IPv6 destinations are sent with ICMPv6.
+    pub fn with_protocol(mut self, protocol: L4) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Probability, in percent, to find all the successors of every node.
+    pub fn with_confidence(mut self, confidence: f64) -> Self {
+        self.confidence = confidence;
+        self
+    }
+
+    pub fn with_max_round(mut self, max_round: u32) -> Self {
+        self.max_round = max_round;
+        self
+    }
+
+    pub fn with_algorithm(mut self, algorithm: AlgorithmKind) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Replaces the sequential flow mappers with the (IPv4, IPv6) pair built by `mappers`, for each destination.
+    pub fn with_flow_mappers(
+        mut self,
+        mappers: impl Fn() -> (Box<dyn FlowMapper>, Box<dyn FlowMapper>) + 'static,
+    ) -> Self {
+        self.flow_mappers = Box::new(mappers);
+        self
+    }
+
+    /// See [`DiamondMiner::with_reprobes`].
+    pub fn with_reprobes(mut self, reprobes: usize) -> Self {
+        self.reprobes = reprobes;
+        self
+    }
+
+    pub fn with_stopping_rule(mut self, stopping_rule: StoppingRule) -> Self {
+        self.stopping_rule = stopping_rule;
+        self
+    }
+
+    pub fn with_estimate_successors(mut self, estimate_successors: bool) -> Self {
+        self.estimate_successors = estimate_successors;
+        self
+    }
+
+    /// See [`ParisTraceroute::with_probes_per_hop`].
+    pub fn with_probes_per_hop(mut self, probes_per_hop: usize) -> Self {
+        self.probes_per_hop = probes_per_hop;
+        self
+    }
+
+    /// See [`ParisTraceroute::with_max_silent_hops`].
+    pub fn with_max_silent_hops(mut self, max_silent_hops: TTL) -> Self {
+        self.max_silent_hops = max_silent_hops;
+        self
+    }
+
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Probing rate in packets per second.
+    pub fn with_probing_rate(mut self, probing_rate: u64) -> Self {
+        self.probing_rate = probing_rate;
+        self
+    }
+
+    /// How long to wait for the reply to each probe.
+    pub fn with_receiver_wait_time(mut self, receiver_wait_time: Duration) -> Self {
+        self.receiver_wait_time = receiver_wait_time;
+        self
+    }
+
+    /// The metadata of the traceroutes. The protocol is the one of the tracer, and the source
+    /// addresses default to the ones of the caracat interface.
+    pub fn with_metadata(mut self, metadata: TracerouteMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Writes the state of the trace to `path` after every round.
+    pub fn with_checkpoint(mut self, path: PathBuf) -> Self {
+        self.checkpoint = Some(CheckpointLog::new(path));
+        self
+    }
+
+    /// Resumes the trace from the checkpoint at `path`, written with the same options.
+    pub fn with_resume(mut self, path: PathBuf) -> Self {
+        self.resume = Some(path);
+        self
+    }
+
+    /// Writes every reply received to `dump` during the trace.
+    pub fn with_reply_dump(mut self, dump: ReplyDump<Box<dyn Write>>) -> Self {
+        self.reply_dump = Some(dump);
+        self
+    }
+
+    /// Writes the plan of every round to `writer`, as one JSON object per line.
+    pub fn with_round_plans(mut self, writer: Box<dyn Write>) -> Self {
+        self.round_plans = Some(writer);
+        self
+    }
+
+    /// Sends the progress of the trace to `events`, see [`TraceEvent`].
+    pub fn with_events(mut self, events: Sender<TraceEvent>) -> Self {
+        self.events = Some(EventSender::new(events));
+        self
+    }
+
+    /// Sends probes at the end of the trace to reveal the LSRs hidden in opaque and invisible
+    /// MPLS tunnels, see [`reveal_tunnels`].
+    pub fn with_tunnel_revelation(mut self, reveal_tunnels: bool) -> Self {
+        self.reveal_tunnels = reveal_tunnels;
+        self
+    }
+
+    /// Tells which interfaces belong to the same router at the end of the trace, see [`AliasResolver`].
+    pub fn with_alias_resolution(mut self, resolve_aliases: bool) -> Self {
+        self.resolve_aliases = resolve_aliases;
+        self
+    }
+
+    /// Rounds of echo probes sent to the interfaces whose aliases cannot be told from the replies
+    /// of the trace, see [`resolve_aliases`].
+    pub fn with_alias_rounds(mut self, alias_rounds: usize) -> Self {
+        self.alias_rounds = alias_rounds;
+        self
+    }
+
+    /// Traces the destination over the backend.
+    pub fn trace(mut self) -> Result<TraceResult> {
+        match self.backend.clone() {
+            Backend::Caracat {
+                interface,
+                instance_id,
+            } => {
+                let mut prober = self.probing_session(interface, instance_id)?;
+                self.trace_with(&mut prober)
+            }
+            Backend::Replay(path) => {
+                let mut prober = replay_prober(&path)?;
+                let result = self.trace_with(&mut prober);
+                info!("{} replies were not replayed", prober.n_remaining());
+                result
+            }
+        }
+    }
+
+    /// Traces every destination over the backend, with a single batch of probes per round.
+    pub fn trace_campaign(mut self) -> Result<Vec<TraceResult>> {
+        match self.backend.clone() {
+            Backend::Caracat {
+                interface,
+                instance_id,
+            } => {
+                let mut prober = self.probing_session(interface, instance_id)?;
+                self.trace_campaign_with(&mut prober)
+            }
+            Backend::Replay(path) => {
+                let mut prober = replay_prober(&path)?;
+                let results = self.trace_campaign_with(&mut prober);
+                info!("{} replies were not replayed", prober.n_remaining());
+                results
+            }
+        }
+    }
+
+    /// Traces the destination over `prober` instead of the backend, e.g. a simulated network.
+    pub fn trace_with<P: Prober>(mut self, prober: &mut P) -> Result<TraceResult> {
+        let [dst_addr] = self.targets[..] else {
+            bail!("a single destination can be traced, use trace_campaign for several");
+        };
+        let mut alg = self.algorithm(dst_addr);
+        let start_time = Utc::now();
+        let (rounds, probes) = self.run(prober, alg.as_mut())?;
+        let end_time = Utc::now();
+        let mut result =
+            TraceResult::new(alg, &self.metadata(), rounds, probes, start_time, end_time);
+        self.reveal_tunnels(prober, &mut result)?;
+        self.resolve_aliases(prober, &mut result)?;
+        Ok(result)
+    }
+
+    /// Traces every destination over `prober` instead of the backend.
+    pub fn trace_campaign_with<P: Prober>(mut self, prober: &mut P) -> Result<Vec<TraceResult>> {
+        if self.checkpoint.is_some() || self.resume.is_some() {
+            bail!("campaigns cannot be checkpointed");
+        }
+        let mut campaign = Campaign::from_algorithms(
+            self.targets
+                .iter()
+                .map(|&dst_addr| self.algorithm(dst_addr))
+                .collect(),
+        );
+        let start_time = Utc::now();
+        let rounds = self.run_campaign(prober, &mut campaign)?;
+        let end_time = Utc::now();
+
+        let metadata = self.metadata();
+        let probes = campaign.probes_sent().to_vec();
+        let mut results: Vec<TraceResult> = campaign
+            .into_miners()
+            .into_iter()
+            .zip(probes)
+            .map(|(alg, probes)| {
+                TraceResult::new(alg, &metadata, rounds, probes, start_time, end_time)
+            })
+            .collect();
+        for result in &mut results {
+            self.reveal_tunnels(prober, result)?;
+            self.resolve_aliases(prober, result)?;
+        }
+        Ok(results)
+    }
+
+    fn reveal_tunnels<P: Prober>(&self, prober: &mut P, result: &mut TraceResult) -> Result<()> {
+        if !self.reveal_tunnels {
+            return Ok(());
+        }
+        let probes = reveal_tunnels(
+            prober,
+            &mut result.tunnels,
+            self.src_port,
+            self.dst_port,
+            self.protocol,
+        )?;
+        debug!(
+            "{} probes sent to reveal the tunnels towards {}",
+            probes,
+            result.dst_addr()
+        );
+        result.statistics.probes += probes;
+        Ok(())
+    }
+
+    fn resolve_aliases<P: Prober>(&self, prober: &mut P, result: &mut TraceResult) -> Result<()> {
+        if !self.resolve_aliases {
+            return Ok(());
+        }
+        let mut resolver =
+            AliasResolver::new(&result.replies(), result.topology(), result.dst_addr());
+        let probes = resolve_aliases(
+            prober,
+            &mut resolver,
+            self.alias_rounds,
+            self.src_port,
+            self.dst_port,
+        )?;
+        result.aliases = resolver.alias_sets();
+        debug!(
+            "{} probes sent to resolve the aliases towards {}, {} alias sets found",
+            probes,
+            result.dst_addr(),
+            result.aliases.len()
+        );
+        result.statistics.probes += probes;
+        Ok(())
+    }
+
+    /// The rounds of the trace over `prober`, to run on an async runtime, see [`AsyncTrace`].
+    /// Checkpoints, reply dumps and round plans are not written.
+    pub fn into_async<P: AsyncProber>(
+        self,
+        prober: P,
+    ) -> Result<AsyncTrace<Box<dyn Algorithm + Send>, P>> {
+        let [dst_addr] = self.targets[..] else {
+            bail!("a single destination can be traced asynchronously");
+        };
+        Ok(AsyncTrace::new(self.algorithm(dst_addr), prober)
+            .with_estimate_successors(self.estimate_successors))
+    }
+
+    fn probing_session(
+        &mut self,
+        interface: Option<String>,
+        instance_id: u16,
+    ) -> Result<ProbingSession> {
+        let interface = match interface {
+            Some(name) => get_interfaces()
+                .into_iter()
+                .find(|interface| interface.name == name)
+                .with_context(|| format!("unknown interface {}", name))?,
+            None => get_default_interface().map_err(anyhow::Error::msg)?,
+        };
+        if self.metadata.src_addrs.is_empty() {
+            self.metadata.src_addrs = interface_addrs(&interface);
+        }
+        let config = Config {
+            receiver_wait_time: self.receiver_wait_time,
+            probing_rate: self.probing_rate,
+            interface: interface.name,
+            instance_id,
+            ..Config::default()
+        };
+        let session = ProbingSession::new(config)?;
+        Ok(match &self.events {
+            Some(events) => session.with_events(events.sender()),
+            None => session,
+        })
+    }
+
+    fn metadata(&self) -> TracerouteMetadata {
+        TracerouteMetadata {
+            protocol: self.protocol,
+            ..self.metadata.clone()
+        }
+    }
+
+    fn algorithm(&self, dst_addr: IpAddr) -> Box<dyn Algorithm + Send> {
+        let (mapper_v4, mapper_v6) = (self.flow_mappers)();
+        match self.algorithm {
+            AlgorithmKind::DiamondMiner => Box::new(
+                DiamondMiner::new(
+                    dst_addr,
+                    self.min_ttl,
+                    self.max_ttl,
+                    self.src_port,
+                    self.dst_port,
+                    self.protocol,
+                    self.confidence,
+                    self.max_round,
+                )
+                .with_flow_mappers(mapper_v4, mapper_v6)
+                .with_reprobes(self.reprobes)
+                .with_stopping_rule(self.stopping_rule),
+            ),
+            AlgorithmKind::MdaLite => Box::new(
+                MdaLite::new(
+                    dst_addr,
+                    self.min_ttl,
+                    self.max_ttl,
+                    self.src_port,
+                    self.dst_port,
+                    self.protocol,
+                    self.confidence,
+                    self.max_round,
+                )
+                .with_flow_mappers(mapper_v4, mapper_v6)
+                .with_reprobes(self.reprobes)
+                .with_stopping_rule(self.stopping_rule),
+            ),
+            AlgorithmKind::ParisTraceroute => Box::new(
+                ParisTraceroute::new(
+                    dst_addr,
+                    self.min_ttl,
+                    self.max_ttl,
+                    self.src_port,
+                    self.dst_port,
+                    self.protocol,
+                )
+                .with_probes_per_hop(self.probes_per_hop)
+                .with_max_silent_hops(self.max_silent_hops),
+            ),
+        }
+    }
+
+    // time to send `n_probes` and wait for their replies
+    fn expected_time(&self, n_probes: usize) -> f64 {
+        self.receiver_wait_time.as_secs_f64() + n_probes as f64 / self.probing_rate as f64
+    }
+
+    // runs the probing rounds of `alg` until it has no more probes to send,
+    // and returns the number of rounds and of probes sent
+    fn run<P: Prober>(&mut self, prober: &mut P, alg: &mut dyn Algorithm) -> Result<(u32, usize)> {
+        let mut round = 0;
+        let mut n_probes = 0;
+
+        let mut probes = match &self.resume {
+            Some(path) => {
+                let checkpoint = Checkpoint::load(path)?;
+                info!(
+                    "resuming from {:?} at round {}",
+                    path, checkpoint.state.current_round
+                );
+                // the first call to next_round sends probes without replies to add
+                round = checkpoint.state.current_round.saturating_sub(1);
+                let probes = checkpoint.probes();
+                alg.restore(checkpoint.state)?;
+                probes
+            }
+            None => alg.next_round(vec![], self.estimate_successors),
+        };
+        let first_round = round;
+        self.save_checkpoint(alg, &probes)?;
+        self.write_round_plan(alg)?;
+        info!(
+            "round={} links_found={} total_ip={} probes={} expected_time={:.1}s",
+            round,
+            0,
+            0,
+            probes.len(),
+            probes.len() as f64 / (self.probing_rate as f64),
+        );
+
+        while !probes.is_empty() {
+            round += 1;
+            n_probes += probes.len();
+            self.round_started(round, &probes);
+            let replies = prober.probe(probes)?;
+            let late_replies = shift_rounds(prober.late_replies(), first_round);
+            debug!(
+                "received {} replies including {} time exceeded replies, and {} late replies",
+                replies.len(),
+                replies.iter().filter(|r| r.is_time_exceeded()).count(),
+                late_replies.len()
+            );
+            self.send_event(TraceEvent::RepliesReceived {
+                round,
+                replies: replies.len(),
+                late_replies: late_replies.len(),
+            });
+            if let Some(dump) = &mut self.reply_dump {
+                for reply in &replies {
+                    dump.write(round, reply)?;
+                }
+                for (late_round, reply) in &late_replies {
+                    dump.write(*late_round, reply)?;
+                }
+                dump.flush()?;
+            }
+            alg.add_late_replies(late_replies);
+
+            let prep_start = Utc::now();
+
+            probes = alg.next_round(replies, self.estimate_successors);
+
+            let prep_end = Utc::now();
+
+            self.save_checkpoint(alg, &probes)?;
+            self.write_round_plan(alg)?;
+            self.round_finished(round, &[(&*alg, probes.is_empty())], &probes);
+
+            debug!(
+                "Preparation time: {:.3}s",
+                (prep_end - prep_start).num_milliseconds() as f64 * 1e-3
+            );
+
+            let n_probes_per_ttl = probes.iter().group_by(|probe| probe.ttl);
+
+            for (ttl, probes) in n_probes_per_ttl.into_iter() {
+                debug!("TTL {}: {} probes", ttl, probes.count());
+            }
+
+            info!(
+                "round={} links_found={} total_ip={} probes={} expected_time={:.1}s",
+                round,
+                alg.topology().n_links(),
+                alg.topology().n_nodes(),
+                probes.len(),
+                self.expected_time(probes.len()),
+            );
+        }
+
+        // replies to the last round can still arrive after it is over
+        let late_replies = shift_rounds(prober.close(), first_round);
+        debug!(
+            "received {} late replies after the last round",
+            late_replies.len()
+        );
+        if let Some(dump) = &mut self.reply_dump {
+            for (late_round, reply) in &late_replies {
+                dump.write(*late_round, reply)?;
+            }
+            dump.flush()?;
+        }
+        alg.add_late_replies(late_replies);
+
+        Ok((round, n_probes))
+    }
+
+    // runs the probing rounds of every instance of `campaign`, and returns the number of rounds
+    fn run_campaign<P: Prober>(
+        &mut self,
+        prober: &mut P,
+        campaign: &mut Campaign<Box<dyn Algorithm + Send>>,
+    ) -> Result<u32> {
+        let mut round = 0;
+
+        let mut probes = campaign.next_round(vec![], self.estimate_successors);
+        for alg in campaign.active_miners() {
+            self.write_round_plan(alg.as_ref())?;
+        }
+
+        while !probes.is_empty() {
+            info!(
+                "round={} active_targets={} probes={} expected_time={:.1}s",
+                round,
+                campaign.n_active(),
+                probes.len(),
+                self.expected_time(probes.len()),
+            );
+            round += 1;
+            self.round_started(round, &probes);
+            let replies = prober.probe(probes)?;
+            let late_replies = prober.late_replies();
+            debug!(
+                "received {} replies and {} late replies",
+                replies.len(),
+                late_replies.len()
+            );
+            self.send_event(TraceEvent::RepliesReceived {
+                round,
+                replies: replies.len(),
+                late_replies: late_replies.len(),
+            });
+            if let Some(dump) = &mut self.reply_dump {
+                for reply in &replies {
+                    dump.write(round, reply)?;
+                }
+                for (late_round, reply) in &late_replies {
+                    dump.write(*late_round, reply)?;
+                }
+                dump.flush()?;
+            }
+            campaign.add_late_replies(late_replies);
+
+            probes = campaign.next_round(replies, self.estimate_successors);
+            for alg in campaign.active_miners() {
+                self.write_round_plan(alg.as_ref())?;
+            }
+            let active: HashSet<IpAddr> =
+                campaign.active_miners().map(|alg| alg.dst_addr()).collect();
+            let algs: Vec<(&dyn Algorithm, bool)> = campaign
+                .miners()
+                .iter()
+                .map(|alg| {
+                    let done = !active.contains(&alg.dst_addr());
+                    (alg.as_ref() as &dyn Algorithm, done)
+                })
+                .collect();
+            self.round_finished(round, &algs, &probes);
+        }
+
+        let late_replies = prober.close();
+        debug!(
+            "received {} late replies after the last round",
+            late_replies.len()
+        );
+        if let Some(dump) = &mut self.reply_dump {
+            for (late_round, reply) in &late_replies {
+                dump.write(*late_round, reply)?;
+            }
+            dump.flush()?;
+        }
+        campaign.add_late_replies(late_replies);
+
+        Ok(round)
+    }
+
+    fn send_event(&self, event: TraceEvent) {
+        if let Some(events) = &self.events {
+            events.send(event);
+        }
+    }
+
+    fn round_started(&self, round: u32, probes: &[Probe]) {
+        self.send_event(TraceEvent::RoundStarted {
+            round,
+            probes: probes.len(),
+            expected_time: Duration::from_secs_f64(self.expected_time(probes.len())),
+        });
+    }
+
+    // sends the nodes found by `algs` in the round, each with whether it is done, then the end of the round
+    fn round_finished(
+        &mut self,
+        round: u32,
+        algs: &[(&dyn Algorithm, bool)],
+        next_probes: &[Probe],
+    ) {
+        let Some(events) = &mut self.events else {
+            return;
+        };
+        for &(alg, done) in algs {
+            events.update(alg, done);
+        }
+        events.send(TraceEvent::RoundFinished {
+            round,
+            links: algs.iter().map(|(alg, _)| alg.topology().n_links()).sum(),
+            nodes: algs.iter().map(|(alg, _)| alg.topology().n_nodes()).sum(),
+            next_probes: next_probes.len(),
+        });
+    }
+
+    // writes the state of `alg` and the probes of its next round to the checkpoint file, if any
+    fn save_checkpoint(&mut self, alg: &dyn Algorithm, probes: &[Probe]) -> Result<()> {
+        let Some(log) = &mut self.checkpoint else {
+            return Ok(());
+        };
+        let Some(state) = alg.checkpoint(log.saved()) else {
+            bail!("the {:?} algorithm cannot be checkpointed", self.algorithm);
+        };
+        log.append(&Checkpoint::new(state, probes))
+    }
+
+    // writes the plan of the last round of `alg` as a JSON line, if the algorithm has one
+    fn write_round_plan(&mut self, alg: &dyn Algorithm) -> Result<()> {
+        let (Some(writer), Some(plan)) = (&mut self.round_plans, alg.round_plan()) else {
+            return Ok(());
+        };
+        let mut line = serde_json::to_value(plan)?;
+        line["dst_addr"] = serde_json::json!(alg.dst_addr());
+        writeln!(writer, "{}", line)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// the prober numbers its rounds from 1 even when the trace is resumed after `first_round`
+fn shift_rounds(late_replies: Vec<(u32, Reply)>, first_round: u32) -> Vec<(u32, Reply)> {
+    late_replies
+        .into_iter()
+        .map(|(round, reply)| (first_round + round, reply))
+        .collect()
+}
+
+fn replay_prober(path: &Path) -> Result<ReplayProber> {
+    let prober = ReplayProber::from_file(path)?;
+    info!("replaying {} replies from {:?}", prober.n_remaining(), path);
+    Ok(prober)
+}
+
+/// The addresses of `interface`, without the IPv6 link-local ones that cannot be used to probe.
+fn interface_addrs(interface: &Interface) -> Vec<IpAddr> {
+    let ipv4 = interface.ipv4.iter().map(|net| IpAddr::V4(net.addr()));
+    let ipv6 = interface
+        .ipv6
+        .iter()
+        .map(|net| net.addr())
+        .filter(|addr| addr.segments()[0] & 0xffc0 != 0xfe80)
+        .map(IpAddr::V6);
+    ipv4.chain(ipv6).collect()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,128 @@
+use crate::helpers::{addr, miner, prober, reply, DEST, SRC};
+use crate::simulator::SimulatedProber;
+
+use super::*;
+
+#[test]
+fn test_trace() {
+    let result = Tracer::new(addr(DEST))
+        .with_ttl_range(1, 10)
+        .with_confidence(95.0)
+        .with_metadata(TracerouteMetadata {
+            agent_id: "vp1".to_string(),
+            ..TracerouteMetadata::default()
+        })
+        .trace_with(&mut prober())
+        .unwrap();
+
+    assert_eq!(result.dst_addr(), addr(DEST));
+    assert_eq!(result.topology().links().count(), 4);
+    assert!(result.statistics.rounds > 1);
+    assert!(result.statistics.probes >= result.statistics.replies);
+    assert_eq!(result.statistics.replies, result.replies().len());
+    assert_eq!(result.statistics.links, 4);
+    assert_eq!(result.traceroute.agent_id, "vp1");
+    assert_eq!(result.traceroute.dst_addr, addr(DEST));
+    assert_eq!(result.traceroute.src_addr, addr(SRC));
+    assert!(!result.traceroute.flows.is_empty());
+
+    // the same trace with the single path algorithm
+    let result = Tracer::new(addr(DEST))
+        .with_ttl_range(1, 10)
+        .with_algorithm(AlgorithmKind::ParisTraceroute)
+        .trace_with(&mut prober())
+        .unwrap();
+    assert_eq!(result.topology().links().count(), 2);
+
+    let campaign = Tracer::campaign(vec![addr(DEST), addr("192.170.1.2")]);
+    assert!(campaign.trace_with(&mut prober()).is_err());
+}
+
+#[test]
+fn test_trace_campaign() {
+    let targets = vec![addr(DEST), addr("192.170.1.2")];
+    let results = Tracer::campaign(targets.clone())
+        .with_ttl_range(1, 10)
+        .with_confidence(95.0)
+        .trace_campaign_with(&mut prober())
+        .unwrap();
+
+    assert_eq!(results.len(), 2);
+    for (result, target) in results.iter().zip(targets) {
+        assert_eq!(result.dst_addr(), target);
+        assert_eq!(result.traceroute.dst_addr, target);
+        assert_eq!(result.topology().links().count(), 4);
+        assert!(result.statistics.probes > 0);
+    }
+
+    assert!(Tracer::new(addr(DEST))
+        .with_checkpoint(PathBuf::from("checkpoint.json"))
+        .trace_campaign_with(&mut prober())
+        .is_err());
+}
+
+// answers like the simulated network, and gets a reply from `late` only once the last round is over
+struct ClosingProber {
+    prober: SimulatedProber,
+    rounds: u32,
+    late: Reply,
+}
+
+impl Prober for ClosingProber {
+    fn probe(&mut self, probes: Vec<Probe>) -> Result<Vec<Reply>> {
+        self.rounds += 1;
+        Prober::probe(&mut self.prober, probes)
+    }
+
+    fn close(&mut self) -> Vec<(u32, Reply)> {
+        vec![(self.rounds, self.late.clone())]
+    }
+}
+
+#[test]
+fn test_replies_after_last_round() {
+    let mut prober = ClosingProber {
+        prober: prober(),
+        rounds: 0,
+        late: reply(4, "192.168.0.9", DEST),
+    };
+    let result = Tracer::new(addr(DEST))
+        .with_ttl_range(1, 10)
+        .trace_with(&mut prober)
+        .unwrap();
+    assert!(result
+        .topology()
+        .nodes()
+        .any(|(node, _)| node == addr("192.168.0.9")));
+}
+
+#[test]
+fn test_late_replies_after_resume() {
+    // a checkpoint taken after the first round
+    let mut miner = miner(95.0);
+    let probes = miner.next_round(vec![], false);
+    let replies = Prober::probe(&mut prober(), probes).unwrap();
+    let probes = miner.next_round(replies, false);
+    let path = std::env::temp_dir().join(format!("voyage-resume-{}.json", std::process::id()));
+    Checkpoint::new(miner.checkpoint(&HashMap::new()), &probes)
+        .save(&path)
+        .unwrap();
+
+    let mut prober = ClosingProber {
+        prober: prober(),
+        rounds: 0,
+        late: reply(4, "192.168.0.9", DEST),
+    };
+    let result = Tracer::new(addr(DEST))
+        .with_ttl_range(1, 10)
+        .with_resume(path.clone())
+        .trace_with(&mut prober)
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // the late reply is stored with the replies to the last round, as without the resume
+    let state = result.algorithm.checkpoint(&HashMap::new()).unwrap();
+    assert!(state.replies_by_round[&state.current_round]
+        .iter()
+        .any(|reply| reply.reply_src_addr == addr("192.168.0.9")));
+}
@@ -0,0 +1,228 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::Frame;
+
+use crate::events::{DestinationProgress, TraceEvent, TraceProgress};
+
+// how often the keyboard is polled while waiting for events
+const TICK: Duration = Duration::from_millis(100);
+
+/// A live view of a trace, rebuilt from its events: the interfaces found at each TTL and the
+/// width of the diamonds they belong to, redrawn as the rounds complete. Unresolved interfaces
+/// are shown in yellow. In a campaign, the destinations are shown one at a time.
+#[derive(Debug, Default)]
+pub struct Dashboard {
+    progress: TraceProgress,
+    selected: usize,
+    done: bool,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&mut self, event: &TraceEvent) {
+        self.progress.apply(event);
+    }
+
+    pub fn progress(&self) -> &TraceProgress {
+        &self.progress
+    }
+
+    /// Shows the next (or previous) destination of a campaign.
+    pub fn select(&mut self, forward: bool) {
+        let n = self.progress.destinations.len().max(1);
+        self.selected = if forward {
+            (self.selected + 1) % n
+        } else {
+            (self.selected + n - 1) % n
+        };
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let [header, hops, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let destination = self
+            .progress
+            .destinations
+            .iter()
+            .nth(self.selected)
+            .map(|(&dst_addr, destination)| (dst_addr, destination));
+
+        let progress = &self.progress;
+        let status = if self.done {
+            "done".green()
+        } else if progress.probing {
+            "probing".yellow()
+        } else {
+            "processing".into()
+        };
+        let mut title = vec![Span::from(" voyage ").bold()];
+        if let Some((dst_addr, _)) = destination {
+            title.push(Span::from(format!("→ {} ", dst_addr)));
+            if progress.destinations.len() > 1 {
+                title.push(Span::from(format!(
+                    "({}/{}) ",
+                    self.selected + 1,
+                    progress.destinations.len()
+                )));
+            }
+        }
+        let summary = Line::from(vec![
+            Span::from(format!(
+                "round {}  probes {}  replies {}  nodes {}  links {}  next round {} probes  ",
+                progress.round,
+                progress.probes,
+                progress.replies,
+                progress.nodes,
+                progress.links,
+                progress.next_probes
+            )),
+            status,
+        ]);
+        frame.render_widget(
+            Paragraph::new(summary).block(Block::bordered().title(Line::from(title))),
+            header,
+        );
+
+        let rows = destination
+            .map(|(dst_addr, destination)| hop_rows(dst_addr, destination))
+            .unwrap_or_default();
+        let width = destination.map_or(0, |(_, destination)| destination.max_width());
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(4),
+                Constraint::Length(width.clamp(5, 32) as u16 + 2),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(Row::new(["TTL", "Width", "Interfaces"]).add_modifier(Modifier::BOLD))
+        .block(Block::bordered());
+        frame.render_widget(table, hops);
+
+        let keys = if self.done {
+            " trace done, press q to exit"
+        } else {
+            " q: quit"
+        };
+        let keys = if progress.destinations.len() > 1 {
+            format!("{}  ←/→: destination", keys)
+        } else {
+            keys.to_string()
+        };
+        frame.render_widget(Paragraph::new(keys).dim(), footer);
+    }
+}
+
+// one row per TTL, up to the destination
+fn hop_rows(dst_addr: IpAddr, destination: &DestinationProgress) -> Vec<Row<'static>> {
+    let mut rows: Vec<Row> = destination
+        .hops
+        .iter()
+        .take_while(|(&ttl, _)| destination.reached_at.is_none_or(|reached| ttl < reached))
+        .map(|(ttl, nodes)| {
+            let interfaces: Vec<Span> = nodes
+                .iter()
+                .map(|node| {
+                    let style = if node.resolved {
+                        Style::default()
+                    } else {
+                        Style::default().fg(Color::Yellow)
+                    };
+                    Span::styled(format!("{} ", node.addr), style)
+                })
+                .collect();
+            Row::new(vec![
+                Line::from(ttl.to_string()),
+                Line::from("█".repeat(nodes.len()).cyan()),
+                Line::from(interfaces),
+            ])
+        })
+        .collect();
+    if let Some(ttl) = destination.reached_at {
+        rows.push(Row::new(vec![
+            Line::from(ttl.to_string()),
+            Line::from("█".cyan()),
+            Line::from(format!("{} (destination)", dst_addr).green()),
+        ]));
+    }
+    rows
+}
+
+/// The error returned by [`run`] when the dashboard is quit before the end of the trace.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Draws the dashboard of the trace that sends its events to `events`, until the trace is over
+/// and a key is pressed. Pressing q or Ctrl-C before the end of the trace restores the terminal
+/// and returns [`Interrupted`].
+pub fn run(events: Receiver<TraceEvent>) -> Result<TraceProgress> {
+    let mut terminal = ratatui::init();
+    let mut dashboard = Dashboard::new();
+    let result = (|| -> Result<()> {
+        loop {
+            terminal.draw(|frame| dashboard.render(frame))?;
+            if !dashboard.done {
+                match events.recv_timeout(TICK) {
+                    Ok(event) => {
+                        dashboard.apply(&event);
+                        for event in events.try_iter() {
+                            dashboard.apply(&event);
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => dashboard.done = true,
+                }
+            }
+            let timeout = if dashboard.done { TICK } else { Duration::ZERO };
+            if !event::poll(timeout)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc if dashboard.done => return Ok(()),
+                KeyCode::Char('q') => return Err(Interrupted.into()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Err(Interrupted.into())
+                }
+                KeyCode::Right | KeyCode::Tab => dashboard.select(true),
+                KeyCode::Left | KeyCode::BackTab => dashboard.select(false),
+                _ => {}
+            }
+        }
+    })();
+    ratatui::restore();
+    result.map(|_| dashboard.progress)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,100 @@
+use ratatui::backend::TestBackend;
+use ratatui::Terminal;
+
+use crate::helpers::addr;
+
+use super::*;
+
+fn node(ttl: u8, ip: &str, resolved: bool) -> TraceEvent {
+    let dst_addr = addr("192.170.0.2");
+    if resolved {
+        TraceEvent::NodeResolved {
+            dst_addr,
+            ttl,
+            addr: addr(ip),
+        }
+    } else {
+        TraceEvent::NodeFound {
+            dst_addr,
+            ttl,
+            addr: addr(ip),
+        }
+    }
+}
+
+#[test]
+fn test_render() {
+    let mut dashboard = Dashboard::new();
+    let events = [
+        TraceEvent::RoundStarted {
+            round: 1,
+            probes: 6,
+            expected_time: Duration::from_secs(1),
+        },
+        TraceEvent::RepliesReceived {
+            round: 1,
+            replies: 5,
+            late_replies: 0,
+        },
+        node(1, "192.168.0.2", false),
+        node(2, "192.168.0.3", false),
+        node(2, "192.168.0.4", false),
+        node(1, "192.168.0.2", true),
+        TraceEvent::DestinationReached {
+            dst_addr: addr("192.170.0.2"),
+            ttl: 3,
+        },
+        TraceEvent::RoundFinished {
+            round: 1,
+            links: 2,
+            nodes: 3,
+            next_probes: 12,
+        },
+    ];
+    for event in &events {
+        dashboard.apply(event);
+    }
+
+    let mut terminal = Terminal::new(TestBackend::new(100, 10)).unwrap();
+    terminal.draw(|frame| dashboard.render(frame)).unwrap();
+    let lines: Vec<String> = terminal
+        .backend()
+        .buffer()
+        .content()
+        .chunks(100)
+        .map(|line| line.iter().map(|cell| cell.symbol()).collect())
+        .collect();
+
+    assert!(lines[0].contains("192.170.0.2"));
+    assert!(lines[1].contains("round 1  probes 6  replies 5  nodes 3  links 2"));
+    assert!(lines[1].contains("processing"));
+    let hop = |ttl: &str| {
+        lines
+            .iter()
+            .find(|line| line.trim_start_matches('│').trim_start().starts_with(ttl))
+            .unwrap()
+    };
+    assert!(hop("2 ").contains("██ "));
+    assert!(hop("2 ").contains("192.168.0.3 192.168.0.4"));
+    assert!(hop("3 ").contains("192.170.0.2 (destination)"));
+
+    // the unresolved interfaces are highlighted
+    let cell = |line: usize, text: &str| {
+        let x = lines[line][..lines[line].find(text).unwrap()]
+            .chars()
+            .count();
+        terminal.backend().buffer()[(x as u16, line as u16)].fg
+    };
+    let (ttl_1, ttl_2) = (
+        lines
+            .iter()
+            .position(|line| line.contains("192.168.0.2"))
+            .unwrap(),
+        lines
+            .iter()
+            .position(|line| line.contains("192.168.0.3"))
+            .unwrap(),
+    );
+    assert_eq!(cell(ttl_1, "192.168.0.2"), Color::Reset);
+    assert_eq!(cell(ttl_2, "192.168.0.3"), Color::Yellow);
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::net::IpAddr;
 
 use caracat::models::{Reply, L4};
@@ -45,6 +46,14 @@ impl From<&L4Wrapper> for L4 {
 pub type TTL = u8;
 pub(crate) type Port = u16;
 
+/// The TTL a reply was sent with, assumed to be the smallest usual value above its TTL on arrival.
+pub(crate) fn initial_ttl(reply_ttl: u8) -> u8 {
+    [32, 64, 128, 255]
+        .into_iter()
+        .find(|&initial| initial >= reply_ttl)
+        .unwrap_or(255)
+}
+
 #[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
 pub struct Flow {
     pub(crate) protocol: L4Wrapper,
@@ -71,6 +80,28 @@ pub struct Link {
     pub far_ip: Option<IpAddr>,
 }
 
+/// How a router spreads traffic over its successors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LoadBalancing {
+    /// The next hop is a hash of the flow 5-tuple, as done by ECMP.
+    #[default]
+    PerFlow,
+    /// The next hop is drawn for every packet, regardless of its flow.
+    PerPacket,
+    /// The next hop only depends on the destination address.
+    PerDestination,
+}
+
+impl fmt::Display for LoadBalancing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadBalancing::PerFlow => write!(f, "per-flow"),
+            LoadBalancing::PerPacket => write!(f, "per-packet"),
+            LoadBalancing::PerDestination => write!(f, "per-destination"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct ReplyPair<'a> {
     pub(crate) ttl: TTL,